@@ -0,0 +1,182 @@
+use crate::client::Client;
+use crate::command::Handler;
+use crate::connection::Connection;
+use crate::error::{Error, Result};
+use crate::resp::RespVal;
+use crate::Database;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+use tokio::sync::oneshot;
+use tokio::time::Instant;
+
+/// A key of a database, as blocked clients may wait on keys of any database.
+pub type BlockingKey = (usize, Vec<u8>);
+
+/// A client waiting for one of several keys to receive data, like a `BLPOP` on empty lists.
+#[derive(Debug)]
+pub struct Waiter {
+    /// A copy of the blocked client, to run its command as it would have run it.
+    pub client: Client,
+    pub handler: Handler,
+    pub args: Vec<Vec<u8>>,
+    keys: Vec<BlockingKey>,
+    sender: oneshot::Sender<Result<RespVal>>,
+}
+
+/// The clients blocked on keys, modeled on Redis' `blocking_keys` and `ready_keys`.
+///
+/// Writes that may unblock a client mark the key as ready. After every command the server
+/// serves the ready keys by running the commands of the clients waiting on them again, in the
+/// order the clients blocked, so that the first client to block gets the first element.
+#[derive(Debug, Default)]
+pub struct BlockedClients {
+    waiters: HashMap<u64, Waiter>,
+    /// Ids of the waiters per key, in the order they blocked.
+    waiting_on: HashMap<BlockingKey, VecDeque<u64>>,
+    ready_keys: VecDeque<BlockingKey>,
+    ready_set: HashSet<BlockingKey>,
+    next_id: u64,
+}
+
+impl BlockedClients {
+    /// Registers a waiter for `keys` and returns its id and the receiving end of its reply.
+    pub fn block(
+        &mut self,
+        db: usize,
+        keys: &[Vec<u8>],
+        client: Client,
+        handler: Handler,
+        args: Vec<Vec<u8>>,
+    ) -> (u64, oneshot::Receiver<Result<RespVal>>) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut blocking_keys: Vec<BlockingKey> = Vec::with_capacity(keys.len());
+        for key in keys {
+            let key = (db, key.clone());
+            if !blocking_keys.contains(&key) {
+                self.waiting_on.entry(key.clone()).or_default().push_back(id);
+                blocking_keys.push(key);
+            }
+        }
+        let (sender, receiver) = oneshot::channel();
+        let waiter = Waiter {
+            client,
+            handler,
+            args,
+            keys: blocking_keys,
+            sender,
+        };
+        self.waiters.insert(id, waiter);
+        (id, receiver)
+    }
+
+    /// Removes a waiter from all keys it waits on.
+    pub fn unblock(&mut self, id: u64) -> Option<Waiter> {
+        let waiter = self.waiters.remove(&id)?;
+        for key in &waiter.keys {
+            if let Some(ids) = self.waiting_on.get_mut(key) {
+                ids.retain(|&waiting| waiting != id);
+                if ids.is_empty() {
+                    self.waiting_on.remove(key);
+                }
+            }
+        }
+        Some(waiter)
+    }
+
+    /// Unblocks a waiter with `reply`.
+    pub fn reply(&mut self, id: u64, reply: Result<RespVal>) {
+        if let Some(waiter) = self.unblock(id) {
+            // The receiver is gone if the client disconnected, then there is no one to tell.
+            let _ = waiter.sender.send(reply);
+        }
+    }
+
+    /// Notes that `key` may now have data for the clients waiting on it.
+    pub fn signal_key_as_ready(&mut self, db: usize, key: &[u8]) {
+        let key = (db, key.to_vec());
+        if self.waiting_on.contains_key(&key) && !self.ready_set.contains(&key) {
+            self.ready_set.insert(key.clone());
+            self.ready_keys.push_back(key);
+        }
+    }
+
+    pub fn take_ready_key(&mut self) -> Option<BlockingKey> {
+        let key = self.ready_keys.pop_front()?;
+        self.ready_set.remove(&key);
+        Some(key)
+    }
+
+    /// The ids of the waiters on `key`, in the order they blocked.
+    pub fn waiters_on(&self, key: &BlockingKey) -> Vec<u64> {
+        self.waiting_on.get(key).map_or_else(Vec::new, |ids| ids.iter().copied().collect())
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Waiter> {
+        self.waiters.get(&id)
+    }
+
+    /// Number of blocked clients, for `INFO`.
+    pub fn count(&self) -> usize {
+        self.waiters.len()
+    }
+}
+
+/// A command that blocked instead of replying.
+pub struct Blocked {
+    pub id: u64,
+    pub receiver: oneshot::Receiver<Result<RespVal>>,
+    /// `None` blocks until a reply arrives.
+    pub timeout: Option<Duration>,
+    pub timeout_reply: RespVal,
+}
+
+impl Blocked {
+    /// Waits for the reply of the blocked command, or its timeout. Meanwhile the connection is
+    /// read, buffering further requests, to notice when the client goes away; `None` is
+    /// returned then.
+    pub async fn wait(mut self, map: &Database, connection: &mut Connection) -> Result<Option<Result<RespVal>>> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let timed_out = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(timed_out);
+        loop {
+            tokio::select! {
+                reply = &mut self.receiver => {
+                    return Ok(Some(reply.expect("a waiter is only dropped after it got a reply")));
+                }
+                _ = &mut timed_out => break,
+                open = connection.fill_buffer() => match open {
+                    Ok(true) => {}
+                    // The connection is done for, so a failure to cancel has no one to report to.
+                    Ok(false) => {
+                        let _ = self.cancel(map);
+                        return Ok(None);
+                    }
+                    Err(err) => {
+                        let _ = self.cancel(map);
+                        return Err(err);
+                    }
+                },
+            }
+        }
+        if self.cancel(map)? {
+            return Ok(Some(Ok(self.timeout_reply)));
+        }
+        // The command was served after the timeout fired but before it could cancel it.
+        let reply = self.receiver.try_recv().expect("the waiter was served");
+        Ok(Some(reply))
+    }
+
+    /// Unregisters the waiter; returns false if it was served in the meantime.
+    fn cancel(&self, map: &Database) -> Result<bool> {
+        let mut db = map
+            .lock()
+            .map_err(|_| Error::StateError("Mutex lock failed".to_string()))?;
+        Ok(db.blocked.unblock(self.id).is_some())
+    }
+}
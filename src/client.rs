@@ -0,0 +1,22 @@
+use crate::resp::ProtocolVersion;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_CLIENT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Per connection state that commands can read and change.
+#[derive(Debug, Clone)]
+pub struct Client {
+    pub id: u64,
+    pub name: Option<Vec<u8>>,
+    pub protocol: ProtocolVersion,
+}
+
+impl Client {
+    pub fn new() -> Client {
+        Client {
+            id: NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed),
+            name: None,
+            protocol: ProtocolVersion::default(),
+        }
+    }
+}
@@ -1,168 +1,646 @@
+use crate::blocking::Blocked;
+use crate::client::Client;
 use crate::error::{Error, Result};
 use crate::resp::RespVal;
+use crate::db::Db;
+use crate::hash::Hash;
+use crate::random;
+use crate::set::Set;
+use crate::stream::Stream;
+use crate::zset::SortedSet;
+use crate::{Config, Database, Value};
+use std::collections::{HashMap, VecDeque};
 use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
 
-#[derive(Debug)]
-pub enum RedisCommand {
-    Ping,
-    Echo(Vec<u8>),
-    Set(SetData),
-    Get(Vec<u8>),
-    ConfigGet(Vec<u8>),
-    Keys(Vec<u8>)
-}
-
-impl RedisCommand {
-    pub fn parse_command(raw: &[u8]) -> Result<RedisCommand> {
-        let (parts, _): (RespVal, _) = RespVal::parse_array(raw)?;
-        match parts {
-            RespVal::Array(vals) => {
-                match vals.get(0) {
-                    Some(RespVal::BulkString(command_bytes)) => match command_bytes.as_slice() {
-                        b"ping" => Ok(RedisCommand::Ping),
-                        b"echo" => {
-                            if let Some(RespVal::BulkString(arg_bytes)) = vals.get(1) {
-                                Ok(RedisCommand::Echo(arg_bytes.clone()))
-                            } else {
-                                Err(Error::ValidationError("ECHO requires an argument. This argument must be a BulkString.".to_string()))
-                            }
-                        }
-                        b"set" => {
-                            let args = &vals[1..];
-                            RedisCommand::parse_set_args(args)
-                        }
-                        b"get" => {
-                            let args = &vals[1..];
-                            RedisCommand::parse_get_args(args)
-                        }
-                        b"config" => {
-                            let args = &vals[1..];
-                            RedisCommand::parse_config_args(args)
-                        }
-                        b"keys" => {
-                            let args = &vals[1..];
-                            RedisCommand::parse_keys_args(args)
-                        }
-                        _ => Err(Error::ValidationError(format!(
-                            "Unknown Command {}",
-                            String::from_utf8_lossy(command_bytes)
-                        ))),
-                    },
-                    _ => Err(Error::ValidationError(
-                        "No command provided as Bulk String".to_string(),
-                    )),
-                }
-            }
-            _ => Err(Error::ValidationError(format!(
-                "Can parse Redis Command only from RESP Array, but got {:?}",
-                parts
-            ))),
+mod bitmap;
+mod connection;
+mod expire;
+mod generic;
+mod hash;
+mod list;
+mod server;
+mod set;
+mod stream;
+mod string;
+mod zset;
+
+/// Everything a command handler may read or change while it runs.
+///
+/// Handlers run while the keyspace lock is held, so they must not block.
+pub struct Context<'a> {
+    pub db: &'a mut Db,
+    pub config: &'a Config,
+    pub client: &'a mut Client,
+    /// Set by commands that have to wait for data, see [`Context::block_on`].
+    block: Option<BlockRequest>,
+}
+
+/// The keys a command waits on and for how long, `None` meaning forever.
+struct BlockRequest {
+    keys: Vec<Vec<u8>>,
+    timeout: Option<Duration>,
+    /// The arguments to run the command with once the keys have data, if not the original ones.
+    args: Option<Vec<Vec<u8>>>,
+}
+
+/// The outcome of a request: either a reply right away, or a command that waits for data.
+pub enum Response {
+    Reply(RespVal),
+    Blocked(Blocked),
+}
+
+impl<'a> Context<'a> {
+    pub fn new(db: &'a mut Db, config: &'a Config, client: &'a mut Client) -> Context<'a> {
+        Context {
+            db,
+            config,
+            client,
+            block: None,
+        }
+    }
+
+    /// Makes the client wait until one of `keys` receives data and then runs the command again,
+    /// instead of sending the reply of the current run. That reply is sent if the client times
+    /// out, so it should be what the command replies when there is no data.
+    pub fn block_on(&mut self, keys: &[Vec<u8>], timeout: Option<Duration>) {
+        self.block = Some(BlockRequest {
+            keys: keys.to_vec(),
+            timeout,
+            args: None,
+        });
+    }
+
+    /// Like [`Context::block_on`], but runs the command with `args` once the keys have data:
+    /// for arguments that are relative to the data at the time the client blocked, like the
+    /// `$` ID of `XREAD`.
+    pub fn block_on_with_args(&mut self, keys: &[Vec<u8>], timeout: Option<Duration>, args: Vec<Vec<u8>>) {
+        self.block = Some(BlockRequest {
+            keys: keys.to_vec(),
+            timeout,
+            args: Some(args),
+        });
+    }
+
+    /// The value stored at `key`, unless there is none or it has expired. Expired values are
+    /// deleted on the way.
+    pub fn get_value(&mut self, key: &[u8]) -> Option<&Value> {
+        self.db.expire_if_needed(key, SystemTime::now());
+        self.db.get(key)
+    }
+
+    pub fn get_value_mut(&mut self, key: &[u8]) -> Option<&mut Value> {
+        self.db.expire_if_needed(key, SystemTime::now());
+        self.db.get_mut(key)
+    }
+
+    /// The string stored at `key`; a `WrongType` error if the key holds another type.
+    pub fn get_string(&mut self, key: &[u8]) -> Result<Option<&Vec<u8>>> {
+        self.get_value(key).map(Value::as_string).transpose()
+    }
+
+    pub fn get_string_mut(&mut self, key: &[u8]) -> Result<Option<&mut Vec<u8>>> {
+        self.get_value_mut(key).map(Value::as_string_mut).transpose()
+    }
+
+    pub fn get_list(&mut self, key: &[u8]) -> Result<Option<&VecDeque<Vec<u8>>>> {
+        self.get_value(key).map(Value::as_list).transpose()
+    }
+
+    pub fn get_list_mut(&mut self, key: &[u8]) -> Result<Option<&mut VecDeque<Vec<u8>>>> {
+        self.get_value_mut(key).map(Value::as_list_mut).transpose()
+    }
+
+    pub fn get_hash(&mut self, key: &[u8]) -> Result<Option<&Hash>> {
+        self.get_value(key).map(Value::as_hash).transpose()
+    }
+
+    pub fn get_hash_mut(&mut self, key: &[u8]) -> Result<Option<&mut Hash>> {
+        self.get_value_mut(key).map(Value::as_hash_mut).transpose()
+    }
+
+    pub fn get_set(&mut self, key: &[u8]) -> Result<Option<&Set>> {
+        self.get_value(key).map(Value::as_set).transpose()
+    }
+
+    pub fn get_set_mut(&mut self, key: &[u8]) -> Result<Option<&mut Set>> {
+        self.get_value_mut(key).map(Value::as_set_mut).transpose()
+    }
+
+    pub fn get_sorted_set(&mut self, key: &[u8]) -> Result<Option<&SortedSet>> {
+        self.get_value(key).map(Value::as_sorted_set).transpose()
+    }
+
+    pub fn get_sorted_set_mut(&mut self, key: &[u8]) -> Result<Option<&mut SortedSet>> {
+        self.get_value_mut(key).map(Value::as_sorted_set_mut).transpose()
+    }
+
+    pub fn get_stream(&mut self, key: &[u8]) -> Result<Option<&Stream>> {
+        self.get_value(key).map(Value::as_stream).transpose()
+    }
+
+    pub fn get_stream_mut(&mut self, key: &[u8]) -> Result<Option<&mut Stream>> {
+        self.get_value_mut(key).map(Value::as_stream_mut).transpose()
+    }
+}
+
+/// Runs a command. `args` is the whole request, so `args[0]` is the command name.
+pub type Handler = fn(&mut Context, &[Vec<u8>]) -> Result<RespVal>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandFlag {
+    Write,
+    ReadOnly,
+    DenyOom,
+    Admin,
+    Blocking,
+    Fast,
+    NoScript,
+    Loading,
+    Stale,
+    /// The key positions can't be described by first key, last key and step.
+    MovableKeys,
+}
+
+impl CommandFlag {
+    fn name(self) -> &'static str {
+        match self {
+            CommandFlag::Write => "write",
+            CommandFlag::ReadOnly => "readonly",
+            CommandFlag::DenyOom => "denyoom",
+            CommandFlag::Admin => "admin",
+            CommandFlag::Blocking => "blocking",
+            CommandFlag::Fast => "fast",
+            CommandFlag::NoScript => "noscript",
+            CommandFlag::Loading => "loading",
+            CommandFlag::Stale => "stale",
+            CommandFlag::MovableKeys => "movablekeys",
         }
     }
+}
 
-    fn parse_set_args(args: &[RespVal]) -> Result<RedisCommand> {
-        if args.len() < 2 {
-            return Err(Error::ValidationError(
-                "SET command requires at least two arguments".to_string(),
-            ));
+/// An entry of the command table.
+///
+/// Arity and key positions follow the conventions of Redis' `COMMAND INFO`: the arity counts
+/// the command name and is negated for variadic commands, key positions are indices into the
+/// request, and a negative last key counts from the end of the request.
+#[derive(Debug, Clone, Copy)]
+pub struct CommandSpec {
+    /// Lowercase name; subcommands are named `container|subcommand`.
+    pub name: &'static str,
+    pub arity: i64,
+    pub flags: &'static [CommandFlag],
+    pub first_key: i64,
+    pub last_key: i64,
+    pub key_step: i64,
+    pub group: &'static str,
+    pub since: &'static str,
+    pub summary: &'static str,
+    /// `None` for containers such as `CONFIG`, which only dispatch to their subcommands.
+    pub handler: Option<Handler>,
+    pub subcommands: &'static [CommandSpec],
+}
+
+impl CommandSpec {
+    pub const fn new(name: &'static str, arity: i64, handler: Handler) -> CommandSpec {
+        CommandSpec {
+            name,
+            arity,
+            flags: &[],
+            first_key: 0,
+            last_key: 0,
+            key_step: 0,
+            group: "",
+            since: "",
+            summary: "",
+            handler: Some(handler),
+            subcommands: &[],
         }
-        match (&args[0], &args[1]) {
-            (RespVal::BulkString(key), RespVal::BulkString(value)) => {
-                let option = SetOption::parse_from(&args[2..]);
-                let options = match option {
-                    Ok(opt) => vec![opt],
-                    _ => Vec::new(),
-                };
-                let set_data = SetData {
-                    key: key.clone(),
-                    value: value.clone(),
-                    options,
-                };
-                Ok(RedisCommand::Set(set_data))
-            }
-            _ => Err(Error::ValidationError(
-                "The first two arguments of SET must be Bulk Strings".to_string(),
-            )),
+    }
+
+    pub const fn container(name: &'static str, subcommands: &'static [CommandSpec]) -> CommandSpec {
+        CommandSpec {
+            name,
+            arity: -2,
+            flags: &[],
+            first_key: 0,
+            last_key: 0,
+            key_step: 0,
+            group: "",
+            since: "",
+            summary: "",
+            handler: None,
+            subcommands,
         }
     }
 
-    fn parse_get_args(args: &[RespVal]) -> Result<RedisCommand> {
-        match args.get(0) {
-            Some(RespVal::BulkString(key)) => Ok(RedisCommand::Get(key.clone())),
-            _ => Err(Error::ValidationError(
-                "GET command requires a Bulk String as first argument.".to_string(),
-            )),
+    pub const fn flags(self, flags: &'static [CommandFlag]) -> CommandSpec {
+        CommandSpec { flags, ..self }
+    }
+
+    pub const fn keys(self, first_key: i64, last_key: i64, key_step: i64) -> CommandSpec {
+        CommandSpec {
+            first_key,
+            last_key,
+            key_step,
+            ..self
         }
     }
 
-    fn parse_config_args(args: &[RespVal]) -> Result<RedisCommand> {
-        if args.len() < 2 {
-            return Err(Error::ValidationError(
-                "CONFIG command requires two Bulk Strings first arguments".to_string()));
+    pub const fn docs(self, group: &'static str, since: &'static str, summary: &'static str) -> CommandSpec {
+        CommandSpec {
+            group,
+            since,
+            summary,
+            ..self
         }
-        match &args[0] {
-            RespVal::BulkString(subcommand) if subcommand.as_slice() == b"get" => {
-                match &args[1] {
-                    RespVal::BulkString(key) => Ok(RedisCommand::ConfigGet(key.clone())),
-                    _ => Err(Error::ValidationError(
-                            "CONFIG GET command requires a Bulk String as argument.".to_string(),
-                    )),
-                }
-            }
-            _ => Err(Error::ValidationError(
-                    "CONFIG command requires a Bulk String with Subcommand[get| ] as first argument.".to_string(),
-            )),
+    }
+
+    /// Adds subcommands to a command that also runs on its own, like `COMMAND`.
+    pub const fn subcommands(self, subcommands: &'static [CommandSpec]) -> CommandSpec {
+        CommandSpec {
+            subcommands,
+            ..self
+        }
+    }
+
+    pub fn has_flag(&self, flag: CommandFlag) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    fn find_subcommand(&self, name: &[u8]) -> Option<&'static CommandSpec> {
+        self.subcommands.iter().find(|subcommand| {
+            let (_, subcommand_name) = subcommand
+                .name
+                .split_once('|')
+                .unwrap_or(("", subcommand.name));
+            subcommand_name.as_bytes().eq_ignore_ascii_case(name)
+        })
+    }
+
+    fn check_arity(&self, args: &[Vec<u8>]) -> Result<()> {
+        let matches = if self.arity >= 0 {
+            args.len() as i64 == self.arity
+        } else {
+            args.len() as i64 >= -self.arity
+        };
+        if matches {
+            Ok(())
+        } else {
+            Err(wrong_arity(self.name))
         }
     }
-    fn parse_keys_args(args: &[RespVal]) -> Result<RedisCommand> {
-        if args.len() < 1 {
-            return Err(Error::ValidationError(
-                "KEYS command requires a Bulk String as first argument".to_string()));
+}
+
+const COMMAND_GROUPS: &[&[CommandSpec]] = &[
+    bitmap::COMMANDS,
+    connection::COMMANDS,
+    expire::COMMANDS,
+    generic::COMMANDS,
+    hash::COMMANDS,
+    list::COMMANDS,
+    server::COMMANDS,
+    set::COMMANDS,
+    stream::COMMANDS,
+    string::COMMANDS,
+    zset::COMMANDS,
+];
+
+fn command_table() -> &'static HashMap<&'static str, &'static CommandSpec> {
+    static TABLE: OnceLock<HashMap<&'static str, &'static CommandSpec>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        COMMAND_GROUPS
+            .iter()
+            .flat_map(|group| group.iter())
+            .map(|spec| (spec.name, spec))
+            .collect()
+    })
+}
+
+/// Looks up a top level command by its case-insensitive name.
+pub fn lookup(name: &[u8]) -> Option<&'static CommandSpec> {
+    let name = std::str::from_utf8(name).ok()?.to_ascii_lowercase();
+    command_table().get(name.as_str()).copied()
+}
+
+pub fn all_commands() -> impl Iterator<Item = &'static CommandSpec> {
+    command_table().values().copied()
+}
+
+/// Finds the entry that handles `args`, descending into subcommands, and validates its arity.
+fn resolve(args: &[Vec<u8>]) -> Result<&'static CommandSpec> {
+    let name = &args[0];
+    let spec = lookup(name).ok_or_else(|| {
+        Error::ValidationError(format!(
+            "unknown command '{}', with args beginning with: {}",
+            String::from_utf8_lossy(name),
+            args[1..]
+                .iter()
+                .map(|arg| format!("'{}' ", String::from_utf8_lossy(arg)))
+                .collect::<String>()
+        ))
+    })?;
+    let spec = match args.get(1) {
+        Some(subcommand_name) if !spec.subcommands.is_empty() => {
+            spec.find_subcommand(subcommand_name).ok_or_else(|| {
+                Error::ValidationError(format!(
+                    "unknown subcommand '{}'. Try {} HELP.",
+                    String::from_utf8_lossy(subcommand_name),
+                    spec.name.to_ascii_uppercase()
+                ))
+            })?
         }
-        match &args[0] {
-            RespVal::BulkString(keys_pattern) if keys_pattern.as_slice() == b"*" =>
-                Ok(RedisCommand::Keys(keys_pattern.clone())),
-            _ => Err(Error::ValidationError(
-                    format!("Keys command got unkown argument"),
-            )),
+        _ => spec,
+    };
+    spec.check_arity(args)?;
+    Ok(spec)
+}
+
+/// Runs the request `frame`, which has to be an array of bulk strings.
+pub fn execute(frame: RespVal, map: &Database, config: &Config, client: &mut Client) -> Result<Response> {
+    let args = match frame {
+        RespVal::Array(vals) if !vals.is_empty() => vals
+            .into_iter()
+            .map(|val| match val {
+                RespVal::BulkString(arg) => Ok(arg),
+                _ => Err(Error::ValidationError(
+                    "Command arguments must be Bulk Strings".to_string(),
+                )),
+            })
+            .collect::<Result<Vec<Vec<u8>>>>()?,
+        parts => {
+            return Err(Error::ValidationError(format!(
+                "Can parse Redis Command only from non-empty RESP Array, but got {:?}",
+                parts
+            )))
+        }
+    };
+    let spec = resolve(&args)?;
+    let handler = spec.handler.ok_or_else(|| wrong_arity(spec.name))?;
+    let mut map = map
+        .lock()
+        .map_err(|_| Error::StateError("Mutex lock failed".to_string()))?;
+    let mut ctx = Context::new(&mut map, config, client);
+    let result = handler(&mut ctx, &args);
+    let block = ctx.block.take();
+    let response = match (result, block) {
+        (Ok(timeout_reply), Some(block)) => {
+            let db_id = map.id();
+            let args = block.args.unwrap_or(args);
+            let (id, receiver) = map.blocked.block(db_id, &block.keys, client.clone(), handler, args);
+            Ok(Response::Blocked(Blocked {
+                id,
+                receiver,
+                timeout: block.timeout,
+                timeout_reply,
+            }))
+        }
+        (result, _) => result.map(Response::Reply),
+    };
+    serve_blocked_clients(&mut map, config);
+    response
+}
+
+/// Runs the commands of clients blocked on keys that received data, oldest first. A command
+/// that blocks again keeps its client waiting; serving a command may make more keys ready.
+fn serve_blocked_clients(db: &mut Db, config: &Config) {
+    while let Some(key) = db.blocked.take_ready_key() {
+        for id in db.blocked.waiters_on(&key) {
+            let (mut client, handler, args) = match db.blocked.get(id) {
+                Some(waiter) => (waiter.client.clone(), waiter.handler, waiter.args.clone()),
+                None => continue,
+            };
+            let mut ctx = Context::new(db, config, &mut client);
+            let result = handler(&mut ctx, &args);
+            if ctx.block.is_none() {
+                db.blocked.reply(id, result);
+            }
         }
     }
 }
 
-#[derive(Debug)]
-pub struct SetData {
-    pub key: Vec<u8>,
-    pub value: Vec<u8>,
-    pub options: Vec<SetOption>,
+pub fn wrong_arity(name: &str) -> Error {
+    Error::ValidationError(format!("wrong number of arguments for '{}' command", name))
+}
+
+pub fn invalid_expire_time(command_name: &str) -> Error {
+    Error::ValidationError(format!("invalid expire time in '{}' command", command_name))
+}
+
+pub fn syntax_error() -> Error {
+    Error::ValidationError("syntax error".to_string())
+}
+
+/// Parses an integer argument the way Redis does, rejecting anything but plain decimal digits
+/// with an optional minus sign. Leading zeros are rejected as well, so that every integer has
+/// exactly one string representation.
+pub fn parse_integer(arg: &[u8]) -> Result<i64> {
+    let valid_digits = |digits: &[u8]| match digits {
+        [] => false,
+        [b'0'] => true,
+        [b'0', ..] => false,
+        digits => digits.iter().all(u8::is_ascii_digit),
+    };
+    let valid = match arg {
+        [b'-', b'0', ..] => false,
+        [b'-', digits @ ..] => valid_digits(digits),
+        digits => valid_digits(digits),
+    };
+    std::str::from_utf8(arg)
+        .ok()
+        .filter(|_| valid)
+        .and_then(|arg| i64::from_str(arg).ok())
+        .ok_or_else(|| Error::ValidationError("value is not an integer or out of range".to_string()))
+}
+
+/// Whether `arg` spells an infinity, which float arguments reject like NaN.
+pub fn is_infinity(arg: &[u8]) -> bool {
+    let unsigned = arg.strip_prefix(b"-").or_else(|| arg.strip_prefix(b"+")).unwrap_or(arg);
+    unsigned.eq_ignore_ascii_case(b"inf") || unsigned.eq_ignore_ascii_case(b"infinity")
+}
+
+/// Parses the timeout of a blocking command: seconds with an optional fraction, 0 meaning
+/// forever.
+pub fn parse_timeout(arg: &[u8]) -> Result<Option<Duration>> {
+    let seconds = std::str::from_utf8(arg)
+        .ok()
+        .and_then(|arg| arg.parse::<f64>().ok())
+        .filter(|seconds| seconds.is_finite())
+        .ok_or_else(|| Error::ValidationError("timeout is not a float or out of range".to_string()))?;
+    if seconds < 0.0 {
+        return Err(Error::ValidationError("timeout is negative".to_string()));
+    }
+    if seconds > (i64::MAX / 1000) as f64 {
+        return Err(Error::ValidationError("timeout is out of range".to_string()));
+    }
+    Ok(Some(Duration::from_secs_f64(seconds)).filter(|timeout| !timeout.is_zero()))
+}
+
+/// Resolves an inclusive range of indices into a sequence of `length` elements, where negative
+/// indices count from the end, the way `LRANGE` and `ZRANGE` do. Returns `None` if nothing is
+/// left of the range after clamping it to the sequence.
+pub fn resolve_range(start: i64, stop: i64, length: usize) -> Option<(usize, usize)> {
+    let length = length as i64;
+    let start = if start < 0 { (start + length).max(0) } else { start };
+    let stop = if stop < 0 { stop + length } else { stop };
+    if start > stop || start >= length {
+        return None;
+    }
+    Some((start as usize, stop.min(length - 1) as usize))
 }
 
-#[derive(Debug)]
-pub enum SetOption {
-    Px(u64),
+/// Parses the count of `HRANDFIELD`, `SRANDMEMBER` and `ZRANDMEMBER`, `with_values` being
+/// whether every element is replied together with its value or score.
+pub fn parse_random_count(arg: &[u8], with_values: bool) -> Result<i64> {
+    let count = parse_integer(arg)?;
+    // Like Redis, accept -LONG_MAX to LONG_MAX, and with values only counts whose reply length
+    // doesn't overflow.
+    if count == i64::MIN || (with_values && count.unsigned_abs() > (i64::MAX / 2) as u64) {
+        return Err(Error::ValidationError("value is out of range".to_string()));
+    }
+    Ok(count)
+}
+
+/// The indices of the elements to reply with out of `length`, for a count parsed by
+/// `parse_random_count`: distinct ones for a positive count, or all of them if there are not
+/// as many, and `-count` indices that may repeat for a negative one. `length` must not be zero.
+///
+/// Unlike Redis, which streams the reply for a negative count, the reply is built in memory
+/// while the keyspace is locked. A count whose indices can't even be allocated is refused
+/// rather than aborting the server.
+pub fn random_indices(length: usize, count: i64) -> Result<Vec<usize>> {
+    if count >= 0 {
+        return Ok(if count as usize >= length {
+            (0..length).collect()
+        } else {
+            random::distinct_below(length, count as usize)
+        });
+    }
+    let count = usize::try_from(count.unsigned_abs()).unwrap_or(usize::MAX);
+    let mut indices = Vec::new();
+    indices
+        .try_reserve_exact(count)
+        .map_err(|_| Error::ValidationError("the reply for this count is too large".to_string()))?;
+    indices.extend((0..count).map(|_| random::random_below(length)));
+    Ok(indices)
 }
 
-impl SetOption {
-    fn parse_from(args: &[RespVal]) -> Result<SetOption> {
-        if args.is_empty() {
-            return Err(Error::ValidationError("No Option given".to_string()));
+pub fn ok() -> RespVal {
+    RespVal::SimpleString(b"OK".to_vec())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    pub fn run(map: &Database, client: &mut Client, args: &[&str]) -> Result<RespVal> {
+        let config = Config {
+            dir: ".".into(),
+            dbfilename: "dump.rdb".into(),
+        };
+        let frame = RespVal::Array(
+            args.iter()
+                .map(|arg| RespVal::BulkString(arg.as_bytes().to_vec()))
+                .collect(),
+        );
+        match execute(frame, map, &config, client)? {
+            Response::Reply(reply) => Ok(reply),
+            Response::Blocked(_) => panic!("{:?} blocked", args),
         }
-        match &args[0] {
-            RespVal::BulkString(arg1) if arg1.eq_ignore_ascii_case(b"px") => {
-                let px_arg_error = Error::ValidationError(
-                    "Option 'px' was not followed by unsigned integer".to_string(),
-                );
-                match &args[1] {
-                    RespVal::BulkString(arg2) => {
-                        let arg2 = String::from_utf8(arg2.to_vec())?;
-                        let period_of_validity = u64::from_str(&arg2).map_err(|_| px_arg_error)?;
-                        Ok(SetOption::Px(period_of_validity))
-                    }
-                    _ => Err(px_arg_error),
-                }
+    }
+
+    /// Runs a command that is expected to block.
+    pub fn run_blocked(map: &Database, client: &mut Client, args: &[&str]) -> Blocked {
+        let config = Config {
+            dir: ".".into(),
+            dbfilename: "dump.rdb".into(),
+        };
+        let frame = RespVal::Array(
+            args.iter()
+                .map(|arg| RespVal::BulkString(arg.as_bytes().to_vec()))
+                .collect(),
+        );
+        match execute(frame, map, &config, client) {
+            Ok(Response::Blocked(blocked)) => blocked,
+            _ => panic!("{:?} didn't block", args),
+        }
+    }
+
+    pub fn new_database() -> Database {
+        Arc::new(Mutex::new(Db::new()))
+    }
+
+    #[test]
+    fn test_dispatch_is_case_insensitive() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(
+            run(&map, &mut client, &["PING"]).unwrap(),
+            RespVal::SimpleString(b"PONG".to_vec())
+        );
+        assert_eq!(run(&map, &mut client, &["SeT", "k", "v"]).unwrap(), ok());
+        assert_eq!(
+            run(&map, &mut client, &["CONFIG", "GET", "dir"]).unwrap(),
+            RespVal::Map(vec![(
+                RespVal::BulkString(b"dir".to_vec()),
+                RespVal::BulkString(b".".to_vec())
+            )])
+        );
+    }
+
+    #[test]
+    fn test_dispatch_checks_arity() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert!(run(&map, &mut client, &["get"]).is_err());
+        assert!(run(&map, &mut client, &["get", "a", "b"]).is_err());
+        assert!(run(&map, &mut client, &["config"]).is_err());
+        assert!(run(&map, &mut client, &["config", "nope"]).is_err());
+        assert!(run(&map, &mut client, &["nope"]).is_err());
+    }
+
+    #[test]
+    fn test_command_table_is_consistent() {
+        for spec in all_commands() {
+            assert_eq!(spec.name, spec.name.to_ascii_lowercase());
+            assert!(spec.handler.is_some() || !spec.subcommands.is_empty());
+            for subcommand in spec.subcommands {
+                assert!(subcommand.name.starts_with(&format!("{}|", spec.name)));
             }
-            _ => Err(Error::ValidationError(
-                "Provided unknown Option for SET command".to_string(),
-            )),
         }
+        assert_eq!(
+            run(&new_database(), &mut Client::new(), &["command", "count"]).unwrap(),
+            RespVal::Integer(all_commands().count() as i64)
+        );
+    }
+
+    #[test]
+    fn test_random_count() {
+        assert_eq!(parse_random_count(b"5000000000000000000", false).unwrap(), 5000000000000000000);
+        assert!(parse_random_count(b"5000000000000000000", true).is_err());
+        assert_eq!(parse_random_count(b"-4611686018427387903", true).unwrap(), -4611686018427387903);
+        assert!(parse_random_count(b"-4611686018427387904", true).is_err());
+        assert!(parse_random_count(b"-9223372036854775808", false).is_err());
+        assert_eq!(random_indices(3, 5000000000000000000).unwrap(), vec![0, 1, 2]);
+        assert_eq!(random_indices(3, -5).unwrap().len(), 5);
+        assert!(random_indices(3, -5).unwrap().iter().all(|&index| index < 3));
+        // A reply this large can't be built, which must not take the server down.
+        assert!(random_indices(3, -4000000000000000000).is_err());
+    }
+
+    #[test]
+    fn test_parse_integer() {
+        assert_eq!(parse_integer(b"-12").unwrap(), -12);
+        assert_eq!(parse_integer(b"9223372036854775807").unwrap(), i64::MAX);
+        assert!(parse_integer(b"9223372036854775808").is_err());
+        assert!(parse_integer(b"+1").is_err());
+        assert!(parse_integer(b" 1").is_err());
+        assert!(parse_integer(b"-").is_err());
+        assert!(parse_integer(b"").is_err());
+        assert!(parse_integer(b"007").is_err());
+        assert!(parse_integer(b"-0").is_err());
+        assert_eq!(parse_integer(b"0").unwrap(), 0);
     }
 }
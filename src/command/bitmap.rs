@@ -0,0 +1,670 @@
+use super::string::MAX_STRING_LENGTH;
+use super::{parse_integer, syntax_error, CommandFlag::*, CommandSpec, Context};
+use crate::error::{Error, Result};
+use crate::resp::RespVal;
+use crate::Value;
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("setbit", 4, setbit)
+        .flags(&[Write, DenyOom])
+        .keys(1, 1, 1)
+        .docs("bitmap", "2.2.0", "Sets or clears the bit at offset of the string value. Creates the key if it doesn't exist."),
+    CommandSpec::new("getbit", 3, getbit)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("bitmap", "2.2.0", "Returns a bit value by offset."),
+    CommandSpec::new("bitcount", -2, bitcount)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("bitmap", "2.6.0", "Counts the number of set bits (population counting) in a string."),
+    CommandSpec::new("bitpos", -3, bitpos)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("bitmap", "2.8.7", "Finds the first set (1) or clear (0) bit in a string."),
+    CommandSpec::new("bitop", -4, bitop)
+        .flags(&[Write, DenyOom])
+        .keys(2, -1, 1)
+        .docs("bitmap", "2.6.0", "Performs bitwise operations on multiple strings, and stores the result."),
+    CommandSpec::new("bitfield", -2, bitfield)
+        .flags(&[Write, DenyOom])
+        .keys(1, 1, 1)
+        .docs("bitmap", "3.2.0", "Performs arbitrary bitfield integer operations on strings."),
+    CommandSpec::new("bitfield_ro", -2, bitfield_ro)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("bitmap", "6.0.0", "Performs arbitrary read-only bitfield integer operations on strings."),
+];
+
+/// Parses a bit offset, which must address a bit within the largest string.
+fn parse_bit_offset(arg: &[u8]) -> Result<usize> {
+    parse_integer(arg)
+        .ok()
+        .and_then(|offset| usize::try_from(offset).ok())
+        .filter(|&offset| offset < MAX_STRING_LENGTH * 8)
+        .ok_or_else(|| Error::ValidationError("bit offset is not an integer or out of range".to_string()))
+}
+
+/// The value of the bit at `offset`, counting from the most significant bit of the first byte.
+/// Bits past the end of the string are 0.
+fn bit_at(data: &[u8], offset: usize) -> bool {
+    data.get(offset / 8).is_some_and(|byte| byte & (0x80 >> (offset % 8)) != 0)
+}
+
+fn setbit(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    let offset = parse_bit_offset(&args[2])?;
+    let bit = match parse_integer(&args[3]) {
+        Ok(0) => false,
+        Ok(1) => true,
+        _ => return Err(Error::ValidationError("bit is not an integer or out of range".to_string())),
+    };
+    if ctx.get_string(key)?.is_none() {
+        ctx.db.insert(key.clone(), Value::string(Vec::new()));
+    }
+    let data = ctx.get_string_mut(key)?.expect("the key was just created");
+    let (index, mask) = (offset / 8, 0x80 >> (offset % 8));
+    if data.len() <= index {
+        data.resize(index + 1, 0);
+    }
+    let previous = data[index] & mask != 0;
+    if bit {
+        data[index] |= mask;
+    } else {
+        data[index] &= !mask;
+    }
+    Ok(RespVal::Integer(previous as i64))
+}
+
+fn getbit(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let offset = parse_bit_offset(&args[2])?;
+    let data = ctx.get_string(&args[1])?.map_or(&[][..], Vec::as_slice);
+    Ok(RespVal::Integer(bit_at(data, offset) as i64))
+}
+
+/// A range of `BITCOUNT` or `BITPOS`, in bytes or with the `BIT` unit in bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BitRange {
+    start: i64,
+    end: i64,
+    in_bits: bool,
+}
+
+impl BitRange {
+    fn parse(start: &[u8], end: &[u8], unit: Option<&Vec<u8>>) -> Result<BitRange> {
+        let in_bits = match unit.map(|unit| unit.to_ascii_lowercase()).as_deref() {
+            None | Some(b"byte") => false,
+            Some(b"bit") => true,
+            Some(_) => return Err(syntax_error()),
+        };
+        Ok(BitRange {
+            start: parse_integer(start)?,
+            end: parse_integer(end)?,
+            in_bits,
+        })
+    }
+
+    /// Resolves the range against a string, like Redis clamping it to the string, and returns
+    /// the first and last bit it covers, or `None` if it is empty.
+    fn resolve(self, data: &[u8]) -> Option<(usize, usize)> {
+        let length = if self.in_bits { data.len() as i64 * 8 } else { data.len() as i64 };
+        let start = if self.start < 0 { (length + self.start).max(0) } else { self.start };
+        let end = if self.end < 0 { (length + self.end).max(0) } else { self.end }.min(length - 1);
+        if start > end {
+            return None;
+        }
+        let (start, end) = (start as usize, end as usize);
+        Some(if self.in_bits { (start, end) } else { (start * 8, end * 8 + 7) })
+    }
+}
+
+/// Counts the set bits of `data` a word at a time.
+fn popcount(data: &[u8]) -> usize {
+    let words = data.chunks_exact(8);
+    let rest = words.remainder().iter().map(|byte| byte.count_ones() as usize).sum::<usize>();
+    words
+        .map(|word| u64::from_ne_bytes(word.try_into().expect("words are 8 bytes")).count_ones() as usize)
+        .sum::<usize>()
+        + rest
+}
+
+/// Counts the set bits from bit `start` to bit `end`, both included.
+fn count_bits(data: &[u8], start: usize, end: usize) -> usize {
+    let (first, last) = (start / 8, end / 8);
+    let before = (data[first] & !(0xff >> (start % 8))).count_ones() as usize;
+    let after = (data[last] & (0xff >> (end % 8) >> 1)).count_ones() as usize;
+    popcount(&data[first..=last]) - before - after
+}
+
+/// Replies with the number of set bits: `key [start end [BYTE | BIT]]`.
+fn bitcount(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let range = match &args[2..] {
+        [] => None,
+        [start, end, unit @ ..] if unit.len() <= 1 => Some(BitRange::parse(start, end, unit.first())?),
+        _ => return Err(syntax_error()),
+    };
+    let data = ctx.get_string(&args[1])?.map_or(&[][..], Vec::as_slice);
+    let count = match range {
+        None => popcount(data),
+        Some(range) => range.resolve(data).map_or(0, |(start, end)| count_bits(data, start, end)),
+    };
+    Ok(RespVal::Integer(count as i64))
+}
+
+/// Finds the first bit equal to `bit` from bit `start` to bit `end`, both included.
+fn find_bit(data: &[u8], bit: bool, start: usize, end: usize) -> Option<usize> {
+    let (first, last) = (start / 8, end / 8);
+    // Bytes without the bit are all ones when looking for a 0, and all zeros for a 1. Bits
+    // outside the range are flipped so that they never match.
+    let skip = if bit { 0x00 } else { 0xff };
+    let byte_at = |index: usize| {
+        let mut byte = data[index] ^ skip;
+        if index == first {
+            byte &= 0xff >> (start % 8);
+        }
+        if index == last {
+            byte &= !(0xff >> (end % 8) >> 1);
+        }
+        byte
+    };
+    (first..=last)
+        .find(|&index| byte_at(index) != 0)
+        .map(|index| index * 8 + byte_at(index).leading_zeros() as usize)
+}
+
+/// Replies with the position of the first bit set to 0 or 1: `key bit [start [end [BYTE |
+/// BIT]]]`. Without an end, the string is as if padded with zeros, so that a 0 is always found.
+fn bitpos(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let bit = match parse_integer(&args[2])? {
+        0 => false,
+        1 => true,
+        _ => return Err(Error::ValidationError("The bit argument must be 1 or 0.".to_string())),
+    };
+    let end_given = args.len() > 4;
+    let range = match &args[3..] {
+        [] => BitRange { start: 0, end: -1, in_bits: false },
+        [start] => BitRange::parse(start, b"-1", None)?,
+        [start, end, unit @ ..] if unit.len() <= 1 => BitRange::parse(start, end, unit.first())?,
+        _ => return Err(syntax_error()),
+    };
+    let data = match ctx.get_string(&args[1])? {
+        Some(data) => data,
+        None => return Ok(RespVal::Integer(if bit { -1 } else { 0 })),
+    };
+    let (start, end) = match range.resolve(data) {
+        Some(range) => range,
+        None => return Ok(RespVal::Integer(-1)),
+    };
+    let position = match find_bit(data, bit, start, end) {
+        Some(position) => position as i64,
+        None if !bit && !end_given => (end / 8 + 1) as i64 * 8,
+        None => -1,
+    };
+    Ok(RespVal::Integer(position))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BitOperation {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+/// Stores the bitwise AND, OR, XOR or NOT of strings, the shorter ones padded with zeros:
+/// `operation destkey key [key ...]`. Replies with the length of the result.
+fn bitop(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let operation = match args[1].to_ascii_lowercase().as_slice() {
+        b"and" => BitOperation::And,
+        b"or" => BitOperation::Or,
+        b"xor" => BitOperation::Xor,
+        b"not" => BitOperation::Not,
+        _ => return Err(syntax_error()),
+    };
+    let (destination, keys) = (&args[2], &args[3..]);
+    if operation == BitOperation::Not && keys.len() != 1 {
+        return Err(Error::ValidationError("BITOP NOT must be called with a single source key.".to_string()));
+    }
+    let mut length = 0;
+    for key in keys {
+        length = length.max(ctx.get_string(key)?.map_or(0, Vec::len));
+    }
+    if length == 0 {
+        ctx.db.remove(destination);
+        return Ok(RespVal::Integer(0));
+    }
+    let mut result = vec![0; length];
+    for (i, key) in keys.iter().enumerate() {
+        let source = ctx.get_string(key)?.map_or(&[][..], Vec::as_slice);
+        let (covered, padding) = result.split_at_mut(source.len());
+        match operation {
+            _ if i == 0 => covered.copy_from_slice(source),
+            BitOperation::And => {
+                covered.iter_mut().zip(source).for_each(|(byte, source)| *byte &= source);
+                padding.fill(0);
+            }
+            BitOperation::Or => covered.iter_mut().zip(source).for_each(|(byte, source)| *byte |= source),
+            BitOperation::Xor => covered.iter_mut().zip(source).for_each(|(byte, source)| *byte ^= source),
+            BitOperation::Not => unreachable!("NOT has a single source"),
+        }
+    }
+    if operation == BitOperation::Not {
+        result.iter_mut().for_each(|byte| *byte = !*byte);
+    }
+    ctx.db.insert(destination.clone(), Value::string(result));
+    Ok(RespVal::Integer(length as i64))
+}
+
+/// The type of a bitfield: a signed integer of 1 to 64 bits, or an unsigned one of 1 to 63.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FieldType {
+    signed: bool,
+    bits: u32,
+}
+
+impl FieldType {
+    /// Parses a type like `i16` or `u8`.
+    fn parse(arg: &[u8]) -> Result<FieldType> {
+        let signed = match arg.first() {
+            Some(b'i') => true,
+            Some(b'u') => false,
+            _ => return Err(invalid_field_type()),
+        };
+        let max_bits = if signed { 64 } else { 63 };
+        let bits = parse_integer(&arg[1..])
+            .ok()
+            .filter(|bits| (1..=max_bits).contains(bits))
+            .ok_or_else(invalid_field_type)?;
+        Ok(FieldType { signed, bits: bits as u32 })
+    }
+
+    /// Parses the offset of a field, in bits or, prefixed with `#`, in multiples of the field
+    /// width.
+    fn parse_offset(self, arg: &[u8]) -> Result<usize> {
+        let out_of_range = || Error::ValidationError("bit offset is not an integer or out of range".to_string());
+        match arg.strip_prefix(b"#") {
+            Some(index) => parse_integer(index)
+                .ok()
+                .and_then(|index| index.checked_mul(self.bits as i64))
+                .and_then(|offset| usize::try_from(offset).ok())
+                .filter(|&offset| offset < MAX_STRING_LENGTH * 8)
+                .ok_or_else(out_of_range),
+            None => parse_bit_offset(arg),
+        }
+    }
+
+    fn min(self) -> i128 {
+        if self.signed {
+            -(1 << (self.bits - 1))
+        } else {
+            0
+        }
+    }
+
+    fn max(self) -> i128 {
+        if self.signed {
+            (1 << (self.bits - 1)) - 1
+        } else {
+            (1 << self.bits) - 1
+        }
+    }
+
+    /// Reads the field at bit `offset`, bits past the end of the string being 0.
+    fn get(self, data: &[u8], offset: usize) -> i64 {
+        let value = (offset..offset + self.bits as usize)
+            .fold(0u64, |value, offset| value << 1 | bit_at(data, offset) as u64);
+        if self.signed {
+            // Sign extend from the most significant bit of the field.
+            let shift = 64 - self.bits;
+            ((value << shift) as i64) >> shift
+        } else {
+            value as i64
+        }
+    }
+
+    /// Writes the low bits of `value` to the field at bit `offset`, which must be within `data`.
+    fn set(self, data: &mut [u8], offset: usize, value: i64) {
+        for i in 0..self.bits as usize {
+            let bit = (value as u64 >> (self.bits as usize - 1 - i)) & 1 != 0;
+            let (index, mask) = ((offset + i) / 8, 0x80 >> ((offset + i) % 8));
+            if bit {
+                data[index] |= mask;
+            } else {
+                data[index] &= !mask;
+            }
+        }
+    }
+
+    /// Adds `increment` to `value`, handling the result not fitting the type as `overflow` says.
+    /// Returns `None` if it doesn't fit and overflows must fail. Like Redis, `value` is read as
+    /// unsigned for an unsigned type, so that setting `u8` to -1 overflows.
+    fn add(self, value: i64, increment: i64, overflow: Overflow) -> Option<i64> {
+        let value = if self.signed { value as i128 } else { value as u64 as i128 };
+        let sum = value + increment as i128;
+        if (self.min()..=self.max()).contains(&sum) {
+            return Some(sum as i64);
+        }
+        match overflow {
+            Overflow::Wrap => {
+                // Keep the low bits, sign extended for a signed type.
+                let shift = 64 - self.bits;
+                let low = (sum as u64) << shift;
+                Some(if self.signed { (low as i64) >> shift } else { (low >> shift) as i64 })
+            }
+            Overflow::Sat if sum > self.max() => Some(self.max() as i64),
+            Overflow::Sat => Some(self.min() as i64),
+            Overflow::Fail => None,
+        }
+    }
+}
+
+fn invalid_field_type() -> Error {
+    Error::ValidationError(
+        "Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.".to_string(),
+    )
+}
+
+/// What `BITFIELD` does when a value doesn't fit its field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Overflow {
+    /// Keep the low bits of the result.
+    Wrap,
+    /// Use the smallest or largest value of the type.
+    Sat,
+    /// Leave the field unchanged and reply with a null.
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldOperation {
+    Get,
+    Set(i64),
+    IncrBy(i64),
+}
+
+/// One `GET`, `SET` or `INCRBY` of a `BITFIELD` command, with the overflow behavior in effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FieldCommand {
+    operation: FieldOperation,
+    field_type: FieldType,
+    offset: usize,
+    overflow: Overflow,
+}
+
+impl FieldCommand {
+    fn parse_all(args: &[Vec<u8>], read_only: bool) -> Result<Vec<FieldCommand>> {
+        let mut commands = Vec::new();
+        let mut overflow = Overflow::Wrap;
+        let mut args = args.iter();
+        while let Some(subcommand) = args.next() {
+            let subcommand = subcommand.to_ascii_lowercase();
+            if subcommand == b"overflow" {
+                overflow = match args.next().ok_or_else(syntax_error)?.to_ascii_lowercase().as_slice() {
+                    b"wrap" => Overflow::Wrap,
+                    b"sat" => Overflow::Sat,
+                    b"fail" => Overflow::Fail,
+                    _ => return Err(Error::ValidationError("Invalid OVERFLOW type specified".to_string())),
+                };
+                continue;
+            }
+            let (field_type, offset) = match (subcommand.as_slice(), args.next(), args.next()) {
+                (b"get" | b"set" | b"incrby", Some(field_type), Some(offset)) => (field_type, offset),
+                _ => return Err(syntax_error()),
+            };
+            let field_type = FieldType::parse(field_type)?;
+            let offset = field_type.parse_offset(offset)?;
+            let mut value = || parse_integer(args.next().ok_or_else(syntax_error)?);
+            let operation = match subcommand.as_slice() {
+                b"get" => FieldOperation::Get,
+                b"set" => FieldOperation::Set(value()?),
+                _ => FieldOperation::IncrBy(value()?),
+            };
+            if read_only && operation != FieldOperation::Get {
+                return Err(Error::ValidationError("BITFIELD_RO only supports the GET subcommand".to_string()));
+            }
+            commands.push(FieldCommand {
+                operation,
+                field_type,
+                offset,
+                overflow,
+            });
+        }
+        Ok(commands)
+    }
+
+    /// Runs the command on `data`, which must be long enough to hold the field of a `SET` or
+    /// `INCRBY`. Replies with the value read, the previous value of a `SET`, or the new value
+    /// of an `INCRBY`.
+    fn apply(self, data: &mut [u8]) -> RespVal {
+        let FieldCommand { field_type, offset, .. } = self;
+        let current = field_type.get(data, offset);
+        let (new, reply) = match self.operation {
+            FieldOperation::Get => return RespVal::Integer(current),
+            FieldOperation::Set(value) => (field_type.add(value, 0, self.overflow), current),
+            FieldOperation::IncrBy(increment) => match field_type.add(current, increment, self.overflow) {
+                Some(new) => (Some(new), new),
+                None => (None, 0),
+            },
+        };
+        match new {
+            Some(new) => {
+                field_type.set(data, offset, new);
+                RespVal::Integer(reply)
+            }
+            None => RespVal::Null,
+        }
+    }
+}
+
+/// Reads and writes integers of arbitrary width at arbitrary bit offsets of a string:
+/// `key [GET encoding offset | [OVERFLOW WRAP | SAT | FAIL] SET encoding offset value |
+/// INCRBY encoding offset increment ...]`.
+fn bitfield(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    let commands = FieldCommand::parse_all(&args[2..], false)?;
+    let end = commands
+        .iter()
+        .filter(|command| command.operation != FieldOperation::Get)
+        .map(|command| (command.offset + command.field_type.bits as usize).div_ceil(8))
+        .max();
+    let end = match end {
+        Some(end) => end,
+        None => return read_fields(ctx, key, &commands),
+    };
+    if ctx.get_string(key)?.is_none() {
+        ctx.db.insert(key.clone(), Value::string(Vec::new()));
+    }
+    let data = ctx.get_string_mut(key)?.expect("the key was just created");
+    // Like Redis, the string grows to fit every field written, even if a write fails.
+    if data.len() < end {
+        data.resize(end, 0);
+    }
+    Ok(RespVal::Array(commands.into_iter().map(|command| command.apply(data)).collect()))
+}
+
+fn bitfield_ro(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let commands = FieldCommand::parse_all(&args[2..], true)?;
+    read_fields(ctx, &args[1], &commands)
+}
+
+fn read_fields(ctx: &mut Context, key: &[u8], commands: &[FieldCommand]) -> Result<RespVal> {
+    let data = ctx.get_string(key)?.map_or(&[][..], Vec::as_slice);
+    let values = commands
+        .iter()
+        .map(|command| RespVal::Integer(command.field_type.get(data, command.offset)))
+        .collect();
+    Ok(RespVal::Array(values))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{new_database, run};
+    use super::*;
+    use crate::client::Client;
+
+    fn bulk(value: &[u8]) -> RespVal {
+        RespVal::BulkString(value.to_vec())
+    }
+
+    #[test]
+    fn test_bit_helpers() {
+        let data: Vec<u8> = (0..=255).collect();
+        assert_eq!(popcount(&data), 1024);
+        assert_eq!(count_bits(&data, 0, 2047), 1024);
+        // 0x0f is bits 120 to 127.
+        assert_eq!(count_bits(&data, 122, 125), 2);
+        assert_eq!(find_bit(&data, true, 0, 2047), Some(15));
+        assert_eq!(find_bit(&data, false, 122, 125), Some(122));
+        assert_eq!(find_bit(&data, true, 120, 123), None);
+        assert_eq!(find_bit(&[0xff; 9], false, 0, 71), None);
+    }
+
+    #[test]
+    fn test_setbit_and_getbit() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(run(&map, &mut client, &["setbit", "k", "7", "1"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["setbit", "k", "7", "1"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["get", "k"]).unwrap(), bulk(b"\x01"));
+        // The string grows with zero bytes to fit the offset.
+        assert_eq!(run(&map, &mut client, &["setbit", "k", "16", "1"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["get", "k"]).unwrap(), bulk(b"\x01\x00\x80"));
+        assert_eq!(run(&map, &mut client, &["setbit", "k", "7", "0"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["getbit", "k", "16"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["getbit", "k", "1000"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["getbit", "missing", "0"]).unwrap(), RespVal::Integer(0));
+        assert!(run(&map, &mut client, &["setbit", "k", "1", "2"]).is_err());
+        assert!(run(&map, &mut client, &["setbit", "k", "-1", "1"]).is_err());
+        assert!(run(&map, &mut client, &["setbit", "k", "4294967296", "1"]).is_err());
+        run(&map, &mut client, &["rpush", "list", "a"]).unwrap();
+        assert!(matches!(run(&map, &mut client, &["setbit", "list", "0", "1"]), Err(Error::WrongType)));
+    }
+
+    #[test]
+    fn test_bitcount_and_bitpos() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["set", "k", "foobar"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["bitcount", "k"]).unwrap(), RespVal::Integer(26));
+        assert_eq!(run(&map, &mut client, &["bitcount", "k", "0", "0"]).unwrap(), RespVal::Integer(4));
+        assert_eq!(run(&map, &mut client, &["bitcount", "k", "1", "1"]).unwrap(), RespVal::Integer(6));
+        assert_eq!(run(&map, &mut client, &["bitcount", "k", "1", "1", "byte"]).unwrap(), RespVal::Integer(6));
+        assert_eq!(run(&map, &mut client, &["bitcount", "k", "5", "30", "bit"]).unwrap(), RespVal::Integer(17));
+        assert_eq!(run(&map, &mut client, &["bitcount", "k", "-2", "-1"]).unwrap(), RespVal::Integer(7));
+        assert_eq!(run(&map, &mut client, &["bitcount", "k", "3", "1"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["bitcount", "missing", "0", "-1"]).unwrap(), RespVal::Integer(0));
+        assert!(run(&map, &mut client, &["bitcount", "k", "0"]).is_err());
+        assert!(run(&map, &mut client, &["bitcount", "k", "0", "1", "bits"]).is_err());
+
+        // Bits 0 to 11 set, then a zero byte: "\xff\xf0\x00".
+        run(&map, &mut client, &["del", "k"]).unwrap();
+        for offset in 0..12 {
+            run(&map, &mut client, &["setbit", "k", &offset.to_string(), "1"]).unwrap();
+        }
+        run(&map, &mut client, &["setbit", "k", "23", "0"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["bitpos", "k", "0"]).unwrap(), RespVal::Integer(12));
+        assert_eq!(run(&map, &mut client, &["bitpos", "k", "1", "2"]).unwrap(), RespVal::Integer(-1));
+        assert_eq!(run(&map, &mut client, &["bitpos", "k", "1", "7", "15", "bit"]).unwrap(), RespVal::Integer(7));
+        assert_eq!(run(&map, &mut client, &["bitpos", "k", "0", "0", "0"]).unwrap(), RespVal::Integer(-1));
+        run(&map, &mut client, &["set", "ones", "\x7f"]).unwrap();
+        run(&map, &mut client, &["setbit", "ones", "0", "1"]).unwrap();
+        // Without an end the string is as if padded with zeros.
+        assert_eq!(run(&map, &mut client, &["bitpos", "ones", "0"]).unwrap(), RespVal::Integer(8));
+        assert_eq!(run(&map, &mut client, &["bitpos", "ones", "0", "0", "-1"]).unwrap(), RespVal::Integer(-1));
+        assert_eq!(run(&map, &mut client, &["bitpos", "missing", "0"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["bitpos", "missing", "1"]).unwrap(), RespVal::Integer(-1));
+        assert!(run(&map, &mut client, &["bitpos", "k", "2"]).is_err());
+    }
+
+    #[test]
+    fn test_bitop() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["set", "a", "abc"]).unwrap();
+        run(&map, &mut client, &["set", "b", "a"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["bitop", "and", "dest", "a", "b"]).unwrap(), RespVal::Integer(3));
+        assert_eq!(run(&map, &mut client, &["get", "dest"]).unwrap(), bulk(b"a\0\0"));
+        assert_eq!(run(&map, &mut client, &["bitop", "or", "dest", "b", "a", "missing"]).unwrap(), RespVal::Integer(3));
+        assert_eq!(run(&map, &mut client, &["get", "dest"]).unwrap(), bulk(b"abc"));
+        assert_eq!(run(&map, &mut client, &["bitop", "xor", "dest", "a", "b"]).unwrap(), RespVal::Integer(3));
+        assert_eq!(run(&map, &mut client, &["get", "dest"]).unwrap(), bulk(b"\0bc"));
+        assert_eq!(run(&map, &mut client, &["bitop", "not", "dest", "b"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["get", "dest"]).unwrap(), bulk(&[!b'a']));
+        assert!(run(&map, &mut client, &["bitop", "not", "dest", "a", "b"]).is_err());
+        assert!(run(&map, &mut client, &["bitop", "nand", "dest", "a", "b"]).is_err());
+        // An empty result deletes the destination.
+        assert_eq!(run(&map, &mut client, &["bitop", "and", "dest", "missing"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["exists", "dest"]).unwrap(), RespVal::Integer(0));
+    }
+
+    #[test]
+    fn test_field_overflow() {
+        let i8 = FieldType { signed: true, bits: 8 };
+        let u2 = FieldType { signed: false, bits: 2 };
+        let i64 = FieldType { signed: true, bits: 64 };
+        assert_eq!(i8.add(127, 1, Overflow::Wrap), Some(-128));
+        assert_eq!(i8.add(127, 1, Overflow::Sat), Some(127));
+        assert_eq!(i8.add(-100, -100, Overflow::Sat), Some(-128));
+        assert_eq!(i8.add(-100, -100, Overflow::Wrap), Some(56));
+        assert_eq!(i8.add(127, 1, Overflow::Fail), None);
+        assert_eq!(u2.add(3, 1, Overflow::Wrap), Some(0));
+        assert_eq!(u2.add(1, -2, Overflow::Sat), Some(0));
+        assert_eq!(u2.add(-1, 0, Overflow::Wrap), Some(3));
+        assert_eq!(i64.add(i64::MAX, 1, Overflow::Wrap), Some(i64::MIN));
+        assert_eq!(i64.add(i64::MIN, -1, Overflow::Sat), Some(i64::MIN));
+        let mut data = [0; 3];
+        FieldType { signed: true, bits: 5 }.set(&mut data, 6, -3);
+        assert_eq!(data, [0b0000_0011, 0b1010_0000, 0]);
+        assert_eq!(FieldType { signed: true, bits: 5 }.get(&data, 6), -3);
+        assert_eq!(FieldType { signed: false, bits: 5 }.get(&data, 6), 29);
+    }
+
+    #[test]
+    fn test_bitfield() {
+        let map = new_database();
+        let mut client = Client::new();
+        let integers = |values: &[i64]| RespVal::Array(values.iter().map(|&value| RespVal::Integer(value)).collect());
+        assert_eq!(
+            run(&map, &mut client, &["bitfield", "k", "incrby", "i5", "100", "1", "get", "u4", "0"]).unwrap(),
+            integers(&[1, 0])
+        );
+        assert_eq!(run(&map, &mut client, &["strlen", "k"]).unwrap(), RespVal::Integer(14));
+        for expected in [1, 2, 3] {
+            assert_eq!(
+                run(&map, &mut client, &["bitfield", "c", "incrby", "u2", "100", "1", "overflow", "sat", "incrby", "u2", "102", "1"])
+                    .unwrap(),
+                integers(&[expected, expected])
+            );
+        }
+        assert_eq!(
+            run(&map, &mut client, &["bitfield", "c", "incrby", "u2", "100", "1", "overflow", "sat", "incrby", "u2", "102", "1"])
+                .unwrap(),
+            integers(&[0, 3])
+        );
+        assert_eq!(
+            run(&map, &mut client, &["bitfield", "c", "overflow", "fail", "incrby", "u2", "102", "1", "get", "u2", "102"]).unwrap(),
+            RespVal::Array(vec![RespVal::Null, RespVal::Integer(3)])
+        );
+        // `#` offsets are multiples of the field width, and SET replies with the old value.
+        assert_eq!(
+            run(&map, &mut client, &["bitfield", "h", "set", "i8", "#1", "-5", "set", "i8", "#1", "7", "get", "u8", "8"]).unwrap(),
+            integers(&[0, -5, 7])
+        );
+        assert_eq!(run(&map, &mut client, &["get", "h"]).unwrap(), bulk(b"\0\x07"));
+        assert_eq!(
+            run(&map, &mut client, &["bitfield", "h", "overflow", "fail", "set", "u8", "0", "256", "get", "u8", "0"]).unwrap(),
+            RespVal::Array(vec![RespVal::Null, RespVal::Integer(0)])
+        );
+        assert_eq!(run(&map, &mut client, &["bitfield", "missing", "get", "i64", "0"]).unwrap(), integers(&[0]));
+        assert_eq!(run(&map, &mut client, &["exists", "missing"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["bitfield_ro", "h", "get", "u4", "12"]).unwrap(), integers(&[7]));
+        assert!(run(&map, &mut client, &["bitfield_ro", "h", "set", "u4", "0", "1"]).is_err());
+        assert!(run(&map, &mut client, &["bitfield", "h", "get", "u64", "0"]).is_err());
+        assert!(run(&map, &mut client, &["bitfield", "h", "get", "I8", "0"]).is_err());
+        assert!(run(&map, &mut client, &["bitfield", "h", "get", "i8", "-1"]).is_err());
+        assert!(run(&map, &mut client, &["bitfield", "h", "overflow", "nope"]).is_err());
+        assert!(run(&map, &mut client, &["bitfield", "h", "set", "i8", "0"]).is_err());
+        // Nothing runs when a later subcommand is invalid.
+        assert!(run(&map, &mut client, &["bitfield", "h", "set", "i8", "0", "1", "bogus"]).is_err());
+        assert_eq!(run(&map, &mut client, &["get", "h"]).unwrap(), bulk(b"\0\x07"));
+    }
+}
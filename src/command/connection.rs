@@ -0,0 +1,85 @@
+use super::{parse_integer, syntax_error, CommandFlag::*, CommandSpec, Context};
+use crate::error::{Error, Result};
+use crate::resp::{ProtocolVersion, RespVal};
+use crate::REDIS_VERSION;
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("ping", -1, ping)
+        .flags(&[Fast])
+        .docs("connection", "1.0.0", "Returns the server's liveliness response."),
+    CommandSpec::new("echo", 2, echo)
+        .flags(&[Fast])
+        .docs("connection", "1.0.0", "Returns the given string."),
+    CommandSpec::new("hello", -1, hello)
+        .flags(&[NoScript, Loading, Stale, Fast])
+        .docs("connection", "6.0.0", "Handshakes with the Redis server."),
+];
+
+fn ping(_ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    match args {
+        [_] => Ok(RespVal::SimpleString(b"PONG".to_vec())),
+        [_, message] => Ok(RespVal::BulkString(message.clone())),
+        _ => Err(super::wrong_arity("ping")),
+    }
+}
+
+fn echo(_ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    Ok(RespVal::BulkString(args[1].clone()))
+}
+
+fn hello(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let mut protocol = None;
+    let mut client_name = None;
+    if let Some(protocol_arg) = args.get(1) {
+        protocol = match parse_integer(protocol_arg) {
+            Ok(2) => Some(ProtocolVersion::Resp2),
+            Ok(3) => Some(ProtocolVersion::Resp3),
+            Ok(_) => return Err(Error::UnsupportedProtocol),
+            Err(_) => {
+                return Err(Error::ValidationError(
+                    "Protocol version is not an integer or out of range".to_string(),
+                ))
+            }
+        };
+    }
+    let mut options = args.iter().skip(2);
+    while let Some(option) = options.next() {
+        if option.eq_ignore_ascii_case(b"auth") {
+            // There are no users or passwords, so every client is the default user and
+            // authenticating as it always succeeds.
+            match (options.next(), options.next()) {
+                (Some(_username), Some(_password)) => (),
+                _ => return Err(syntax_error()),
+            }
+        } else if option.eq_ignore_ascii_case(b"setname") {
+            client_name = Some(options.next().ok_or_else(syntax_error)?.clone());
+        } else {
+            return Err(Error::ValidationError(format!(
+                "Syntax error in HELLO option '{}'",
+                String::from_utf8_lossy(option)
+            )));
+        }
+    }
+    // Options are validated completely before the connection state changes.
+    let client = &mut *ctx.client;
+    if let Some(protocol) = protocol {
+        client.protocol = protocol;
+    }
+    if let Some(client_name) = client_name {
+        client.name = Some(client_name);
+    }
+    let protocol_number = match client.protocol {
+        ProtocolVersion::Resp2 => 2,
+        ProtocolVersion::Resp3 => 3,
+    };
+    let field = |name: &str| RespVal::BulkString(name.as_bytes().to_vec());
+    Ok(RespVal::Map(vec![
+        (field("server"), field("redis")),
+        (field("version"), field(REDIS_VERSION)),
+        (field("proto"), RespVal::Integer(protocol_number)),
+        (field("id"), RespVal::Integer(client.id as i64)),
+        (field("mode"), field("standalone")),
+        (field("role"), field("master")),
+        (field("modules"), RespVal::Array(Vec::new())),
+    ]))
+}
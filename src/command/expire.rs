@@ -0,0 +1,258 @@
+use super::{invalid_expire_time, parse_integer, CommandFlag::*, CommandSpec, Context};
+use crate::error::{Error, Result};
+use crate::resp::RespVal;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("expire", -3, expire)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("generic", "1.0.0", "Sets the expiration time of a key in seconds."),
+    CommandSpec::new("pexpire", -3, pexpire)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("generic", "2.6.0", "Sets the expiration time of a key in milliseconds."),
+    CommandSpec::new("expireat", -3, expireat)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("generic", "1.2.0", "Sets the expiration time of a key to a Unix timestamp."),
+    CommandSpec::new("pexpireat", -3, pexpireat)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("generic", "2.6.0", "Sets the expiration time of a key to a Unix milliseconds timestamp."),
+    CommandSpec::new("ttl", 2, ttl)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("generic", "1.0.0", "Returns the expiration time in seconds of a key."),
+    CommandSpec::new("pttl", 2, pttl)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("generic", "2.6.0", "Returns the expiration time in milliseconds of a key."),
+    CommandSpec::new("expiretime", 2, expiretime)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("generic", "7.0.0", "Returns the expiration time of a key as a Unix timestamp."),
+    CommandSpec::new("pexpiretime", 2, pexpiretime)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("generic", "7.0.0", "Returns the expiration time of a key as a Unix milliseconds timestamp."),
+    CommandSpec::new("persist", 2, persist)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("generic", "2.2.0", "Removes the expiration time of a key."),
+];
+
+/// Milliseconds since the Unix epoch, negative for times before it.
+pub fn unix_millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_millis() as i64,
+        Err(err) => -(err.duration().as_millis() as i64),
+    }
+}
+
+/// The point in time `millis` milliseconds after the Unix epoch, if it can be represented.
+pub fn from_unix_millis(millis: i64) -> Option<SystemTime> {
+    if millis >= 0 {
+        UNIX_EPOCH.checked_add(Duration::from_millis(millis as u64))
+    } else {
+        UNIX_EPOCH.checked_sub(Duration::from_millis(millis.unsigned_abs()))
+    }
+}
+
+/// The NX, XX, GT and LT options of the EXPIRE family. GT and LT treat a key without
+/// expiration time as if it expired infinitely far in the future.
+#[derive(Debug, Default)]
+pub struct ExpireConditions {
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+}
+
+impl ExpireConditions {
+    pub fn parse(args: &[Vec<u8>]) -> Result<ExpireConditions> {
+        let mut conditions = ExpireConditions::default();
+        for arg in args {
+            match arg.to_ascii_lowercase().as_slice() {
+                b"nx" => conditions.nx = true,
+                b"xx" => conditions.xx = true,
+                b"gt" => conditions.gt = true,
+                b"lt" => conditions.lt = true,
+                _ => {
+                    return Err(Error::ValidationError(format!(
+                        "Unsupported option {}",
+                        String::from_utf8_lossy(arg)
+                    )))
+                }
+            }
+        }
+        if conditions.nx && (conditions.xx || conditions.gt || conditions.lt) {
+            return Err(Error::ValidationError(
+                "NX and XX, GT or LT options at the same time are not compatible".to_string(),
+            ));
+        }
+        if conditions.gt && conditions.lt {
+            return Err(Error::ValidationError(
+                "GT and LT options at the same time are not compatible".to_string(),
+            ));
+        }
+        Ok(conditions)
+    }
+
+    pub fn allow(&self, current: Option<SystemTime>, new: SystemTime) -> bool {
+        match current {
+            None => !(self.xx || self.gt),
+            Some(_) if self.nx => false,
+            Some(current) if self.gt => new > current,
+            Some(current) if self.lt => new < current,
+            Some(_) => true,
+        }
+    }
+}
+
+fn expire(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    expire_generic(ctx, args, 1000, true)
+}
+
+fn pexpire(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    expire_generic(ctx, args, 1, true)
+}
+
+fn expireat(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    expire_generic(ctx, args, 1000, false)
+}
+
+fn pexpireat(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    expire_generic(ctx, args, 1, false)
+}
+
+/// Sets the expiration time of `args[1]` to `args[2]` times `unit` milliseconds, counted from
+/// now if `relative` and from the Unix epoch otherwise. A time that has already passed deletes
+/// the key right away.
+fn expire_generic(ctx: &mut Context, args: &[Vec<u8>], unit: i64, relative: bool) -> Result<RespVal> {
+    let command_name = String::from_utf8_lossy(&args[0]).to_ascii_lowercase();
+    let key = &args[1];
+    let time = parse_integer(&args[2])?;
+    let conditions = ExpireConditions::parse(&args[3..])?;
+    let now = SystemTime::now();
+    let base = if relative { unix_millis(now) } else { 0 };
+    let expiration_time = time
+        .checked_mul(unit)
+        .and_then(|millis| millis.checked_add(base))
+        .and_then(from_unix_millis)
+        .ok_or_else(|| invalid_expire_time(&command_name))?;
+    let current = match ctx.get_value(key) {
+        Some(value) => value.expiration_time,
+        None => return Ok(RespVal::Integer(0)),
+    };
+    if !conditions.allow(current, expiration_time) {
+        return Ok(RespVal::Integer(0));
+    }
+    if expiration_time <= now {
+        ctx.db.remove(key);
+    } else {
+        ctx.db.set_expiration_time(key, Some(expiration_time));
+    }
+    Ok(RespVal::Integer(1))
+}
+
+fn ttl(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    // Round to the nearest second, like Redis does.
+    remaining_ttl(ctx, &args[1], |millis| (millis + 500) / 1000)
+}
+
+fn pttl(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    remaining_ttl(ctx, &args[1], |millis| millis)
+}
+
+/// Replies with the time to live of `key` converted by `scale`, -1 if the key exists but has no
+/// expiration time and -2 if it doesn't exist.
+fn remaining_ttl(ctx: &mut Context, key: &[u8], scale: fn(i64) -> i64) -> Result<RespVal> {
+    let now = SystemTime::now();
+    let reply = match ctx.get_value(key) {
+        None => -2,
+        Some(value) => match value.expiration_time {
+            None => -1,
+            Some(expiration_time) => scale((unix_millis(expiration_time) - unix_millis(now)).max(0)),
+        },
+    };
+    Ok(RespVal::Integer(reply))
+}
+
+fn expiretime(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    absolute_expiration_time(ctx, &args[1], |millis| millis / 1000)
+}
+
+fn pexpiretime(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    absolute_expiration_time(ctx, &args[1], |millis| millis)
+}
+
+fn absolute_expiration_time(ctx: &mut Context, key: &[u8], scale: fn(i64) -> i64) -> Result<RespVal> {
+    let reply = match ctx.get_value(key) {
+        None => -2,
+        Some(value) => value.expiration_time.map_or(-1, |time| scale(unix_millis(time))),
+    };
+    Ok(RespVal::Integer(reply))
+}
+
+fn persist(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    let has_expiration_time = ctx.get_value(key).is_some_and(|value| value.expiration_time.is_some());
+    if has_expiration_time {
+        ctx.db.set_expiration_time(key, None);
+    }
+    Ok(RespVal::Integer(has_expiration_time as i64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{new_database, run};
+    use super::*;
+    use crate::client::Client;
+
+    #[test]
+    fn test_expire_conditions() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["set", "k", "v"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["expire", "missing", "10"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["expire", "k", "10", "xx"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["expire", "k", "10", "gt"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["expire", "k", "100", "lt"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["expire", "k", "10", "nx"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["expire", "k", "200", "lt"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["expire", "k", "200", "xx", "gt"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["ttl", "k"]).unwrap(), RespVal::Integer(200));
+        assert!(run(&map, &mut client, &["expire", "k", "10", "nx", "xx"]).is_err());
+        assert!(run(&map, &mut client, &["expire", "k", "10", "gt", "lt"]).is_err());
+        assert!(run(&map, &mut client, &["expire", "k", "10", "foo"]).is_err());
+        assert!(run(&map, &mut client, &["expire", "k", "9223372036854775807"]).is_err());
+    }
+
+    #[test]
+    fn test_ttl_replies() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["set", "k", "v"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["ttl", "missing"]).unwrap(), RespVal::Integer(-2));
+        assert_eq!(run(&map, &mut client, &["pttl", "k"]).unwrap(), RespVal::Integer(-1));
+        assert_eq!(run(&map, &mut client, &["pexpiretime", "k"]).unwrap(), RespVal::Integer(-1));
+        assert_eq!(run(&map, &mut client, &["pexpireat", "k", "33177600000000"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["expiretime", "k"]).unwrap(), RespVal::Integer(33177600000));
+        assert_eq!(run(&map, &mut client, &["pexpiretime", "k"]).unwrap(), RespVal::Integer(33177600000000));
+        assert_eq!(run(&map, &mut client, &["persist", "k"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["persist", "k"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["ttl", "k"]).unwrap(), RespVal::Integer(-1));
+    }
+
+    #[test]
+    fn test_expire_in_the_past_deletes_the_key() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["set", "a", "1"]).unwrap();
+        run(&map, &mut client, &["set", "b", "1"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["expire", "a", "-1"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["expireat", "b", "1"]).unwrap(), RespVal::Integer(1));
+        assert!(map.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,368 @@
+use super::{ok, parse_integer, syntax_error, CommandFlag::*, CommandSpec, Context};
+use crate::error::{Error, Result};
+use crate::glob;
+use crate::lazyfree;
+use crate::resp::RespVal;
+use crate::Value;
+use std::time::SystemTime;
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("keys", 2, keys)
+        .flags(&[ReadOnly])
+        .docs("generic", "1.0.0", "Returns all key names that match a pattern."),
+    CommandSpec::new("scan", -2, scan)
+        .flags(&[ReadOnly])
+        .docs("generic", "2.8.0", "Iterates over the key names in the database."),
+    CommandSpec::new("del", -2, del)
+        .flags(&[Write])
+        .keys(1, -1, 1)
+        .docs("generic", "1.0.0", "Deletes one or more keys."),
+    CommandSpec::new("unlink", -2, unlink)
+        .flags(&[Write, Fast])
+        .keys(1, -1, 1)
+        .docs("generic", "4.0.0", "Asynchronously deletes one or more keys."),
+    CommandSpec::new("exists", -2, exists)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, -1, 1)
+        .docs("generic", "1.0.0", "Determines whether one or more keys exist."),
+    CommandSpec::new("touch", -2, touch)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, -1, 1)
+        .docs("generic", "3.2.1", "Returns the number of existing keys out of those specified after updating the time they were last accessed."),
+    CommandSpec::new("type", 2, type_command)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("generic", "1.0.0", "Determines the type of value stored at a key."),
+    CommandSpec::new("rename", 3, rename)
+        .flags(&[Write])
+        .keys(1, 2, 1)
+        .docs("generic", "1.0.0", "Renames a key and overwrites the destination."),
+    CommandSpec::new("renamenx", 3, renamenx)
+        .flags(&[Write, Fast])
+        .keys(1, 2, 1)
+        .docs("generic", "1.0.0", "Renames a key only when the target key name doesn't exist."),
+    CommandSpec::new("copy", -3, copy)
+        .flags(&[Write, DenyOom])
+        .keys(1, 2, 1)
+        .docs("generic", "6.2.0", "Copies the value of a key to a new key."),
+    CommandSpec::new("randomkey", 1, randomkey)
+        .flags(&[ReadOnly])
+        .docs("generic", "1.0.0", "Returns a random key name from the database."),
+    CommandSpec::new("dbsize", 1, dbsize)
+        .flags(&[ReadOnly, Fast])
+        .docs("server", "1.0.0", "Returns the number of keys in the database."),
+    CommandSpec::container("object", OBJECT_SUBCOMMANDS)
+        .docs("generic", "2.2.3", "A container for object introspection commands."),
+];
+
+const OBJECT_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("object|encoding", 3, object_encoding)
+        .flags(&[ReadOnly])
+        .keys(2, 2, 1)
+        .docs("generic", "2.2.3", "Returns the internal encoding of a Redis object."),
+];
+
+fn keys(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let pattern = &args[1];
+    let match_all = pattern.as_slice() == b"*";
+    let now = SystemTime::now();
+    let keys: Vec<RespVal> = ctx
+        .db
+        .iter()
+        .filter(|(key, value)| !value.is_expired_at(now) && (match_all || glob::matches(pattern, key)))
+        .map(|(key, _)| RespVal::BulkString(key.clone()))
+        .collect();
+    Ok(RespVal::Array(keys))
+}
+
+/// The type names `TYPE` may reply with.
+const TYPE_NAMES: &[&str] = &["string", "list", "set", "zset", "hash", "stream"];
+
+fn scan(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let mut cursor = std::str::from_utf8(&args[1])
+        .ok()
+        .and_then(|cursor| cursor.parse::<u64>().ok())
+        .ok_or_else(|| Error::ValidationError("invalid cursor".to_string()))?;
+    let mut pattern = None;
+    let mut count = 10;
+    let mut type_name = None;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        let value = options.next().ok_or_else(syntax_error)?;
+        match option.to_ascii_lowercase().as_slice() {
+            b"match" => pattern = Some(value).filter(|pattern| pattern.as_slice() != b"*"),
+            b"count" => {
+                count = parse_integer(value)?;
+                if count < 1 {
+                    return Err(syntax_error());
+                }
+            }
+            b"type" => {
+                let name = String::from_utf8_lossy(value).to_ascii_lowercase();
+                if !TYPE_NAMES.contains(&name.as_str()) {
+                    return Err(Error::ValidationError(format!("unknown type name '{}'", name)));
+                }
+                type_name = Some(name);
+            }
+            _ => return Err(syntax_error()),
+        }
+    }
+    // Like Redis, COUNT bounds the keys visited, not the ones that pass the filters. Visiting
+    // empty buckets counts too, so a sparse table doesn't turn a call into a full scan.
+    let mut keys = Vec::new();
+    let mut remaining_calls = count.saturating_mul(10);
+    loop {
+        cursor = ctx.db.scan(cursor, |key, _| keys.push(key.clone()));
+        remaining_calls -= 1;
+        if cursor == 0 || remaining_calls == 0 || keys.len() as i64 >= count {
+            break;
+        }
+    }
+    let now = SystemTime::now();
+    let keys = keys
+        .into_iter()
+        .filter(|key| pattern.is_none_or(|pattern| glob::matches(pattern, key)))
+        .filter(|key| {
+            if ctx.db.expire_if_needed(key, now) {
+                return false;
+            }
+            match &type_name {
+                Some(type_name) => ctx.db.get(key).is_some_and(|value| value.type_name() == type_name),
+                None => true,
+            }
+        })
+        .map(RespVal::BulkString)
+        .collect();
+    Ok(RespVal::Array(vec![
+        RespVal::BulkString(cursor.to_string().into_bytes()),
+        RespVal::Array(keys),
+    ]))
+}
+
+/// Removes the given keys and returns how many of them existed. Expired values are removed
+/// as well, but not counted.
+fn remove_keys(ctx: &mut Context, keys: &[Vec<u8>]) -> (i64, Vec<Value>) {
+    let now = SystemTime::now();
+    let mut removed = Vec::new();
+    for key in keys {
+        if ctx.db.expire_if_needed(key, now) {
+            continue;
+        }
+        if let Some(value) = ctx.db.remove(key) {
+            removed.push(value);
+        }
+    }
+    (removed.len() as i64, removed)
+}
+
+fn del(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (count, _removed) = remove_keys(ctx, &args[1..]);
+    Ok(RespVal::Integer(count))
+}
+
+fn unlink(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (count, removed) = remove_keys(ctx, &args[1..]);
+    lazyfree::free_values(removed);
+    Ok(RespVal::Integer(count))
+}
+
+/// Counts the keys that exist; keys given several times are counted several times.
+fn exists(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let count = args[1..]
+        .iter()
+        .filter(|key| ctx.get_value(key).is_some())
+        .count();
+    Ok(RespVal::Integer(count as i64))
+}
+
+fn touch(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    // There is no eviction and thus no access time to update.
+    exists(ctx, args)
+}
+
+fn type_command(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let type_name = ctx.get_value(&args[1]).map_or("none", |value| value.type_name());
+    Ok(RespVal::SimpleString(type_name.as_bytes().to_vec()))
+}
+
+fn rename(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    rename_key(ctx, &args[1], &args[2], false)
+}
+
+fn renamenx(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    rename_key(ctx, &args[1], &args[2], true)
+}
+
+/// Moves the value at `source` together with its TTL to `destination`.
+fn rename_key(ctx: &mut Context, source: &[u8], destination: &[u8], only_new: bool) -> Result<RespVal> {
+    if ctx.get_value(source).is_none() {
+        return Err(Error::ValidationError("no such key".to_string()));
+    }
+    if source == destination {
+        return Ok(if only_new { RespVal::Integer(0) } else { ok() });
+    }
+    if only_new && ctx.get_value(destination).is_some() {
+        return Ok(RespVal::Integer(0));
+    }
+    let value = ctx.db.remove(source).expect("the source key exists");
+    ctx.db.insert(destination.to_vec(), value);
+    Ok(if only_new { RespVal::Integer(1) } else { ok() })
+}
+
+fn copy(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (source, destination) = (&args[1], &args[2]);
+    let mut replace = false;
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        if option.eq_ignore_ascii_case(b"replace") {
+            replace = true;
+        } else if option.eq_ignore_ascii_case(b"db") {
+            // There is only database 0.
+            let db_index = parse_integer(options.next().ok_or_else(syntax_error)?)?;
+            if db_index != 0 {
+                return Err(Error::ValidationError("DB index is out of range".to_string()));
+            }
+        } else {
+            return Err(syntax_error());
+        }
+    }
+    if source == destination {
+        return Err(Error::ValidationError(
+            "source and destination objects are the same".to_string(),
+        ));
+    }
+    let value = match ctx.get_value(source) {
+        Some(value) => value.clone(),
+        None => return Ok(RespVal::Integer(0)),
+    };
+    if !replace && ctx.get_value(destination).is_some() {
+        return Ok(RespVal::Integer(0));
+    }
+    ctx.db.insert(destination.clone(), value);
+    Ok(RespVal::Integer(1))
+}
+
+fn randomkey(ctx: &mut Context, _args: &[Vec<u8>]) -> Result<RespVal> {
+    let now = SystemTime::now();
+    // Expired keys that are hit are reclaimed, so this terminates even if all keys expired.
+    while let Some((key, _)) = ctx.db.random_entry() {
+        let key = key.clone();
+        if !ctx.db.expire_if_needed(&key, now) {
+            return Ok(RespVal::BulkString(key));
+        }
+    }
+    Ok(RespVal::Null)
+}
+
+fn dbsize(ctx: &mut Context, _args: &[Vec<u8>]) -> Result<RespVal> {
+    Ok(RespVal::Integer(ctx.db.len() as i64))
+}
+
+fn object_encoding(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let encoding = ctx.get_value(&args[2]).map(Value::encoding_name);
+    Ok(encoding.map_or(RespVal::Null, |encoding| RespVal::BulkString(encoding.as_bytes().to_vec())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{new_database, run};
+    use super::*;
+    use crate::client::Client;
+
+    #[test]
+    fn test_del_and_exists_count_keys() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["mset", "a", "1", "b", "2"]).unwrap();
+        run(&map, &mut client, &["set", "expired", "3", "pxat", "1"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["exists", "a", "a", "expired", "c"]).unwrap(), RespVal::Integer(2));
+        assert_eq!(run(&map, &mut client, &["del", "a", "expired", "c"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["unlink", "a", "b"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["dbsize"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["randomkey"]).unwrap(), RespVal::Null);
+    }
+
+    #[test]
+    fn test_expired_keys_are_hidden_and_reclaimed_on_access() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["set", "live", "1"]).unwrap();
+        run(&map, &mut client, &["set", "expired", "2", "pxat", "1"]).unwrap();
+        let keys = run(&map, &mut client, &["keys", "*"]).unwrap();
+        assert_eq!(keys, RespVal::Array(vec![RespVal::BulkString(b"live".to_vec())]));
+        assert_eq!(run(&map, &mut client, &["dbsize"]).unwrap(), RespVal::Integer(2));
+        assert_eq!(run(&map, &mut client, &["get", "expired"]).unwrap(), RespVal::Null);
+        assert_eq!(run(&map, &mut client, &["dbsize"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(map.lock().unwrap().stats.expired_keys, 1);
+    }
+
+    #[test]
+    fn test_keys_filters_by_pattern() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["mset", "user:1", "a", "user:2", "b", "session:1", "c"]).unwrap();
+        run(&map, &mut client, &["set", "user:3", "d", "pxat", "1"]).unwrap();
+        let mut keys = match run(&map, &mut client, &["keys", "user:*"]).unwrap() {
+            RespVal::Array(keys) => keys,
+            reply => panic!("unexpected reply {:?}", reply),
+        };
+        keys.sort_by_key(|key| format!("{:?}", key));
+        assert_eq!(keys, vec![RespVal::BulkString(b"user:1".to_vec()), RespVal::BulkString(b"user:2".to_vec())]);
+        assert_eq!(run(&map, &mut client, &["keys", "s[a-e]ss?on:\\1"]).unwrap(), RespVal::Array(vec![RespVal::BulkString(b"session:1".to_vec())]));
+    }
+
+    #[test]
+    fn test_scan_returns_every_key_once_done() {
+        let map = new_database();
+        let mut client = Client::new();
+        for i in 0..100 {
+            run(&map, &mut client, &["set", &format!("key:{}", i), "v"]).unwrap();
+        }
+        run(&map, &mut client, &["set", "other", "v"]).unwrap();
+        let mut cursor = "0".to_string();
+        let mut seen = std::collections::HashSet::new();
+        loop {
+            let reply = run(&map, &mut client, &["scan", &cursor, "match", "key:*", "count", "7", "type", "string"]).unwrap();
+            let (next, keys) = match reply {
+                RespVal::Array(mut reply) => match (reply.remove(0), reply.remove(0)) {
+                    (RespVal::BulkString(next), RespVal::Array(keys)) => (next, keys),
+                    reply => panic!("unexpected reply {:?}", reply),
+                },
+                reply => panic!("unexpected reply {:?}", reply),
+            };
+            seen.extend(keys.into_iter().map(|key| match key {
+                RespVal::BulkString(key) => key,
+                key => panic!("unexpected key {:?}", key),
+            }));
+            cursor = String::from_utf8(next).unwrap();
+            if cursor == "0" {
+                break;
+            }
+        }
+        assert_eq!(seen.len(), 100);
+        assert!(!seen.contains(b"other".as_slice()));
+        assert!(run(&map, &mut client, &["scan", "abc"]).is_err());
+        assert!(run(&map, &mut client, &["scan", "0", "count", "0"]).is_err());
+        assert!(run(&map, &mut client, &["scan", "0", "type", "nope"]).is_err());
+        assert!(run(&map, &mut client, &["scan", "0", "match"]).is_err());
+    }
+
+    #[test]
+    fn test_rename_and_copy() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["set", "a", "1", "ex", "100"]).unwrap();
+        run(&map, &mut client, &["set", "b", "2"]).unwrap();
+        assert!(run(&map, &mut client, &["rename", "missing", "x"]).is_err());
+        assert_eq!(run(&map, &mut client, &["renamenx", "a", "b"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["rename", "a", "c"]).unwrap(), ok());
+        assert!(map.lock().unwrap()[b"c".as_slice()].expiration_time.is_some());
+        assert_eq!(run(&map, &mut client, &["type", "a"]).unwrap(), RespVal::SimpleString(b"none".to_vec()));
+        assert_eq!(run(&map, &mut client, &["copy", "c", "b"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["copy", "c", "b", "replace"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["get", "b"]).unwrap(), RespVal::BulkString(b"1".to_vec()));
+        assert!(run(&map, &mut client, &["copy", "c", "c"]).is_err());
+        assert!(run(&map, &mut client, &["copy", "c", "d", "db", "1"]).is_err());
+        assert_eq!(run(&map, &mut client, &["type", "b"]).unwrap(), RespVal::SimpleString(b"string".to_vec()));
+    }
+}
@@ -0,0 +1,571 @@
+use super::expire::{from_unix_millis, unix_millis, ExpireConditions};
+use super::{invalid_expire_time, is_infinity, ok, parse_integer, parse_random_count, random_indices, syntax_error, wrong_arity, CommandFlag::*, CommandSpec, Context};
+use crate::decimal::Decimal;
+use crate::error::{Error, Result};
+use crate::hash::Hash;
+use crate::random;
+use crate::resp::{ProtocolVersion, RespVal};
+use crate::{Data, Value};
+use std::time::SystemTime;
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("hset", -4, hset)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "2.0.0", "Creates or modifies the value of a field in a hash."),
+    CommandSpec::new("hmset", -4, hmset)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "2.0.0", "Sets the values of multiple fields."),
+    CommandSpec::new("hsetnx", 4, hsetnx)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "2.0.0", "Sets the value of a field in a hash only when the field doesn't exist."),
+    CommandSpec::new("hget", 3, hget)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "2.0.0", "Returns the value of a field in a hash."),
+    CommandSpec::new("hmget", -3, hmget)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "2.0.0", "Returns the values of all fields in a hash."),
+    CommandSpec::new("hdel", -3, hdel)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "2.0.0", "Deletes one or more fields and their values from a hash. Deletes the hash if no fields remain."),
+    CommandSpec::new("hgetall", 2, hgetall)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("hash", "2.0.0", "Returns all fields and values in a hash."),
+    CommandSpec::new("hkeys", 2, hkeys)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("hash", "2.0.0", "Returns all fields in a hash."),
+    CommandSpec::new("hvals", 2, hvals)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("hash", "2.0.0", "Returns all values in a hash."),
+    CommandSpec::new("hlen", 2, hlen)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "2.0.0", "Returns the number of fields in a hash."),
+    CommandSpec::new("hexists", 3, hexists)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "2.0.0", "Determines whether a field exists in a hash."),
+    CommandSpec::new("hstrlen", 3, hstrlen)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "3.2.0", "Returns the length of the value of a field."),
+    CommandSpec::new("hincrby", 4, hincrby)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "2.0.0", "Increments the integer value of a field in a hash by a number. Uses 0 as initial value if the field doesn't exist."),
+    CommandSpec::new("hincrbyfloat", 4, hincrbyfloat)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "2.6.0", "Increments the floating point value of a field by a number. Uses 0 as initial value if the field doesn't exist."),
+    CommandSpec::new("hrandfield", -2, hrandfield)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("hash", "6.2.0", "Returns one or more random fields from a hash."),
+    CommandSpec::new("hexpire", -6, hexpire)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "7.4.0", "Set expiry for hash field using relative time to expire (seconds)"),
+    CommandSpec::new("hpexpire", -6, hpexpire)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "7.4.0", "Set expiry for hash field using relative time to expire (milliseconds)"),
+    CommandSpec::new("hexpireat", -6, hexpireat)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "7.4.0", "Set expiry for hash field using an absolute Unix timestamp (seconds)"),
+    CommandSpec::new("hpexpireat", -6, hpexpireat)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "7.4.0", "Set expiry for hash field using an absolute Unix timestamp (milliseconds)"),
+    CommandSpec::new("httl", -5, httl)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "7.4.0", "Returns the TTL in seconds of a hash field."),
+    CommandSpec::new("hpttl", -5, hpttl)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "7.4.0", "Returns the TTL in milliseconds of a hash field."),
+    CommandSpec::new("hexpiretime", -5, hexpiretime)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "7.4.0", "Returns the expiration time of a hash field as a Unix timestamp, in seconds."),
+    CommandSpec::new("hpexpiretime", -5, hpexpiretime)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "7.4.0", "Returns the expiration time of a hash field as a Unix timestamp, in msec."),
+    CommandSpec::new("hpersist", -5, hpersist)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("hash", "7.4.0", "Removes the expiration time for each specified field"),
+];
+
+/// Expiration times of hash fields are capped to 48 bits of milliseconds, like in Redis.
+const MAX_FIELD_EXPIRATION_MILLIS: i64 = 0x3FFF_FFFF_FFFF;
+
+/// Reply codes of the per-field TTL commands.
+const NO_SUCH_FIELD: i64 = -2;
+const NO_TTL: i64 = -1;
+const CONDITION_NOT_MET: i64 = 0;
+const EXPIRATION_SET: i64 = 1;
+const DELETED: i64 = 2;
+
+/// The hash at `key`, which is created if it doesn't exist.
+fn hash_or_create<'a>(ctx: &'a mut Context, key: &[u8]) -> Result<&'a mut Hash> {
+    if ctx.get_hash(key)?.is_none() {
+        ctx.db.insert(key.to_vec(), Value::new(Data::Hash(Hash::default())));
+    }
+    Ok(ctx.get_hash_mut(key)?.expect("the key was just created"))
+}
+
+/// Sets the field value pairs in `args[2..]` and returns how many fields are new.
+fn set_fields(ctx: &mut Context, args: &[Vec<u8>]) -> Result<usize> {
+    if !args.len().is_multiple_of(2) {
+        return Err(wrong_arity(&String::from_utf8_lossy(&args[0]).to_ascii_lowercase()));
+    }
+    let hash = hash_or_create(ctx, &args[1])?;
+    let added = args[2..]
+        .chunks(2)
+        .filter(|pair| hash.insert(pair[0].clone(), pair[1].clone()))
+        .count();
+    Ok(added)
+}
+
+fn hset(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let added = set_fields(ctx, args)?;
+    Ok(RespVal::Integer(added as i64))
+}
+
+fn hmset(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    set_fields(ctx, args)?;
+    Ok(ok())
+}
+
+fn hsetnx(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let hash = hash_or_create(ctx, &args[1])?;
+    if hash.contains(&args[2]) {
+        return Ok(RespVal::Integer(0));
+    }
+    hash.insert(args[2].clone(), args[3].clone());
+    Ok(RespVal::Integer(1))
+}
+
+fn hget(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let value = ctx.get_hash(&args[1])?.and_then(|hash| hash.get(&args[2]));
+    Ok(value.map_or(RespVal::Null, |value| RespVal::BulkString(value.clone())))
+}
+
+fn hmget(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let hash = ctx.get_hash(&args[1])?;
+    let values = args[2..]
+        .iter()
+        .map(|field| match hash.and_then(|hash| hash.get(field)) {
+            Some(value) => RespVal::BulkString(value.clone()),
+            None => RespVal::Null,
+        })
+        .collect();
+    Ok(RespVal::Array(values))
+}
+
+fn hdel(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    let hash = match ctx.get_hash_mut(key)? {
+        Some(hash) => hash,
+        None => return Ok(RespVal::Integer(0)),
+    };
+    let deleted = args[2..].iter().filter(|field| hash.remove(field).is_some()).count();
+    if hash.is_empty() {
+        ctx.db.remove(key);
+    }
+    Ok(RespVal::Integer(deleted as i64))
+}
+
+fn hgetall(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let pairs = ctx.get_hash(&args[1])?.map_or_else(Vec::new, |hash| {
+        hash.iter()
+            .map(|(field, value)| (RespVal::BulkString(field.clone()), RespVal::BulkString(value.clone())))
+            .collect()
+    });
+    Ok(RespVal::Map(pairs))
+}
+
+fn hkeys(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let fields = ctx.get_hash(&args[1])?.map_or_else(Vec::new, |hash| {
+        hash.iter().map(|(field, _)| RespVal::BulkString(field.clone())).collect()
+    });
+    Ok(RespVal::Array(fields))
+}
+
+fn hvals(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let values = ctx.get_hash(&args[1])?.map_or_else(Vec::new, |hash| {
+        hash.iter().map(|(_, value)| RespVal::BulkString(value.clone())).collect()
+    });
+    Ok(RespVal::Array(values))
+}
+
+fn hlen(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let length = ctx.get_hash(&args[1])?.map_or(0, Hash::len);
+    Ok(RespVal::Integer(length as i64))
+}
+
+fn hexists(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let exists = ctx.get_hash(&args[1])?.is_some_and(|hash| hash.contains(&args[2]));
+    Ok(RespVal::Integer(exists as i64))
+}
+
+fn hstrlen(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let length = ctx
+        .get_hash(&args[1])?
+        .and_then(|hash| hash.get(&args[2]))
+        .map_or(0, Vec::len);
+    Ok(RespVal::Integer(length as i64))
+}
+
+fn hincrby(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let increment = parse_integer(&args[3])?;
+    let hash = hash_or_create(ctx, &args[1])?;
+    let current = match hash.get(&args[2]) {
+        Some(value) => parse_integer(value)
+            .map_err(|_| Error::ValidationError("hash value is not an integer".to_string()))?,
+        None => 0,
+    };
+    let new = current
+        .checked_add(increment)
+        .ok_or_else(|| Error::ValidationError("increment or decrement would overflow".to_string()))?;
+    hash.insert_keeping_ttl(args[2].clone(), new.to_string().into_bytes());
+    Ok(RespVal::Integer(new))
+}
+
+fn hincrbyfloat(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let nan_or_infinity = || Error::ValidationError("increment would produce NaN or Infinity".to_string());
+    let increment = match Decimal::parse(&args[3]) {
+        Some(increment) => increment,
+        None if is_infinity(&args[3]) => return Err(nan_or_infinity()),
+        None => return Err(Error::ValidationError("value is not a valid float".to_string())),
+    };
+    let hash = hash_or_create(ctx, &args[1])?;
+    let current = match hash.get(&args[2]) {
+        Some(value) => Decimal::parse(value)
+            .ok_or_else(|| Error::ValidationError("hash value is not a float".to_string()))?,
+        None => Decimal::ZERO,
+    };
+    let new = current.checked_add(increment).ok_or_else(nan_or_infinity)?;
+    let new = new.to_human_string().into_bytes();
+    hash.insert_keeping_ttl(args[2].clone(), new.clone());
+    Ok(RespVal::BulkString(new))
+}
+
+/// Replies with a random field, or with a count, up to that many distinct fields; a negative
+/// count allows repeated fields and always returns exactly that many.
+fn hrandfield(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let with_values = match args.get(3) {
+        None => false,
+        Some(option) if args.len() == 4 && option.eq_ignore_ascii_case(b"withvalues") => true,
+        Some(_) => return Err(syntax_error()),
+    };
+    let count = args.get(2).map(|count| parse_random_count(count, with_values)).transpose()?;
+    let protocol = ctx.client.protocol;
+    let hash = match (ctx.get_hash(&args[1])?, count) {
+        (Some(hash), _) => hash,
+        (None, Some(_)) => return Ok(RespVal::Array(Vec::new())),
+        (None, None) => return Ok(RespVal::Null),
+    };
+    let count = match count {
+        Some(count) => count,
+        None => {
+            let (field, _) = hash.entry_at(random::random_below(hash.len()));
+            return Ok(RespVal::BulkString(field.clone()));
+        }
+    };
+    let indices = random_indices(hash.len(), count)?;
+    let mut reply = Vec::with_capacity(indices.len());
+    for index in indices {
+        let (field, value) = hash.entry_at(index);
+        let field = RespVal::BulkString(field.clone());
+        if !with_values {
+            reply.push(field);
+            continue;
+        }
+        let value = RespVal::BulkString(value.clone());
+        // RESP3 pairs up fields and values, RESP2 has them alternate in a flat array.
+        match protocol {
+            ProtocolVersion::Resp3 => reply.push(RespVal::Array(vec![field, value])),
+            ProtocolVersion::Resp2 => reply.extend([field, value]),
+        }
+    }
+    Ok(RespVal::Array(reply))
+}
+
+/// Parses `FIELDS numfields field [field ...]` starting at `args[0]`.
+fn parse_fields(args: &[Vec<u8>]) -> Result<&[Vec<u8>]> {
+    if !args.first().is_some_and(|arg| arg.eq_ignore_ascii_case(b"fields")) {
+        return Err(Error::ValidationError(
+            "Mandatory argument FIELDS is missing or not at the right position".to_string(),
+        ));
+    }
+    let numfields = args.get(1).map(|arg| parse_integer(arg)).transpose()?.unwrap_or(0);
+    if numfields <= 0 {
+        return Err(Error::ValidationError("Parameter `numFields` should be greater than 0".to_string()));
+    }
+    let fields = &args[2..];
+    if numfields as u64 != fields.len() as u64 {
+        return Err(Error::ValidationError(
+            "The `numfields` parameter must match the number of arguments".to_string(),
+        ));
+    }
+    Ok(fields)
+}
+
+fn hexpire(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    expire_fields(ctx, args, 1000, true)
+}
+
+fn hpexpire(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    expire_fields(ctx, args, 1, true)
+}
+
+fn hexpireat(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    expire_fields(ctx, args, 1000, false)
+}
+
+fn hpexpireat(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    expire_fields(ctx, args, 1, false)
+}
+
+/// Sets the expiration time of the fields to `args[2]` times `unit` milliseconds, counted from
+/// now if `relative` and from the Unix epoch otherwise, and replies with a status code per
+/// field. A time that has already passed deletes the field right away.
+fn expire_fields(ctx: &mut Context, args: &[Vec<u8>], unit: i64, relative: bool) -> Result<RespVal> {
+    let command_name = String::from_utf8_lossy(&args[0]).to_ascii_lowercase();
+    let key = &args[1];
+    let time = parse_integer(&args[2])?;
+    // The condition is optional; anything else there is reported as FIELDS missing.
+    let (conditions, fields) = match args[3].to_ascii_lowercase().as_slice() {
+        b"nx" | b"xx" | b"gt" | b"lt" => (ExpireConditions::parse(&args[3..4])?, parse_fields(&args[4..])?),
+        _ => (ExpireConditions::default(), parse_fields(&args[3..])?),
+    };
+    if time < 0 {
+        return Err(Error::ValidationError("invalid expire time, must be >= 0".to_string()));
+    }
+    let now = SystemTime::now();
+    let base = if relative { unix_millis(now) } else { 0 };
+    let expiration_time = time
+        .checked_mul(unit)
+        .and_then(|millis| millis.checked_add(base))
+        .filter(|&millis| millis <= MAX_FIELD_EXPIRATION_MILLIS)
+        .and_then(from_unix_millis)
+        .ok_or_else(|| invalid_expire_time(&command_name))?;
+    let hash = match ctx.get_hash_mut(key)? {
+        Some(hash) => hash,
+        None => return Ok(RespVal::Array(vec![RespVal::Integer(NO_SUCH_FIELD); fields.len()])),
+    };
+    let mut codes = Vec::with_capacity(fields.len());
+    for field in fields {
+        let code = if !hash.contains(field) {
+            NO_SUCH_FIELD
+        } else if !conditions.allow(hash.expiration_time(field), expiration_time) {
+            CONDITION_NOT_MET
+        } else if expiration_time <= now {
+            hash.remove(field);
+            DELETED
+        } else {
+            hash.set_expiration_time(field, Some(expiration_time));
+            EXPIRATION_SET
+        };
+        codes.push(RespVal::Integer(code));
+    }
+    if hash.is_empty() {
+        ctx.db.remove(key);
+    } else if hash.has_volatile_fields() {
+        ctx.db.track_volatile_fields(key);
+    }
+    Ok(RespVal::Array(codes))
+}
+
+fn httl(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    // Unlike TTL for keys, Redis rounds the TTL of fields up.
+    field_ttls(ctx, args, |time, now| (unix_millis(time) - unix_millis(now) + 999) / 1000)
+}
+
+fn hpttl(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    field_ttls(ctx, args, |time, now| unix_millis(time) - unix_millis(now))
+}
+
+fn hexpiretime(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    field_ttls(ctx, args, |time, _| unix_millis(time) / 1000)
+}
+
+fn hpexpiretime(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    field_ttls(ctx, args, |time, _| unix_millis(time))
+}
+
+/// Replies with the expiration time of each field converted by `scale`, -1 for fields without
+/// one and -2 for missing fields.
+fn field_ttls(ctx: &mut Context, args: &[Vec<u8>], scale: fn(SystemTime, SystemTime) -> i64) -> Result<RespVal> {
+    let fields = parse_fields(&args[2..])?;
+    let now = SystemTime::now();
+    let hash = ctx.get_hash(&args[1])?;
+    let codes = fields
+        .iter()
+        .map(|field| {
+            let code = match hash {
+                Some(hash) if hash.contains(field) => hash.expiration_time(field).map_or(NO_TTL, |time| scale(time, now)),
+                _ => NO_SUCH_FIELD,
+            };
+            RespVal::Integer(code)
+        })
+        .collect();
+    Ok(RespVal::Array(codes))
+}
+
+fn hpersist(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let fields = parse_fields(&args[2..])?;
+    let hash = match ctx.get_hash_mut(&args[1])? {
+        Some(hash) => hash,
+        None => return Ok(RespVal::Array(vec![RespVal::Integer(NO_SUCH_FIELD); fields.len()])),
+    };
+    let codes = fields
+        .iter()
+        .map(|field| {
+            let code = if hash.expiration_time(field).is_some() {
+                hash.set_expiration_time(field, None);
+                EXPIRATION_SET
+            } else if hash.contains(field) {
+                NO_TTL
+            } else {
+                NO_SUCH_FIELD
+            };
+            RespVal::Integer(code)
+        })
+        .collect();
+    Ok(RespVal::Array(codes))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{new_database, run};
+    use super::*;
+    use crate::client::Client;
+
+    fn bulk(value: &str) -> RespVal {
+        RespVal::BulkString(value.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_set_get_delete() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(run(&map, &mut client, &["hset", "h", "a", "1", "b", "2"]).unwrap(), RespVal::Integer(2));
+        assert_eq!(run(&map, &mut client, &["hset", "h", "a", "3", "c", "4"]).unwrap(), RespVal::Integer(1));
+        assert!(run(&map, &mut client, &["hset", "h", "a"]).is_err());
+        assert_eq!(run(&map, &mut client, &["hsetnx", "h", "a", "5"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["hget", "h", "a"]).unwrap(), bulk("3"));
+        assert_eq!(
+            run(&map, &mut client, &["hmget", "h", "a", "x", "c"]).unwrap(),
+            RespVal::Array(vec![bulk("3"), RespVal::Null, bulk("4")])
+        );
+        assert_eq!(
+            run(&map, &mut client, &["hgetall", "h"]).unwrap(),
+            RespVal::Map(vec![(bulk("a"), bulk("3")), (bulk("b"), bulk("2")), (bulk("c"), bulk("4"))])
+        );
+        assert_eq!(run(&map, &mut client, &["hlen", "h"]).unwrap(), RespVal::Integer(3));
+        assert_eq!(run(&map, &mut client, &["hstrlen", "h", "a"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["hexists", "h", "x"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["hdel", "h", "a", "b", "x"]).unwrap(), RespVal::Integer(2));
+        assert_eq!(run(&map, &mut client, &["hkeys", "h"]).unwrap(), RespVal::Array(vec![bulk("c")]));
+        assert_eq!(run(&map, &mut client, &["hdel", "h", "c"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["exists", "h"]).unwrap(), RespVal::Integer(0));
+        run(&map, &mut client, &["set", "s", "v"]).unwrap();
+        assert!(matches!(run(&map, &mut client, &["hget", "s", "a"]), Err(Error::WrongType)));
+    }
+
+    #[test]
+    fn test_increment() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(run(&map, &mut client, &["hincrby", "h", "n", "5"]).unwrap(), RespVal::Integer(5));
+        assert_eq!(run(&map, &mut client, &["hincrby", "h", "n", "-7"]).unwrap(), RespVal::Integer(-2));
+        assert_eq!(run(&map, &mut client, &["hincrbyfloat", "h", "n", "0.5"]).unwrap(), bulk("-1.5"));
+        assert_eq!(run(&map, &mut client, &["hincrbyfloat", "h", "f", "1e2"]).unwrap(), bulk("100"));
+        assert!(run(&map, &mut client, &["hincrby", "h", "n", "1"]).is_err());
+        assert!(run(&map, &mut client, &["hincrbyfloat", "h", "n", "inf"]).is_err());
+        run(&map, &mut client, &["hset", "h", "max", &i64::MAX.to_string()]).unwrap();
+        assert!(run(&map, &mut client, &["hincrby", "h", "max", "1"]).is_err());
+    }
+
+    #[test]
+    fn test_hrandfield() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(run(&map, &mut client, &["hrandfield", "h"]).unwrap(), RespVal::Null);
+        assert_eq!(run(&map, &mut client, &["hrandfield", "h", "3"]).unwrap(), RespVal::Array(Vec::new()));
+        run(&map, &mut client, &["hset", "h", "a", "1", "b", "2", "c", "3", "d", "4"]).unwrap();
+        let fields = |reply: RespVal| match reply {
+            RespVal::Array(fields) => fields,
+            reply => panic!("unexpected reply {:?}", reply),
+        };
+        let mut distinct = fields(run(&map, &mut client, &["hrandfield", "h", "3"]).unwrap());
+        distinct.sort_by_key(|field| format!("{:?}", field));
+        distinct.dedup();
+        assert_eq!(distinct.len(), 3);
+        assert_eq!(fields(run(&map, &mut client, &["hrandfield", "h", "10"]).unwrap()).len(), 4);
+        assert_eq!(fields(run(&map, &mut client, &["hrandfield", "h", "-10"]).unwrap()).len(), 10);
+        let pairs = fields(run(&map, &mut client, &["hrandfield", "h", "-2", "withvalues"]).unwrap());
+        assert_eq!(pairs.len(), 4);
+        client.protocol = ProtocolVersion::Resp3;
+        let pairs = fields(run(&map, &mut client, &["hrandfield", "h", "2", "withvalues"]).unwrap());
+        assert!(pairs.iter().all(|pair| matches!(pair, RespVal::Array(pair) if pair.len() == 2)));
+        assert!(run(&map, &mut client, &["hrandfield", "h", "1", "values"]).is_err());
+        assert!(run(&map, &mut client, &["hrandfield", "h", "5000000000000000000", "withvalues"]).is_err());
+    }
+
+    #[test]
+    fn test_field_expiration() {
+        let map = new_database();
+        let mut client = Client::new();
+        let codes = |codes: &[i64]| RespVal::Array(codes.iter().map(|code| RespVal::Integer(*code)).collect());
+        assert_eq!(run(&map, &mut client, &["hexpire", "h", "10", "fields", "2", "a", "b"]).unwrap(), codes(&[-2, -2]));
+        run(&map, &mut client, &["hset", "h", "a", "1", "b", "2", "c", "3"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["hexpire", "h", "100", "fields", "2", "a", "x"]).unwrap(), codes(&[1, -2]));
+        assert_eq!(run(&map, &mut client, &["hexpire", "h", "50", "gt", "fields", "2", "a", "b"]).unwrap(), codes(&[0, 0]));
+        assert_eq!(run(&map, &mut client, &["hexpire", "h", "50", "nx", "fields", "2", "a", "b"]).unwrap(), codes(&[0, 1]));
+        assert_eq!(run(&map, &mut client, &["httl", "h", "fields", "4", "a", "b", "c", "x"]).unwrap(), codes(&[100, 50, -1, -2]));
+        run(&map, &mut client, &["hincrby", "h", "a", "1"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["httl", "h", "fields", "1", "a"]).unwrap(), codes(&[100]));
+        assert_eq!(run(&map, &mut client, &["hpersist", "h", "fields", "3", "a", "c", "x"]).unwrap(), codes(&[1, -1, -2]));
+        run(&map, &mut client, &["hset", "h", "b", "2"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["httl", "h", "fields", "1", "b"]).unwrap(), codes(&[-1]));
+        assert_eq!(run(&map, &mut client, &["hpexpireat", "h", "1", "fields", "1", "c"]).unwrap(), codes(&[2]));
+        assert_eq!(run(&map, &mut client, &["hlen", "h"]).unwrap(), RespVal::Integer(2));
+        assert!(run(&map, &mut client, &["hexpire", "h", "10", "fields", "2", "a"]).is_err());
+        assert!(run(&map, &mut client, &["hexpire", "h", "10", "fields", "0", "a"]).is_err());
+        assert!(run(&map, &mut client, &["hexpire", "h", "10", "xx", "nx", "a"]).is_err());
+        assert!(run(&map, &mut client, &["hexpire", "h", "-1", "fields", "1", "a"]).is_err());
+        assert!(run(&map, &mut client, &["hexpireat", "h", &i64::MAX.to_string(), "fields", "1", "a"]).is_err());
+    }
+
+    #[test]
+    fn test_expired_fields_are_deleted() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["hset", "h", "a", "1", "b", "2"]).unwrap();
+        run(&map, &mut client, &["hset", "g", "a", "1"]).unwrap();
+        let expire_soon = |map, client: &mut Client, key, field| {
+            run(map, client, &["hpexpire", key, "1", "fields", "1", field]).unwrap();
+        };
+        expire_soon(&map, &mut client, "h", "a");
+        expire_soon(&map, &mut client, "g", "a");
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(run(&map, &mut client, &["hgetall", "h"]).unwrap(), RespVal::Map(vec![(bulk("b"), bulk("2"))]));
+        map.lock().unwrap().active_expire_cycle(std::time::Duration::from_secs(60));
+        assert_eq!(run(&map, &mut client, &["dbsize"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(map.lock().unwrap().stats.expired_subkeys, 2);
+    }
+}
@@ -0,0 +1,697 @@
+use super::{ok, parse_integer, parse_timeout, resolve_range, syntax_error, wrong_arity, CommandFlag::*, CommandSpec, Context};
+use crate::error::{Error, Result};
+use crate::resp::RespVal;
+use crate::{Data, Value};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("lpush", -3, lpush)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("list", "1.0.0", "Prepends one or more elements to a list. Creates the key if it doesn't exist."),
+    CommandSpec::new("rpush", -3, rpush)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("list", "1.0.0", "Appends one or more elements to a list. Creates the key if it doesn't exist."),
+    CommandSpec::new("lpushx", -3, lpushx)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("list", "2.2.0", "Prepends one or more elements to a list only when the list exists."),
+    CommandSpec::new("rpushx", -3, rpushx)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("list", "2.2.0", "Appends an element to a list only when the list exists."),
+    CommandSpec::new("lpop", -2, lpop)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("list", "1.0.0", "Returns the first elements in a list after removing it. Deletes the list if the last element was popped."),
+    CommandSpec::new("rpop", -2, rpop)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("list", "1.0.0", "Returns and removes the last elements of a list. Deletes the list if the last element was popped."),
+    CommandSpec::new("lrange", 4, lrange)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("list", "1.0.0", "Returns a range of elements from a list."),
+    CommandSpec::new("llen", 2, llen)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("list", "1.0.0", "Returns the length of a list."),
+    CommandSpec::new("lindex", 3, lindex)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("list", "1.0.0", "Returns an element from a list by its index."),
+    CommandSpec::new("lset", 4, lset)
+        .flags(&[Write, DenyOom])
+        .keys(1, 1, 1)
+        .docs("list", "1.0.0", "Sets the value of an element in a list by its index."),
+    CommandSpec::new("lrem", 4, lrem)
+        .flags(&[Write])
+        .keys(1, 1, 1)
+        .docs("list", "1.0.0", "Removes elements from a list. Deletes the list if the last element was removed."),
+    CommandSpec::new("ltrim", 4, ltrim)
+        .flags(&[Write])
+        .keys(1, 1, 1)
+        .docs("list", "1.0.0", "Removes elements from both ends a list. Deletes the list if all elements were trimmed."),
+    CommandSpec::new("linsert", 5, linsert)
+        .flags(&[Write, DenyOom])
+        .keys(1, 1, 1)
+        .docs("list", "2.2.0", "Inserts an element before or after another element in a list."),
+    CommandSpec::new("lpos", -3, lpos)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("list", "6.0.6", "Returns the index of matching elements in a list."),
+    CommandSpec::new("lmove", 5, lmove)
+        .flags(&[Write, DenyOom])
+        .keys(1, 2, 1)
+        .docs("list", "6.2.0", "Returns an element after popping it from one list and pushing it to another. Deletes the list if the last element was moved."),
+    CommandSpec::new("rpoplpush", 3, rpoplpush)
+        .flags(&[Write, DenyOom])
+        .keys(1, 2, 1)
+        .docs("list", "1.2.0", "Returns the last element of a list after removing and pushing it to another list. Deletes the list if the last element was popped."),
+    CommandSpec::new("lmpop", -4, lmpop)
+        .flags(&[Write, MovableKeys])
+        .keys(0, 0, 0)
+        .docs("list", "7.0.0", "Returns multiple elements from a list after removing them. Deletes the list if the last element was popped."),
+    CommandSpec::new("blpop", -3, blpop)
+        .flags(&[Write, Blocking])
+        .keys(1, -2, 1)
+        .docs("list", "2.0.0", "Removes and returns the first element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped."),
+    CommandSpec::new("brpop", -3, brpop)
+        .flags(&[Write, Blocking])
+        .keys(1, -2, 1)
+        .docs("list", "2.0.0", "Removes and returns the last element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped."),
+    CommandSpec::new("blmove", 6, blmove)
+        .flags(&[Write, DenyOom, Blocking])
+        .keys(1, 2, 1)
+        .docs("list", "6.2.0", "Pops an element from a list, pushes it to another list and returns it. Blocks until an element is available otherwise. Deletes the list if the last element was moved."),
+    CommandSpec::new("brpoplpush", 4, brpoplpush)
+        .flags(&[Write, DenyOom, Blocking])
+        .keys(1, 2, 1)
+        .docs("list", "2.2.0", "Pops an element from a list, pushes it to another list and returns it. Block until an element is available otherwise. Deletes the list if the last element was popped."),
+    CommandSpec::new("blmpop", -5, blmpop)
+        .flags(&[Write, Blocking, MovableKeys])
+        .keys(0, 0, 0)
+        .docs("list", "7.0.0", "Pops the first element from one of multiple lists. Blocks until an element is available otherwise. Deletes the list if the last element was popped."),
+];
+
+/// One of the two ends of a list, as in the `LEFT` and `RIGHT` arguments of `LMOVE`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum End {
+    Left,
+    Right,
+}
+
+impl End {
+    pub fn parse(arg: &[u8]) -> Result<End> {
+        match arg.to_ascii_lowercase().as_slice() {
+            b"left" => Ok(End::Left),
+            b"right" => Ok(End::Right),
+            _ => Err(syntax_error()),
+        }
+    }
+}
+
+fn pop_end(list: &mut VecDeque<Vec<u8>>, end: End) -> Option<Vec<u8>> {
+    match end {
+        End::Left => list.pop_front(),
+        End::Right => list.pop_back(),
+    }
+}
+
+fn push_end(list: &mut VecDeque<Vec<u8>>, end: End, element: Vec<u8>) {
+    match end {
+        End::Left => list.push_front(element),
+        End::Right => list.push_back(element),
+    }
+}
+
+/// Deletes the list at `key` if it is empty: like in Redis, a key never holds an empty list.
+fn remove_if_empty(ctx: &mut Context, key: &[u8]) {
+    let is_empty = matches!(ctx.db.get(key), Some(Value { data: Data::List(list), .. }) if list.is_empty());
+    if is_empty {
+        ctx.db.remove(key);
+    }
+}
+
+/// Pushes `elements` one by one to the list at `key`, creating it if needed, and returns the
+/// new length.
+fn push_elements(ctx: &mut Context, key: &[u8], end: End, elements: impl IntoIterator<Item = Vec<u8>>) -> Result<usize> {
+    if ctx.get_list(key)?.is_none() {
+        ctx.db.insert(key.to_vec(), Value::new(Data::List(VecDeque::new())));
+    }
+    let list = ctx.get_list_mut(key)?.expect("the key was just created");
+    for element in elements {
+        push_end(list, end, element);
+    }
+    Ok(list.len())
+}
+
+fn lpush(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    push(ctx, args, End::Left, false)
+}
+
+fn rpush(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    push(ctx, args, End::Right, false)
+}
+
+fn lpushx(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    push(ctx, args, End::Left, true)
+}
+
+fn rpushx(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    push(ctx, args, End::Right, true)
+}
+
+fn push(ctx: &mut Context, args: &[Vec<u8>], end: End, only_existing: bool) -> Result<RespVal> {
+    let key = &args[1];
+    if only_existing && ctx.get_list(key)?.is_none() {
+        return Ok(RespVal::Integer(0));
+    }
+    let length = push_elements(ctx, key, end, args[2..].iter().cloned())?;
+    Ok(RespVal::Integer(length as i64))
+}
+
+fn lpop(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    pop(ctx, args, End::Left)
+}
+
+fn rpop(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    pop(ctx, args, End::Right)
+}
+
+/// Pops a single element, or with a count argument an array of up to that many elements.
+fn pop(ctx: &mut Context, args: &[Vec<u8>], end: End) -> Result<RespVal> {
+    if args.len() > 3 {
+        return Err(wrong_arity(&String::from_utf8_lossy(&args[0]).to_ascii_lowercase()));
+    }
+    let key = &args[1];
+    let count = args.get(2).map(|count| parse_positive(count)).transpose()?;
+    let list = match ctx.get_list_mut(key)? {
+        Some(list) => list,
+        None if count.is_some() => return Ok(RespVal::NullArray),
+        None => return Ok(RespVal::Null),
+    };
+    let reply = match count {
+        Some(count) => RespVal::Array(
+            (0..count)
+                .map_while(|_| pop_end(list, end))
+                .map(RespVal::BulkString)
+                .collect(),
+        ),
+        None => RespVal::BulkString(pop_end(list, end).expect("lists are never empty")),
+    };
+    remove_if_empty(ctx, key);
+    Ok(reply)
+}
+
+fn parse_positive(arg: &[u8]) -> Result<usize> {
+    let count = parse_integer(arg)?;
+    usize::try_from(count).map_err(|_| Error::ValidationError("value is out of range, must be positive".to_string()))
+}
+
+fn lrange(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let start = parse_integer(&args[2])?;
+    let stop = parse_integer(&args[3])?;
+    let list = match ctx.get_list(&args[1])? {
+        Some(list) => list,
+        None => return Ok(RespVal::Array(Vec::new())),
+    };
+    let elements = match resolve_range(start, stop, list.len()) {
+        Some((start, stop)) => list.range(start..=stop).cloned().map(RespVal::BulkString).collect(),
+        None => Vec::new(),
+    };
+    Ok(RespVal::Array(elements))
+}
+
+fn llen(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let length = ctx.get_list(&args[1])?.map_or(0, VecDeque::len);
+    Ok(RespVal::Integer(length as i64))
+}
+
+/// Resolves an index that counts from the end if negative; `None` if it is out of range.
+fn resolve_index(index: i64, length: usize) -> Option<usize> {
+    let index = if index < 0 { index + length as i64 } else { index };
+    usize::try_from(index).ok().filter(|&index| index < length)
+}
+
+fn lindex(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let index = parse_integer(&args[2])?;
+    let element = ctx
+        .get_list(&args[1])?
+        .and_then(|list| list.get(resolve_index(index, list.len())?));
+    Ok(element.map_or(RespVal::Null, |element| RespVal::BulkString(element.clone())))
+}
+
+fn lset(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let index = parse_integer(&args[2])?;
+    let list = ctx
+        .get_list_mut(&args[1])?
+        .ok_or_else(|| Error::ValidationError("no such key".to_string()))?;
+    let index = resolve_index(index, list.len())
+        .ok_or_else(|| Error::ValidationError("index out of range".to_string()))?;
+    list[index] = args[3].clone();
+    Ok(ok())
+}
+
+/// Removes up to `count` occurrences of an element, from the head if `count` is positive and
+/// from the tail if it is negative; 0 removes all of them.
+fn lrem(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    let count = parse_integer(&args[2])?;
+    let element = &args[3];
+    let list = match ctx.get_list_mut(key)? {
+        Some(list) => list,
+        None => return Ok(RespVal::Integer(0)),
+    };
+    let limit = if count == 0 { u64::MAX } else { count.unsigned_abs() };
+    let mut removed = 0;
+    let mut keep = |existing: &Vec<u8>| {
+        if removed < limit && existing == element {
+            removed += 1;
+            false
+        } else {
+            true
+        }
+    };
+    if count < 0 {
+        let mut reversed: VecDeque<Vec<u8>> = list.drain(..).rev().collect();
+        reversed.retain(&mut keep);
+        list.extend(reversed.into_iter().rev());
+    } else {
+        list.retain(keep);
+    }
+    remove_if_empty(ctx, key);
+    Ok(RespVal::Integer(removed as i64))
+}
+
+fn ltrim(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    let start = parse_integer(&args[2])?;
+    let stop = parse_integer(&args[3])?;
+    let list = match ctx.get_list_mut(key)? {
+        Some(list) => list,
+        None => return Ok(ok()),
+    };
+    match resolve_range(start, stop, list.len()) {
+        Some((start, stop)) => {
+            list.truncate(stop + 1);
+            list.drain(..start);
+        }
+        None => list.clear(),
+    }
+    remove_if_empty(ctx, key);
+    Ok(ok())
+}
+
+fn linsert(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let after = match args[2].to_ascii_lowercase().as_slice() {
+        b"before" => false,
+        b"after" => true,
+        _ => return Err(syntax_error()),
+    };
+    let (pivot, element) = (&args[3], &args[4]);
+    let list = match ctx.get_list_mut(&args[1])? {
+        Some(list) => list,
+        None => return Ok(RespVal::Integer(0)),
+    };
+    match list.iter().position(|existing| existing == pivot) {
+        Some(position) => {
+            list.insert(position + after as usize, element.clone());
+            Ok(RespVal::Integer(list.len() as i64))
+        }
+        None => Ok(RespVal::Integer(-1)),
+    }
+}
+
+/// Finds the positions of an element. RANK skips the first matches, or searches from the tail
+/// if negative; COUNT returns that many matches as an array, all of them if 0; MAXLEN limits
+/// how many elements are compared.
+fn lpos(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let element = &args[2];
+    let mut rank = 1;
+    let mut count = None;
+    let mut max_length = 0;
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        let value = parse_integer(options.next().ok_or_else(syntax_error)?)?;
+        match option.to_ascii_lowercase().as_slice() {
+            b"rank" => {
+                if value == i64::MIN {
+                    return Err(Error::ValidationError(format!(
+                        "value is out of range, value must between {} and {}",
+                        -i64::MAX,
+                        i64::MAX
+                    )));
+                }
+                if value == 0 {
+                    return Err(Error::ValidationError(
+                        "RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the last match".to_string(),
+                    ));
+                }
+                rank = value;
+            }
+            b"count" => {
+                count = Some(usize::try_from(value).map_err(|_| {
+                    Error::ValidationError("COUNT can't be negative".to_string())
+                })?);
+            }
+            b"maxlen" => {
+                max_length = usize::try_from(value).map_err(|_| {
+                    Error::ValidationError("MAXLEN can't be negative".to_string())
+                })?;
+            }
+            _ => return Err(syntax_error()),
+        }
+    }
+    let list = match ctx.get_list(&args[1])? {
+        Some(list) => list,
+        None if count.is_some() => return Ok(RespVal::Array(Vec::new())),
+        None => return Ok(RespVal::Null),
+    };
+    let wanted = match count {
+        None => 1,
+        Some(0) => usize::MAX,
+        Some(count) => count,
+    };
+    let compared = if max_length == 0 { list.len() } else { max_length.min(list.len()) };
+    let positions: Box<dyn Iterator<Item = usize>> = if rank > 0 {
+        Box::new(0..compared)
+    } else {
+        Box::new((list.len() - compared..list.len()).rev())
+    };
+    let matches: Vec<RespVal> = positions
+        .filter(|&position| list[position] == *element)
+        .skip((rank.unsigned_abs() - 1) as usize)
+        .take(wanted)
+        .map(|position| RespVal::Integer(position as i64))
+        .collect();
+    match count {
+        Some(_) => Ok(RespVal::Array(matches)),
+        None => Ok(matches.into_iter().next().unwrap_or(RespVal::Null)),
+    }
+}
+
+fn lmove(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let from = End::parse(&args[3])?;
+    let to = End::parse(&args[4])?;
+    move_element(ctx, &args[1], &args[2], from, to)
+}
+
+fn rpoplpush(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    move_element(ctx, &args[1], &args[2], End::Right, End::Left)
+}
+
+/// Pops an element from `from` of the list at `source` and pushes it to `to` of the list at
+/// `destination`, which may be the same list.
+fn move_element(ctx: &mut Context, source: &[u8], destination: &[u8], from: End, to: End) -> Result<RespVal> {
+    if ctx.get_list(source)?.is_none() {
+        return Ok(RespVal::Null);
+    }
+    // Check the type of the destination before anything is popped.
+    ctx.get_list(destination)?;
+    let list = ctx.get_list_mut(source)?.expect("the source exists");
+    let element = pop_end(list, from).expect("lists are never empty");
+    // The source is only deleted after the push, which keeps a single element list that is
+    // rotated onto itself alive, together with its TTL.
+    push_elements(ctx, destination, to, [element.clone()])?;
+    remove_if_empty(ctx, source);
+    Ok(RespVal::BulkString(element))
+}
+
+fn blpop(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    blocking_pop(ctx, args, End::Left)
+}
+
+fn brpop(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    blocking_pop(ctx, args, End::Right)
+}
+
+/// Pops an element from the first non-empty list of the keys, replying with the key and the
+/// element, or waits for one of the lists to be pushed to.
+fn blocking_pop(ctx: &mut Context, args: &[Vec<u8>], end: End) -> Result<RespVal> {
+    let (keys, timeout) = args[1..].split_at(args.len() - 2);
+    let timeout = parse_timeout(&timeout[0])?;
+    for key in keys {
+        if let Some(list) = ctx.get_list_mut(key)? {
+            let element = pop_end(list, end).expect("lists are never empty");
+            remove_if_empty(ctx, key);
+            return Ok(RespVal::Array(vec![RespVal::BulkString(key.clone()), RespVal::BulkString(element)]));
+        }
+    }
+    ctx.block_on(keys, timeout);
+    Ok(RespVal::NullArray)
+}
+
+fn blmove(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let from = End::parse(&args[3])?;
+    let to = End::parse(&args[4])?;
+    let timeout = parse_timeout(&args[5])?;
+    blocking_move(ctx, &args[1], &args[2], from, to, timeout)
+}
+
+fn brpoplpush(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let timeout = parse_timeout(&args[3])?;
+    blocking_move(ctx, &args[1], &args[2], End::Right, End::Left, timeout)
+}
+
+fn blocking_move(
+    ctx: &mut Context,
+    source: &[u8],
+    destination: &[u8],
+    from: End,
+    to: End,
+    timeout: Option<Duration>,
+) -> Result<RespVal> {
+    if ctx.get_list(source)?.is_none() {
+        ctx.block_on(&[source.to_vec()], timeout);
+        return Ok(RespVal::Null);
+    }
+    move_element(ctx, source, destination, from, to)
+}
+
+/// The arguments of `LMPOP` and `BLMPOP` after the timeout: `numkeys key [key ...] LEFT|RIGHT
+/// [COUNT count]`.
+struct MultiPop<'a> {
+    keys: &'a [Vec<u8>],
+    end: End,
+    count: usize,
+}
+
+impl MultiPop<'_> {
+    fn parse(args: &[Vec<u8>]) -> Result<MultiPop<'_>> {
+        let numkeys = parse_integer(&args[0])?;
+        if numkeys <= 0 {
+            return Err(Error::ValidationError("numkeys should be greater than 0".to_string()));
+        }
+        // The keys have to be followed by at least the end to pop from.
+        let numkeys = usize::try_from(numkeys)
+            .ok()
+            .filter(|&numkeys| numkeys < args.len() - 1)
+            .ok_or_else(syntax_error)?;
+        let keys = &args[1..=numkeys];
+        let end = End::parse(&args[numkeys + 1])?;
+        let count = match &args[numkeys + 2..] {
+            [] => 1,
+            [option, count] if option.eq_ignore_ascii_case(b"count") => usize::try_from(parse_integer(count)?)
+                .ok()
+                .filter(|&count| count > 0)
+                .ok_or_else(|| Error::ValidationError("count should be greater than 0".to_string()))?,
+            _ => return Err(syntax_error()),
+        };
+        Ok(MultiPop { keys, end, count })
+    }
+
+    /// Pops from the first non-empty list, replying with its key and the popped elements.
+    fn pop(&self, ctx: &mut Context) -> Result<Option<RespVal>> {
+        for key in self.keys {
+            if let Some(list) = ctx.get_list_mut(key)? {
+                let elements = (0..self.count)
+                    .map_while(|_| pop_end(list, self.end))
+                    .map(RespVal::BulkString)
+                    .collect();
+                remove_if_empty(ctx, key);
+                return Ok(Some(RespVal::Array(vec![RespVal::BulkString(key.clone()), RespVal::Array(elements)])));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn lmpop(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let reply = MultiPop::parse(&args[1..])?.pop(ctx)?;
+    Ok(reply.unwrap_or(RespVal::NullArray))
+}
+
+fn blmpop(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let timeout = parse_timeout(&args[1])?;
+    let multi_pop = MultiPop::parse(&args[2..])?;
+    match multi_pop.pop(ctx)? {
+        Some(reply) => Ok(reply),
+        None => {
+            ctx.block_on(multi_pop.keys, timeout);
+            Ok(RespVal::NullArray)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{new_database, run, run_blocked};
+    use super::*;
+    use crate::client::Client;
+
+    fn bulk_array(elements: &[&str]) -> RespVal {
+        RespVal::Array(elements.iter().map(|element| RespVal::BulkString(element.as_bytes().to_vec())).collect())
+    }
+
+    fn integers(values: &[i64]) -> RespVal {
+        RespVal::Array(values.iter().map(|value| RespVal::Integer(*value)).collect())
+    }
+
+    #[test]
+    fn test_push_pop_and_range() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(run(&map, &mut client, &["rpush", "l", "b", "c"]).unwrap(), RespVal::Integer(2));
+        assert_eq!(run(&map, &mut client, &["lpush", "l", "a", "z"]).unwrap(), RespVal::Integer(4));
+        assert_eq!(run(&map, &mut client, &["lpushx", "missing", "a"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["lrange", "l", "0", "-1"]).unwrap(), bulk_array(&["z", "a", "b", "c"]));
+        assert_eq!(run(&map, &mut client, &["lrange", "l", "-100", "1"]).unwrap(), bulk_array(&["z", "a"]));
+        assert_eq!(run(&map, &mut client, &["lrange", "l", "2", "100"]).unwrap(), bulk_array(&["b", "c"]));
+        assert_eq!(run(&map, &mut client, &["lrange", "l", "3", "1"]).unwrap(), bulk_array(&[]));
+        assert_eq!(run(&map, &mut client, &["lindex", "l", "-1"]).unwrap(), RespVal::BulkString(b"c".to_vec()));
+        assert_eq!(run(&map, &mut client, &["lindex", "l", "4"]).unwrap(), RespVal::Null);
+        assert_eq!(run(&map, &mut client, &["lpop", "l"]).unwrap(), RespVal::BulkString(b"z".to_vec()));
+        assert_eq!(run(&map, &mut client, &["rpop", "l", "2"]).unwrap(), bulk_array(&["c", "b"]));
+        assert_eq!(run(&map, &mut client, &["rpop", "l", "0"]).unwrap(), bulk_array(&[]));
+        assert_eq!(run(&map, &mut client, &["lpop", "l", "5"]).unwrap(), bulk_array(&["a"]));
+        assert_eq!(run(&map, &mut client, &["exists", "l"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["lpop", "l"]).unwrap(), RespVal::Null);
+        assert_eq!(run(&map, &mut client, &["lpop", "l", "1"]).unwrap(), RespVal::NullArray);
+        assert!(run(&map, &mut client, &["lpop", "l", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["set", "s", "v"]).unwrap();
+        run(&map, &mut client, &["rpush", "l", "a"]).unwrap();
+        let wrong_type = |result: Result<RespVal>| matches!(result, Err(Error::WrongType));
+        assert!(wrong_type(run(&map, &mut client, &["lpush", "s", "a"])));
+        assert!(wrong_type(run(&map, &mut client, &["llen", "s"])));
+        assert!(wrong_type(run(&map, &mut client, &["get", "l"])));
+        assert!(wrong_type(run(&map, &mut client, &["incr", "l"])));
+        assert!(wrong_type(run(&map, &mut client, &["lmove", "l", "s", "left", "left"])));
+        assert_eq!(run(&map, &mut client, &["llen", "l"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["mget", "s", "l"]).unwrap(), RespVal::Array(vec![RespVal::BulkString(b"v".to_vec()), RespVal::Null]));
+        assert_eq!(run(&map, &mut client, &["type", "l"]).unwrap(), RespVal::SimpleString(b"list".to_vec()));
+        assert_eq!(run(&map, &mut client, &["set", "l", "v"]).unwrap(), ok());
+    }
+
+    #[test]
+    fn test_modify_in_place() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["rpush", "l", "a", "b", "a", "c", "a"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["lrem", "l", "-2", "a"]).unwrap(), RespVal::Integer(2));
+        assert_eq!(run(&map, &mut client, &["lrange", "l", "0", "-1"]).unwrap(), bulk_array(&["a", "b", "c"]));
+        assert_eq!(run(&map, &mut client, &["linsert", "l", "after", "b", "x"]).unwrap(), RespVal::Integer(4));
+        assert_eq!(run(&map, &mut client, &["linsert", "l", "BEFORE", "a", "y"]).unwrap(), RespVal::Integer(5));
+        assert_eq!(run(&map, &mut client, &["linsert", "l", "before", "nope", "y"]).unwrap(), RespVal::Integer(-1));
+        assert_eq!(run(&map, &mut client, &["lset", "l", "-1", "z"]).unwrap(), ok());
+        assert!(run(&map, &mut client, &["lset", "l", "5", "z"]).is_err());
+        assert!(run(&map, &mut client, &["lset", "missing", "0", "z"]).is_err());
+        assert_eq!(run(&map, &mut client, &["lrange", "l", "0", "-1"]).unwrap(), bulk_array(&["y", "a", "b", "x", "z"]));
+        assert_eq!(run(&map, &mut client, &["ltrim", "l", "1", "-2"]).unwrap(), ok());
+        assert_eq!(run(&map, &mut client, &["lrange", "l", "0", "-1"]).unwrap(), bulk_array(&["a", "b", "x"]));
+        assert_eq!(run(&map, &mut client, &["ltrim", "l", "5", "10"]).unwrap(), ok());
+        assert_eq!(run(&map, &mut client, &["exists", "l"]).unwrap(), RespVal::Integer(0));
+    }
+
+    #[test]
+    fn test_lpos() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["rpush", "l", "a", "b", "c", "1", "2", "3", "c", "c"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["lpos", "l", "c"]).unwrap(), RespVal::Integer(2));
+        assert_eq!(run(&map, &mut client, &["lpos", "l", "c", "rank", "2"]).unwrap(), RespVal::Integer(6));
+        assert_eq!(run(&map, &mut client, &["lpos", "l", "c", "rank", "-1"]).unwrap(), RespVal::Integer(7));
+        assert_eq!(run(&map, &mut client, &["lpos", "l", "c", "count", "2"]).unwrap(), integers(&[2, 6]));
+        assert_eq!(run(&map, &mut client, &["lpos", "l", "c", "count", "0"]).unwrap(), integers(&[2, 6, 7]));
+        assert_eq!(run(&map, &mut client, &["lpos", "l", "c", "rank", "-2", "count", "0"]).unwrap(), integers(&[6, 2]));
+        assert_eq!(run(&map, &mut client, &["lpos", "l", "c", "count", "0", "maxlen", "3"]).unwrap(), integers(&[2]));
+        assert_eq!(run(&map, &mut client, &["lpos", "l", "c", "rank", "-1", "maxlen", "1", "count", "0"]).unwrap(), integers(&[7]));
+        assert_eq!(run(&map, &mut client, &["lpos", "l", "x"]).unwrap(), RespVal::Null);
+        assert_eq!(run(&map, &mut client, &["lpos", "missing", "x", "count", "1"]).unwrap(), integers(&[]));
+        assert!(run(&map, &mut client, &["lpos", "l", "c", "rank", "0"]).is_err());
+        assert!(run(&map, &mut client, &["lpos", "l", "c", "count", "-1"]).is_err());
+        assert!(run(&map, &mut client, &["lpos", "l", "c", "maxlen"]).is_err());
+    }
+
+    #[test]
+    fn test_lmove() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["rpush", "a", "1", "2"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["lmove", "a", "b", "left", "right"]).unwrap(), RespVal::BulkString(b"1".to_vec()));
+        assert_eq!(run(&map, &mut client, &["rpoplpush", "a", "b"]).unwrap(), RespVal::BulkString(b"2".to_vec()));
+        assert_eq!(run(&map, &mut client, &["lrange", "b", "0", "-1"]).unwrap(), bulk_array(&["2", "1"]));
+        assert_eq!(run(&map, &mut client, &["lmove", "a", "b", "left", "right"]).unwrap(), RespVal::Null);
+        run(&map, &mut client, &["rpush", "c", "x"]).unwrap();
+        run(&map, &mut client, &["expire", "c", "100"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["lmove", "c", "c", "left", "right"]).unwrap(), RespVal::BulkString(b"x".to_vec()));
+        assert_eq!(run(&map, &mut client, &["ttl", "c"]).unwrap(), RespVal::Integer(100));
+        assert!(run(&map, &mut client, &["lmove", "b", "c", "up", "right"]).is_err());
+    }
+
+    #[test]
+    fn test_blocking_pop() {
+        let map = new_database();
+        let (mut first, mut second, mut pusher) = (Client::new(), Client::new(), Client::new());
+        run(&map, &mut pusher, &["rpush", "ready", "x"]).unwrap();
+        assert_eq!(run(&map, &mut first, &["blpop", "empty", "ready", "0"]).unwrap(), bulk_array(&["ready", "x"]));
+        let mut first_blocked = run_blocked(&map, &mut first, &["blpop", "a", "b", "0"]);
+        let mut second_blocked = run_blocked(&map, &mut second, &["brpop", "b", "0.5"]);
+        assert_eq!(first_blocked.timeout, None);
+        assert_eq!(second_blocked.timeout, Some(Duration::from_millis(500)));
+        assert_eq!(second_blocked.timeout_reply, RespVal::NullArray);
+        // The client that blocked first is served first.
+        assert_eq!(run(&map, &mut pusher, &["rpush", "b", "1", "2", "3"]).unwrap(), RespVal::Integer(3));
+        assert_eq!(first_blocked.receiver.try_recv().unwrap().unwrap(), bulk_array(&["b", "1"]));
+        assert_eq!(second_blocked.receiver.try_recv().unwrap().unwrap(), bulk_array(&["b", "3"]));
+        assert_eq!(run(&map, &mut pusher, &["lrange", "b", "0", "-1"]).unwrap(), bulk_array(&["2"]));
+        assert_eq!(map.lock().unwrap().blocked.count(), 0);
+        assert!(run(&map, &mut first, &["blpop", "a", "-1"]).is_err());
+        assert!(run(&map, &mut first, &["blpop", "a", "soon"]).is_err());
+    }
+
+    #[test]
+    fn test_blocked_client_gets_wrong_type() {
+        let map = new_database();
+        let (mut waiter, mut writer) = (Client::new(), Client::new());
+        let mut blocked = run_blocked(&map, &mut waiter, &["blmove", "source", "destination", "left", "left", "0"]);
+        run(&map, &mut writer, &["set", "source", "string"]).unwrap();
+        assert!(matches!(blocked.receiver.try_recv().unwrap(), Err(Error::WrongType)));
+    }
+
+    #[test]
+    fn test_lmpop() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["rpush", "b", "1", "2", "3"]).unwrap();
+        let popped = RespVal::Array(vec![RespVal::BulkString(b"b".to_vec()), bulk_array(&["3", "2"])]);
+        assert_eq!(run(&map, &mut client, &["lmpop", "2", "a", "b", "right", "count", "2"]).unwrap(), popped);
+        assert_eq!(run(&map, &mut client, &["lmpop", "1", "a", "left"]).unwrap(), RespVal::NullArray);
+        assert!(run(&map, &mut client, &["lmpop", "0", "a", "left"]).is_err());
+        assert!(run(&map, &mut client, &["lmpop", "2", "a", "left"]).is_err());
+        assert!(run(&map, &mut client, &["lmpop", "1", "a", "left", "count", "0"]).is_err());
+        let mut blocked = run_blocked(&map, &mut client, &["blmpop", "0", "1", "a", "left", "count", "5"]);
+        let mut other = Client::new();
+        run(&map, &mut other, &["lpush", "a", "x", "y"]).unwrap();
+        let popped = RespVal::Array(vec![RespVal::BulkString(b"a".to_vec()), bulk_array(&["y", "x"])]);
+        assert_eq!(blocked.receiver.try_recv().unwrap().unwrap(), popped);
+    }
+}
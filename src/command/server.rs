@@ -0,0 +1,234 @@
+use super::{all_commands, lookup, CommandFlag, CommandFlag::*, CommandSpec, Context};
+use crate::error::{Error, Result};
+use crate::resp::RespVal;
+use crate::REDIS_VERSION;
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::container("config", CONFIG_SUBCOMMANDS)
+        .docs("server", "2.0.0", "A container for server configuration commands."),
+    CommandSpec::new("info", -1, info)
+        .flags(&[Loading, Stale])
+        .docs("server", "1.0.0", "Returns information and statistics about the server."),
+    CommandSpec::new("command", -1, command)
+        .flags(&[Loading, Stale])
+        .docs("server", "2.8.13", "Returns detailed information about all commands.")
+        .subcommands(COMMAND_SUBCOMMANDS),
+];
+
+const CONFIG_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("config|get", 3, config_get)
+        .flags(&[Admin, NoScript, Loading, Stale])
+        .docs("server", "2.0.0", "Returns the effective values of configuration parameters."),
+];
+
+const COMMAND_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("command|count", 2, command_count)
+        .flags(&[Loading, Stale])
+        .docs("server", "2.8.13", "Returns a count of commands."),
+    CommandSpec::new("command|info", -2, command_info)
+        .flags(&[Loading, Stale])
+        .docs("server", "2.8.13", "Returns information about one, multiple or all commands."),
+    CommandSpec::new("command|docs", -2, command_docs)
+        .flags(&[Loading, Stale])
+        .docs("server", "7.0.0", "Returns documentary information about one, multiple or all commands."),
+];
+
+fn config_get(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[2];
+    let val = match key.to_ascii_lowercase().as_slice() {
+        b"dir" => &ctx.config.dir,
+        b"dbfilename" => &ctx.config.dbfilename,
+        _ => return Err(Error::ValidationError("Wrong argument to CONFIG GET command".to_string())),
+    };
+    let val = RespVal::BulkString(val.to_string_lossy().as_bytes().to_vec());
+    Ok(RespVal::Map(vec![(RespVal::BulkString(key.clone()), val)]))
+}
+
+/// Sections of the `INFO` reply, in the order they are printed.
+const INFO_SECTIONS: &[&str] = &["server", "clients", "stats", "keyspace"];
+
+fn info(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let requested: Vec<Vec<u8>> = args[1..].iter().map(|arg| arg.to_ascii_lowercase()).collect();
+    let show_all = requested.is_empty()
+        || requested
+            .iter()
+            .any(|section| matches!(section.as_slice(), b"all" | b"default" | b"everything"));
+    let sections: Vec<String> = INFO_SECTIONS
+        .iter()
+        .filter(|section| show_all || requested.iter().any(|name| name == section.as_bytes()))
+        .map(|section| info_section(ctx, section))
+        .collect();
+    Ok(RespVal::VerbatimString(*b"txt", sections.join("\r\n").into_bytes()))
+}
+
+fn info_section(ctx: &Context, section: &str) -> String {
+    let (title, fields) = match section {
+        "server" => ("Server", vec![
+            format!("redis_version:{}", REDIS_VERSION),
+            "redis_mode:standalone".to_string(),
+        ]),
+        "clients" => ("Clients", vec![format!("blocked_clients:{}", ctx.db.blocked.count())]),
+        "stats" => ("Stats", vec![
+            format!("expired_keys:{}", ctx.db.stats.expired_keys),
+            format!("expired_subkeys:{}", ctx.db.stats.expired_subkeys),
+            format!("expired_stale_perc:{:.2}", ctx.db.stats.expired_stale_perc * 100.0),
+            format!("expired_time_cap_reached_count:{}", ctx.db.stats.expired_time_cap_reached_count),
+        ]),
+        _ => {
+            let mut fields = Vec::new();
+            if !ctx.db.is_empty() {
+                fields.push(format!("db0:keys={},expires={},avg_ttl=0", ctx.db.len(), ctx.db.volatile_len()));
+            }
+            ("Keyspace", fields)
+        }
+    };
+    let mut text = format!("# {}\r\n", title);
+    for field in fields {
+        text.push_str(&field);
+        text.push_str("\r\n");
+    }
+    text
+}
+
+fn command(_ctx: &mut Context, _args: &[Vec<u8>]) -> Result<RespVal> {
+    Ok(RespVal::Array(all_commands().map(command_info_reply).collect()))
+}
+
+fn command_count(_ctx: &mut Context, _args: &[Vec<u8>]) -> Result<RespVal> {
+    Ok(RespVal::Integer(all_commands().count() as i64))
+}
+
+fn command_info(_ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    if args.len() == 2 {
+        return Ok(RespVal::Array(all_commands().map(command_info_reply).collect()));
+    }
+    let infos = args[2..]
+        .iter()
+        .map(|name| lookup_with_subcommand(name).map_or(RespVal::Null, command_info_reply))
+        .collect();
+    Ok(RespVal::Array(infos))
+}
+
+fn command_docs(_ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let specs: Vec<&CommandSpec> = if args.len() == 2 {
+        all_commands().collect()
+    } else {
+        args[2..].iter().filter_map(|name| lookup_with_subcommand(name)).collect()
+    };
+    Ok(RespVal::Map(specs.into_iter().map(command_docs_reply).collect()))
+}
+
+/// Looks up commands by their full name, which includes the container for subcommands, e.g.
+/// `config|get`.
+fn lookup_with_subcommand(name: &[u8]) -> Option<&'static CommandSpec> {
+    match name.iter().position(|&byte| byte == b'|') {
+        Some(separator) => lookup(&name[..separator])?.find_subcommand(&name[separator + 1..]),
+        None => lookup(name),
+    }
+}
+
+fn simple(text: &str) -> RespVal {
+    RespVal::SimpleString(text.as_bytes().to_vec())
+}
+
+fn bulk(text: &str) -> RespVal {
+    RespVal::BulkString(text.as_bytes().to_vec())
+}
+
+/// The ACL categories of a command, derived from its group and flags.
+fn acl_categories(spec: &CommandSpec) -> Vec<RespVal> {
+    let group_category = match spec.group {
+        "generic" => "@keyspace",
+        "sorted-set" => "@sortedset",
+        "server" => "@admin",
+        group => group,
+    };
+    let mut categories = Vec::new();
+    if group_category.starts_with('@') {
+        categories.push(group_category.to_string());
+    } else if !group_category.is_empty() {
+        categories.push(format!("@{}", group_category));
+    }
+    if spec.has_flag(Write) {
+        categories.push("@write".to_string());
+    }
+    if spec.has_flag(ReadOnly) {
+        categories.push("@read".to_string());
+    }
+    if spec.has_flag(Admin) {
+        categories.push("@dangerous".to_string());
+    }
+    if spec.has_flag(Blocking) {
+        categories.push("@blocking".to_string());
+    }
+    categories.push(if spec.has_flag(Fast) { "@fast" } else { "@slow" }.to_string());
+    categories.dedup();
+    categories.iter().map(|category| simple(category)).collect()
+}
+
+/// Describes the key positions of commands without movable keys as a single key specification.
+fn key_specs(spec: &CommandSpec) -> Vec<RespVal> {
+    if spec.first_key == 0 || spec.has_flag(MovableKeys) {
+        return Vec::new();
+    }
+    let access = if spec.has_flag(Write) { "RW" } else { "RO" };
+    let last_key = if spec.last_key >= 0 {
+        spec.last_key - spec.first_key
+    } else {
+        spec.last_key
+    };
+    vec![RespVal::Map(vec![
+        (bulk("flags"), RespVal::Set(vec![simple(access)])),
+        (
+            bulk("begin_search"),
+            RespVal::Map(vec![
+                (bulk("type"), bulk("index")),
+                (bulk("spec"), RespVal::Map(vec![(bulk("index"), RespVal::Integer(spec.first_key))])),
+            ]),
+        ),
+        (
+            bulk("find_keys"),
+            RespVal::Map(vec![
+                (bulk("type"), bulk("range")),
+                (
+                    bulk("spec"),
+                    RespVal::Map(vec![
+                        (bulk("lastkey"), RespVal::Integer(last_key)),
+                        (bulk("keystep"), RespVal::Integer(spec.key_step)),
+                        (bulk("limit"), RespVal::Integer(0)),
+                    ]),
+                ),
+            ]),
+        ),
+    ])]
+}
+
+fn command_info_reply(spec: &CommandSpec) -> RespVal {
+    RespVal::Array(vec![
+        bulk(spec.name),
+        RespVal::Integer(spec.arity),
+        RespVal::Set(spec.flags.iter().map(|flag| simple(CommandFlag::name(*flag))).collect()),
+        RespVal::Integer(spec.first_key),
+        RespVal::Integer(spec.last_key),
+        RespVal::Integer(spec.key_step),
+        RespVal::Set(acl_categories(spec)),
+        RespVal::Set(Vec::new()),
+        RespVal::Array(key_specs(spec)),
+        RespVal::Array(spec.subcommands.iter().map(command_info_reply).collect()),
+    ])
+}
+
+fn command_docs_reply(spec: &CommandSpec) -> (RespVal, RespVal) {
+    let mut docs = vec![
+        (bulk("summary"), bulk(spec.summary)),
+        (bulk("since"), bulk(spec.since)),
+        (bulk("group"), bulk(spec.group)),
+    ];
+    if !spec.subcommands.is_empty() {
+        docs.push((
+            bulk("subcommands"),
+            RespVal::Map(spec.subcommands.iter().map(command_docs_reply).collect()),
+        ));
+    }
+    (bulk(spec.name), RespVal::Map(docs))
+}
@@ -0,0 +1,419 @@
+use super::{parse_integer, parse_random_count, random_indices, syntax_error, CommandFlag::*, CommandSpec, Context};
+use crate::error::{Error, Result};
+use crate::random;
+use crate::resp::RespVal;
+use crate::set::Set;
+use crate::{Data, Value};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("sadd", -3, sadd)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("set", "1.0.0", "Adds one or more members to a set. Creates the key if it doesn't exist."),
+    CommandSpec::new("srem", -3, srem)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("set", "1.0.0", "Removes one or more members from a set. Deletes the set if the last member was removed."),
+    CommandSpec::new("smembers", 2, smembers)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("set", "1.0.0", "Returns all members of a set."),
+    CommandSpec::new("sismember", 3, sismember)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("set", "1.0.0", "Determines whether a member belongs to a set."),
+    CommandSpec::new("smismember", -3, smismember)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("set", "6.2.0", "Determines whether multiple members belong to a set."),
+    CommandSpec::new("scard", 2, scard)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("set", "1.0.0", "Returns the number of members in a set."),
+    CommandSpec::new("spop", -2, spop)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("set", "1.0.0", "Returns one or more random members from a set after removing them. Deletes the set if the last member was popped."),
+    CommandSpec::new("srandmember", -2, srandmember)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("set", "1.0.0", "Get one or multiple random members from a set"),
+    CommandSpec::new("smove", 4, smove)
+        .flags(&[Write, Fast])
+        .keys(1, 2, 1)
+        .docs("set", "1.0.0", "Moves a member from one set to another."),
+    CommandSpec::new("sinter", -2, sinter)
+        .flags(&[ReadOnly])
+        .keys(1, -1, 1)
+        .docs("set", "1.0.0", "Returns the intersect of multiple sets."),
+    CommandSpec::new("sinterstore", -3, sinterstore)
+        .flags(&[Write, DenyOom])
+        .keys(1, -1, 1)
+        .docs("set", "1.0.0", "Stores the intersect of multiple sets in a key."),
+    CommandSpec::new("sintercard", -3, sintercard)
+        .flags(&[ReadOnly, MovableKeys])
+        .keys(0, 0, 0)
+        .docs("set", "7.0.0", "Returns the number of members of the intersect of multiple sets."),
+    CommandSpec::new("sunion", -2, sunion)
+        .flags(&[ReadOnly])
+        .keys(1, -1, 1)
+        .docs("set", "1.0.0", "Returns the union of multiple sets."),
+    CommandSpec::new("sunionstore", -3, sunionstore)
+        .flags(&[Write, DenyOom])
+        .keys(1, -1, 1)
+        .docs("set", "1.0.0", "Stores the union of multiple sets in a key."),
+    CommandSpec::new("sdiff", -2, sdiff)
+        .flags(&[ReadOnly])
+        .keys(1, -1, 1)
+        .docs("set", "1.0.0", "Returns the difference of multiple sets."),
+    CommandSpec::new("sdiffstore", -3, sdiffstore)
+        .flags(&[Write, DenyOom])
+        .keys(1, -1, 1)
+        .docs("set", "1.0.0", "Stores the difference of multiple sets in a key."),
+];
+
+fn bulk_strings(members: impl IntoIterator<Item = Vec<u8>>) -> Vec<RespVal> {
+    members.into_iter().map(RespVal::BulkString).collect()
+}
+
+/// Deletes the set at `key` if it is empty: like in Redis, a key never holds an empty set.
+fn remove_if_empty(ctx: &mut Context, key: &[u8]) {
+    let is_empty = matches!(ctx.db.get(key), Some(Value { data: Data::Set(set), .. }) if set.is_empty());
+    if is_empty {
+        ctx.db.remove(key);
+    }
+}
+
+fn sadd(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    if ctx.get_set(key)?.is_none() {
+        ctx.db.insert(key.clone(), Value::new(Data::Set(Set::default())));
+    }
+    let set = ctx.get_set_mut(key)?.expect("the key was just created");
+    let added = args[2..].iter().filter(|member| set.insert(member.to_vec())).count();
+    Ok(RespVal::Integer(added as i64))
+}
+
+fn srem(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    let set = match ctx.get_set_mut(key)? {
+        Some(set) => set,
+        None => return Ok(RespVal::Integer(0)),
+    };
+    let removed = args[2..].iter().filter(|member| set.remove(member)).count();
+    remove_if_empty(ctx, key);
+    Ok(RespVal::Integer(removed as i64))
+}
+
+fn smembers(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let members = ctx.get_set(&args[1])?.map_or_else(Vec::new, Set::members);
+    Ok(RespVal::Set(bulk_strings(members)))
+}
+
+fn sismember(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let is_member = ctx.get_set(&args[1])?.is_some_and(|set| set.contains(&args[2]));
+    Ok(RespVal::Integer(is_member as i64))
+}
+
+fn smismember(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let set = ctx.get_set(&args[1])?;
+    let replies = args[2..]
+        .iter()
+        .map(|member| RespVal::Integer(set.is_some_and(|set| set.contains(member)) as i64))
+        .collect();
+    Ok(RespVal::Array(replies))
+}
+
+fn scard(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let length = ctx.get_set(&args[1])?.map_or(0, Set::len);
+    Ok(RespVal::Integer(length as i64))
+}
+
+/// Removes and replies with a random member, or with a count, up to that many distinct
+/// members.
+fn spop(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    if args.len() > 3 {
+        return Err(syntax_error());
+    }
+    let key = &args[1];
+    let count = match args.get(2) {
+        Some(count) => Some(
+            usize::try_from(parse_integer(count)?)
+                .map_err(|_| Error::ValidationError("value is out of range, must be positive".to_string()))?,
+        ),
+        None => None,
+    };
+    let set = match ctx.get_set_mut(key)? {
+        Some(set) => set,
+        None if count.is_some() => return Ok(RespVal::Set(Vec::new())),
+        None => return Ok(RespVal::Null),
+    };
+    let indices = random::distinct_below(set.len(), count.unwrap_or(1).min(set.len()));
+    let popped: Vec<Vec<u8>> = indices.into_iter().map(|index| set.member_at(index)).collect();
+    for member in &popped {
+        set.remove(member);
+    }
+    remove_if_empty(ctx, key);
+    match count {
+        Some(_) => Ok(RespVal::Set(bulk_strings(popped))),
+        None => Ok(RespVal::BulkString(popped.into_iter().next().expect("sets are never empty"))),
+    }
+}
+
+/// Replies with a random member, or with a count, up to that many distinct members; a
+/// negative count allows repeated members and always returns exactly that many.
+fn srandmember(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    if args.len() > 3 {
+        return Err(syntax_error());
+    }
+    let count = args.get(2).map(|count| parse_random_count(count, false)).transpose()?;
+    let set = match (ctx.get_set(&args[1])?, count) {
+        (Some(set), _) => set,
+        (None, Some(_)) => return Ok(RespVal::Array(Vec::new())),
+        (None, None) => return Ok(RespVal::Null),
+    };
+    let count = match count {
+        Some(count) => count,
+        None => return Ok(RespVal::BulkString(set.member_at(random::random_below(set.len())))),
+    };
+    let members = random_indices(set.len(), count)?.into_iter().map(|index| set.member_at(index));
+    Ok(RespVal::Array(bulk_strings(members)))
+}
+
+fn smove(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (source, destination, member) = (&args[1], &args[2], &args[3]);
+    // Check the type of the destination before anything is removed.
+    let destination_exists = ctx.get_set(destination)?.is_some();
+    let source_set = match ctx.get_set_mut(source)? {
+        Some(set) => set,
+        None => return Ok(RespVal::Integer(0)),
+    };
+    if source == destination {
+        return Ok(RespVal::Integer(source_set.contains(member) as i64));
+    }
+    if !source_set.remove(member) {
+        return Ok(RespVal::Integer(0));
+    }
+    remove_if_empty(ctx, source);
+    if !destination_exists {
+        ctx.db.insert(destination.clone(), Value::new(Data::Set(Set::default())));
+    }
+    ctx.get_set_mut(destination)?
+        .expect("the destination exists")
+        .insert(member.clone());
+    Ok(RespVal::Integer(1))
+}
+
+/// The sets at `keys`, `None` for missing keys. Fails if any key holds another type.
+fn sets_at<'a>(ctx: &'a mut Context, keys: &[Vec<u8>]) -> Result<Vec<Option<&'a Set>>> {
+    // Expire and check every key first, the sets are borrowed together afterwards.
+    for key in keys {
+        ctx.get_set(key)?;
+    }
+    let db = &*ctx.db;
+    Ok(keys
+        .iter()
+        .map(|key| db.get(key).map(|value| value.as_set().expect("the type was checked")))
+        .collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SetOperation {
+    Intersection,
+    Union,
+    Difference,
+}
+
+impl SetOperation {
+    fn apply(self, sets: &[Option<&Set>]) -> Set {
+        match self {
+            SetOperation::Intersection => {
+                if sets.iter().any(Option::is_none) {
+                    return Set::default();
+                }
+                let mut sets: Vec<&Set> = sets.iter().flatten().copied().collect();
+                // Only members of the smallest set can be in the intersection.
+                sets.sort_by_key(|set| set.len());
+                let (smallest, others) = sets.split_first().expect("there is at least one key");
+                smallest
+                    .members()
+                    .into_iter()
+                    .filter(|member| others.iter().all(|set| set.contains(member)))
+                    .collect()
+            }
+            SetOperation::Union => sets.iter().flatten().flat_map(|set| set.members()).collect(),
+            SetOperation::Difference => match sets.split_first() {
+                Some((Some(first), others)) => first
+                    .members()
+                    .into_iter()
+                    .filter(|member| !others.iter().flatten().any(|set| set.contains(member)))
+                    .collect(),
+                _ => Set::default(),
+            },
+        }
+    }
+}
+
+fn sinter(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    combine(ctx, &args[1..], SetOperation::Intersection)
+}
+
+fn sunion(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    combine(ctx, &args[1..], SetOperation::Union)
+}
+
+fn sdiff(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    combine(ctx, &args[1..], SetOperation::Difference)
+}
+
+fn combine(ctx: &mut Context, keys: &[Vec<u8>], operation: SetOperation) -> Result<RespVal> {
+    let result = operation.apply(&sets_at(ctx, keys)?);
+    Ok(RespVal::Set(bulk_strings(result.members())))
+}
+
+fn sinterstore(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    combine_and_store(ctx, &args[1], &args[2..], SetOperation::Intersection)
+}
+
+fn sunionstore(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    combine_and_store(ctx, &args[1], &args[2..], SetOperation::Union)
+}
+
+fn sdiffstore(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    combine_and_store(ctx, &args[1], &args[2..], SetOperation::Difference)
+}
+
+/// Stores the result at `destination`, replacing whatever is there, or deletes it if the
+/// result is empty.
+fn combine_and_store(ctx: &mut Context, destination: &[u8], keys: &[Vec<u8>], operation: SetOperation) -> Result<RespVal> {
+    let result = operation.apply(&sets_at(ctx, keys)?);
+    let length = result.len();
+    if result.is_empty() {
+        ctx.db.remove(destination);
+    } else {
+        ctx.db.insert(destination.to_vec(), Value::new(Data::Set(result)));
+    }
+    Ok(RespVal::Integer(length as i64))
+}
+
+/// Replies with the size of the intersection, counting no further than a non-zero `LIMIT`.
+fn sintercard(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let numkeys = parse_integer(&args[1])?;
+    if numkeys <= 0 {
+        return Err(Error::ValidationError("numkeys should be greater than 0".to_string()));
+    }
+    let numkeys = usize::try_from(numkeys)
+        .ok()
+        .filter(|&numkeys| numkeys <= args.len() - 2)
+        .ok_or_else(|| Error::ValidationError("Number of keys can't be greater than number of args".to_string()))?;
+    let keys = &args[2..2 + numkeys];
+    let limit = match &args[2 + numkeys..] {
+        [] => 0,
+        [option, limit] if option.eq_ignore_ascii_case(b"limit") => usize::try_from(parse_integer(limit)?)
+            .map_err(|_| Error::ValidationError("LIMIT can't be negative".to_string()))?,
+        _ => return Err(syntax_error()),
+    };
+    let sets = sets_at(ctx, keys)?;
+    if sets.iter().any(Option::is_none) {
+        return Ok(RespVal::Integer(0));
+    }
+    let mut sets: Vec<&Set> = sets.into_iter().flatten().collect();
+    sets.sort_by_key(|set| set.len());
+    let (smallest, others) = sets.split_first().expect("there is at least one key");
+    let limit = if limit == 0 { usize::MAX } else { limit };
+    let count = smallest
+        .members()
+        .into_iter()
+        .filter(|member| others.iter().all(|set| set.contains(member)))
+        .take(limit)
+        .count();
+    Ok(RespVal::Integer(count as i64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{new_database, run};
+    use super::*;
+    use crate::client::Client;
+
+    /// The members of a set reply, sorted, as the order of members is unspecified.
+    fn sorted(reply: RespVal) -> Vec<String> {
+        let mut members: Vec<String> = match reply {
+            RespVal::Set(members) | RespVal::Array(members) => members
+                .into_iter()
+                .map(|member| match member {
+                    RespVal::BulkString(member) => String::from_utf8(member).unwrap(),
+                    member => panic!("unexpected member {:?}", member),
+                })
+                .collect(),
+            reply => panic!("unexpected reply {:?}", reply),
+        };
+        members.sort();
+        members
+    }
+
+    #[test]
+    fn test_membership() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(run(&map, &mut client, &["sadd", "s", "1", "2", "2", "3"]).unwrap(), RespVal::Integer(3));
+        assert_eq!(run(&map, &mut client, &["object", "encoding", "s"]).unwrap(), RespVal::BulkString(b"intset".to_vec()));
+        assert_eq!(run(&map, &mut client, &["sadd", "s", "a"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["object", "encoding", "s"]).unwrap(), RespVal::BulkString(b"hashtable".to_vec()));
+        assert_eq!(sorted(run(&map, &mut client, &["smembers", "s"]).unwrap()), ["1", "2", "3", "a"]);
+        assert_eq!(run(&map, &mut client, &["sismember", "s", "a"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(
+            run(&map, &mut client, &["smismember", "s", "1", "x"]).unwrap(),
+            RespVal::Array(vec![RespVal::Integer(1), RespVal::Integer(0)])
+        );
+        assert_eq!(run(&map, &mut client, &["srem", "s", "1", "x"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["scard", "s"]).unwrap(), RespVal::Integer(3));
+        assert_eq!(run(&map, &mut client, &["smove", "s", "t", "a"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["smove", "s", "t", "a"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(sorted(run(&map, &mut client, &["smembers", "t"]).unwrap()), ["a"]);
+        run(&map, &mut client, &["set", "string", "v"]).unwrap();
+        assert!(matches!(run(&map, &mut client, &["smove", "s", "string", "2"]), Err(Error::WrongType)));
+        assert_eq!(run(&map, &mut client, &["scard", "s"]).unwrap(), RespVal::Integer(2));
+    }
+
+    #[test]
+    fn test_random_members() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(run(&map, &mut client, &["spop", "s"]).unwrap(), RespVal::Null);
+        assert_eq!(run(&map, &mut client, &["srandmember", "s", "2"]).unwrap(), RespVal::Array(Vec::new()));
+        run(&map, &mut client, &["sadd", "s", "a", "b", "c", "d"]).unwrap();
+        let mut distinct = sorted(run(&map, &mut client, &["srandmember", "s", "3"]).unwrap());
+        distinct.dedup();
+        assert_eq!(distinct.len(), 3);
+        assert_eq!(sorted(run(&map, &mut client, &["srandmember", "s", "-9"]).unwrap()).len(), 9);
+        assert_eq!(sorted(run(&map, &mut client, &["spop", "s", "3"]).unwrap()).len(), 3);
+        assert_eq!(run(&map, &mut client, &["scard", "s"]).unwrap(), RespVal::Integer(1));
+        assert!(matches!(run(&map, &mut client, &["spop", "s"]).unwrap(), RespVal::BulkString(_)));
+        assert_eq!(run(&map, &mut client, &["exists", "s"]).unwrap(), RespVal::Integer(0));
+        assert!(run(&map, &mut client, &["spop", "s", "-1"]).is_err());
+    }
+
+    #[test]
+    fn test_algebra() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["sadd", "a", "1", "2", "3", "x"]).unwrap();
+        run(&map, &mut client, &["sadd", "b", "2", "3", "4"]).unwrap();
+        assert_eq!(sorted(run(&map, &mut client, &["sinter", "a", "b"]).unwrap()), ["2", "3"]);
+        assert_eq!(sorted(run(&map, &mut client, &["sinter", "a", "missing"]).unwrap()), Vec::<String>::new());
+        assert_eq!(sorted(run(&map, &mut client, &["sunion", "a", "b", "missing"]).unwrap()), ["1", "2", "3", "4", "x"]);
+        assert_eq!(sorted(run(&map, &mut client, &["sdiff", "a", "b", "missing"]).unwrap()), ["1", "x"]);
+        assert_eq!(run(&map, &mut client, &["sinterstore", "c", "a", "b"]).unwrap(), RespVal::Integer(2));
+        assert_eq!(run(&map, &mut client, &["object", "encoding", "c"]).unwrap(), RespVal::BulkString(b"intset".to_vec()));
+        assert_eq!(run(&map, &mut client, &["sdiffstore", "c", "missing", "a"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["exists", "c"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["sunionstore", "a", "a", "b"]).unwrap(), RespVal::Integer(5));
+        assert_eq!(run(&map, &mut client, &["sintercard", "2", "a", "b"]).unwrap(), RespVal::Integer(3));
+        assert_eq!(run(&map, &mut client, &["sintercard", "2", "a", "b", "limit", "2"]).unwrap(), RespVal::Integer(2));
+        assert!(run(&map, &mut client, &["sintercard", "3", "a", "b"]).is_err());
+        assert!(run(&map, &mut client, &["sintercard", "1", "a", "limit", "-1"]).is_err());
+        run(&map, &mut client, &["set", "string", "v"]).unwrap();
+        assert!(matches!(run(&map, &mut client, &["sunion", "a", "string"]), Err(Error::WrongType)));
+    }
+}
@@ -0,0 +1,1215 @@
+use super::{ok, parse_integer, syntax_error, wrong_arity, CommandFlag::*, CommandSpec, Context};
+use crate::error::{Error, Result};
+use crate::resp::{ProtocolVersion, RespVal};
+use crate::stream::{ConsumerGroup, Fields, PendingEntry, Stream, StreamId, Trim, TrimStrategy, NODE_MAX_ENTRIES};
+use crate::{Data, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("xadd", -5, xadd)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("stream", "5.0.0", "Appends a new message to a stream. Creates the key if it doesn't exist."),
+    CommandSpec::new("xrange", -4, xrange)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("stream", "5.0.0", "Returns the messages from a stream within a range of IDs."),
+    CommandSpec::new("xrevrange", -4, xrevrange)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("stream", "5.0.0", "Returns the messages from a stream within a range of IDs in reverse order."),
+    CommandSpec::new("xlen", 2, xlen)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("stream", "5.0.0", "Return the number of messages in a stream."),
+    CommandSpec::new("xdel", -3, xdel)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("stream", "5.0.0", "Returns the number of messages after removing them from a stream."),
+    CommandSpec::new("xtrim", -4, xtrim)
+        .flags(&[Write])
+        .keys(1, 1, 1)
+        .docs("stream", "5.0.0", "Deletes messages from the beginning of a stream."),
+    CommandSpec::new("xread", -4, xread)
+        .flags(&[ReadOnly, Blocking, MovableKeys])
+        .keys(0, 0, 0)
+        .docs("stream", "5.0.0", "Returns messages from multiple streams with IDs greater than the ones requested. Blocks until a message is available otherwise."),
+    CommandSpec::new("xreadgroup", -7, xreadgroup)
+        .flags(&[Write, Blocking, MovableKeys])
+        .keys(0, 0, 0)
+        .docs("stream", "5.0.0", "Returns new or historical messages from a stream for a consumer in a group. Blocks until a message is available otherwise."),
+    CommandSpec::new("xack", -4, xack)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("stream", "5.0.0", "Returns the number of messages that were successfully acknowledged by the consumer group member of a stream."),
+    CommandSpec::new("xpending", -3, xpending)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("stream", "5.0.0", "Returns the information and entries from a stream consumer group's pending entries list."),
+    CommandSpec::new("xclaim", -6, xclaim)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("stream", "5.0.0", "Changes, or acquires, ownership of a message in a consumer group, as if the message was delivered a consumer group member."),
+    CommandSpec::new("xautoclaim", -6, xautoclaim)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("stream", "6.2.0", "Changes, or acquires, ownership of messages in a consumer group, as if the messages were delivered to as consumer group member."),
+    CommandSpec::container("xgroup", XGROUP_SUBCOMMANDS)
+        .docs("stream", "5.0.0", "A container for consumer groups commands."),
+    CommandSpec::container("xinfo", XINFO_SUBCOMMANDS)
+        .docs("stream", "5.0.0", "A container for stream introspection commands."),
+];
+
+const XGROUP_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("xgroup|create", -5, xgroup_create)
+        .flags(&[Write, DenyOom])
+        .keys(2, 2, 1)
+        .docs("stream", "5.0.0", "Creates a consumer group."),
+    CommandSpec::new("xgroup|setid", -5, xgroup_setid)
+        .flags(&[Write])
+        .keys(2, 2, 1)
+        .docs("stream", "5.0.0", "Sets the last-delivered ID of a consumer group."),
+    CommandSpec::new("xgroup|destroy", 4, xgroup_destroy)
+        .flags(&[Write])
+        .keys(2, 2, 1)
+        .docs("stream", "5.0.0", "Destroys a consumer group."),
+    CommandSpec::new("xgroup|createconsumer", 5, xgroup_createconsumer)
+        .flags(&[Write, DenyOom])
+        .keys(2, 2, 1)
+        .docs("stream", "6.2.0", "Creates a consumer in a consumer group."),
+    CommandSpec::new("xgroup|delconsumer", 5, xgroup_delconsumer)
+        .flags(&[Write])
+        .keys(2, 2, 1)
+        .docs("stream", "5.0.0", "Deletes a consumer from a consumer group."),
+];
+
+const XINFO_SUBCOMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("xinfo|stream", 3, xinfo_stream)
+        .flags(&[ReadOnly])
+        .keys(2, 2, 1)
+        .docs("stream", "5.0.0", "Returns information about a stream."),
+    CommandSpec::new("xinfo|groups", 3, xinfo_groups)
+        .flags(&[ReadOnly])
+        .keys(2, 2, 1)
+        .docs("stream", "5.0.0", "Returns a list of the consumer groups of a stream."),
+    CommandSpec::new("xinfo|consumers", 4, xinfo_consumers)
+        .flags(&[ReadOnly])
+        .keys(2, 2, 1)
+        .docs("stream", "5.0.0", "Returns a list of the consumers in a consumer group."),
+];
+
+/// Approximate trimming removes at most this many entries unless told otherwise, like Redis.
+const DEFAULT_TRIM_LIMIT: usize = 100 * NODE_MAX_ENTRIES;
+
+fn invalid_id() -> Error {
+    Error::ValidationError("Invalid stream ID specified as stream command argument".to_string())
+}
+
+/// Parses `ms-seq`, or `ms` alone with `missing_seq` as sequence number.
+fn parse_id(arg: &[u8], missing_seq: u64) -> Result<StreamId> {
+    StreamId::parse(arg, missing_seq).ok_or_else(invalid_id)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+fn entry_reply(id: StreamId, fields: &Fields) -> RespVal {
+    let fields = fields
+        .iter()
+        .flat_map(|(field, value)| [RespVal::BulkString(field.clone()), RespVal::BulkString(value.clone())])
+        .collect();
+    RespVal::Array(vec![RespVal::BulkString(id.to_string().into_bytes()), RespVal::Array(fields)])
+}
+
+fn entries_reply(entries: Vec<(StreamId, &Fields)>) -> RespVal {
+    RespVal::Array(entries.into_iter().map(|(id, fields)| entry_reply(id, fields)).collect())
+}
+
+/// The stream at `key`, which is created if it doesn't exist.
+fn stream_or_create<'a>(ctx: &'a mut Context, key: &[u8]) -> Result<&'a mut Stream> {
+    if ctx.get_stream(key)?.is_none() {
+        ctx.db.insert(key.to_vec(), Value::new(Data::Stream(Stream::default())));
+    }
+    Ok(ctx.get_stream_mut(key)?.expect("the key was just created"))
+}
+
+/// The options `XADD` and `XTRIM` share.
+#[derive(Debug, Default)]
+struct AddOptions {
+    no_mkstream: bool,
+    trim: Option<Trim>,
+}
+
+impl AddOptions {
+    /// Parses `[NOMKSTREAM] [MAXLEN|MINID [=|~] threshold [LIMIT count]]` at the start of
+    /// `args`, returning the options with the number of arguments they took. `NOMKSTREAM` is
+    /// only an option of `XADD`.
+    fn parse(args: &[Vec<u8>], is_xadd: bool) -> Result<(AddOptions, usize)> {
+        let mut options = AddOptions::default();
+        let mut strategy = None;
+        let mut approximate = false;
+        let mut limit = None;
+        let mut i = 0;
+        while i < args.len() {
+            match args[i].to_ascii_lowercase().as_slice() {
+                b"nomkstream" if is_xadd => options.no_mkstream = true,
+                option @ (b"maxlen" | b"minid") => {
+                    let is_max_len = option == b"maxlen";
+                    if matches!((strategy, is_max_len), (Some(TrimStrategy::MinId(_)), true) | (Some(TrimStrategy::MaxLen(_)), false)) {
+                        return Err(Error::ValidationError(
+                            "syntax error, MAXLEN and MINID options at the same time are not compatible".to_string(),
+                        ));
+                    }
+                    approximate = false;
+                    match args.get(i + 1).map(Vec::as_slice) {
+                        Some(b"~") => {
+                            approximate = true;
+                            i += 1;
+                        }
+                        Some(b"=") => i += 1,
+                        _ => {}
+                    }
+                    let threshold = args.get(i + 1).ok_or_else(syntax_error)?;
+                    strategy = Some(if is_max_len {
+                        let max_len = usize::try_from(parse_integer(threshold)?)
+                            .map_err(|_| Error::ValidationError("The MAXLEN argument must be >= 0.".to_string()))?;
+                        TrimStrategy::MaxLen(max_len)
+                    } else {
+                        TrimStrategy::MinId(parse_id(threshold, 0)?)
+                    });
+                    i += 1;
+                }
+                b"limit" => {
+                    let count = args.get(i + 1).ok_or_else(syntax_error)?;
+                    limit = Some(
+                        usize::try_from(parse_integer(count)?)
+                            .map_err(|_| Error::ValidationError("The LIMIT argument must be >= 0.".to_string()))?,
+                    );
+                    i += 1;
+                }
+                _ => break,
+            }
+            i += 1;
+        }
+        if limit.is_some() && !approximate {
+            return Err(Error::ValidationError(
+                "syntax error, LIMIT cannot be used without the special ~ option".to_string(),
+            ));
+        }
+        options.trim = strategy.map(|strategy| Trim {
+            strategy,
+            approximate,
+            // A limit of 0 means no limit.
+            limit: if approximate {
+                Some(limit.unwrap_or(DEFAULT_TRIM_LIMIT)).filter(|&limit| limit > 0)
+            } else {
+                None
+            },
+        });
+        Ok((options, i))
+    }
+}
+
+/// The ID argument of `XADD`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NewId {
+    /// `*`: the current time, or right after the last ID if that is greater.
+    Auto,
+    /// `ms-*`: the next sequence number within the millisecond.
+    Partial(u64),
+    Explicit(StreamId),
+}
+
+impl NewId {
+    fn parse(arg: &[u8]) -> Result<NewId> {
+        match arg {
+            b"*" => Ok(NewId::Auto),
+            [ms @ .., b'-', b'*'] if !ms.contains(&b'-') => Ok(NewId::Partial(parse_id(ms, 0)?.ms)),
+            _ => Ok(NewId::Explicit(parse_id(arg, 0)?)),
+        }
+    }
+
+    /// The ID an entry added to `stream` gets.
+    fn resolve(self, stream: &Stream) -> Result<StreamId> {
+        let last_id = stream.last_id();
+        let too_small =
+            || Error::ValidationError("The ID specified in XADD is equal or smaller than the target stream top item".to_string());
+        let id = match self {
+            NewId::Auto => stream.next_id(now_millis()).ok_or_else(|| {
+                Error::ValidationError("The stream has exhausted the last possible ID, unable to add more items".to_string())
+            })?,
+            NewId::Partial(ms) if ms == last_id.ms => {
+                StreamId::new(ms, last_id.seq.checked_add(1).ok_or_else(too_small)?)
+            }
+            // 0-0 is never a valid ID.
+            NewId::Partial(0) => StreamId::new(0, 1),
+            NewId::Partial(ms) => StreamId::new(ms, 0),
+            NewId::Explicit(StreamId::MIN) => {
+                return Err(Error::ValidationError("The ID specified in XADD must be greater than 0-0".to_string()))
+            }
+            NewId::Explicit(id) => id,
+        };
+        if id <= last_id {
+            return Err(too_small());
+        }
+        Ok(id)
+    }
+}
+
+fn xadd(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    let (options, parsed) = AddOptions::parse(&args[2..], true)?;
+    let (id, fields) = match &args[2 + parsed..] {
+        [id, fields @ ..] if !fields.is_empty() && fields.len().is_multiple_of(2) => (NewId::parse(id)?, fields),
+        _ => return Err(wrong_arity("xadd")),
+    };
+    let id = match ctx.get_stream(key)? {
+        Some(stream) => id.resolve(stream)?,
+        None if options.no_mkstream => return Ok(RespVal::Null),
+        None => id.resolve(&Stream::default())?,
+    };
+    let stream = stream_or_create(ctx, key)?;
+    stream.insert(id, fields.chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect());
+    if let Some(trim) = &options.trim {
+        stream.trim(trim);
+    }
+    ctx.db.signal_key_as_ready(key);
+    Ok(RespVal::BulkString(id.to_string().into_bytes()))
+}
+
+/// Parses one end of an `XRANGE` interval: `-`, `+`, an ID, or an ID after `(` to exclude it.
+/// A missing sequence number selects the whole millisecond.
+fn parse_interval_end(arg: &[u8], is_start: bool) -> Result<StreamId> {
+    let missing_seq = if is_start { 0 } else { u64::MAX };
+    match arg {
+        b"-" => Ok(StreamId::MIN),
+        b"+" => Ok(StreamId::MAX),
+        [b'(', id @ ..] => {
+            let id = parse_id(id, missing_seq)?;
+            let (excluded, end) = if is_start { (id.next(), "start") } else { (id.previous(), "end") };
+            excluded.ok_or_else(|| Error::ValidationError(format!("invalid {} ID for the interval", end)))
+        }
+        _ => parse_id(arg, missing_seq),
+    }
+}
+
+fn xrange(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    range(ctx, args, false)
+}
+
+fn xrevrange(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    range(ctx, args, true)
+}
+
+fn range(ctx: &mut Context, args: &[Vec<u8>], reverse: bool) -> Result<RespVal> {
+    let (start, end) = if reverse { (&args[3], &args[2]) } else { (&args[2], &args[3]) };
+    let start = parse_interval_end(start, true)?;
+    let end = parse_interval_end(end, false)?;
+    let count = match &args[4..] {
+        [] => None,
+        // A negative count is taken as 0.
+        [option, count] if option.eq_ignore_ascii_case(b"count") => Some(parse_integer(count)?.max(0) as usize),
+        _ => return Err(syntax_error()),
+    };
+    let stream = match ctx.get_stream(&args[1])? {
+        Some(stream) => stream,
+        None => return Ok(RespVal::Array(Vec::new())),
+    };
+    if count == Some(0) {
+        return Ok(RespVal::NullArray);
+    }
+    Ok(entries_reply(stream.range(start, end, reverse, count)))
+}
+
+fn xlen(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let length = ctx.get_stream(&args[1])?.map_or(0, Stream::len);
+    Ok(RespVal::Integer(length as i64))
+}
+
+fn xdel(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    // Every ID is validated before anything is deleted.
+    let ids = args[2..].iter().map(|id| parse_id(id, 0)).collect::<Result<Vec<_>>>()?;
+    let deleted = match ctx.get_stream_mut(&args[1])? {
+        Some(stream) => ids.into_iter().filter(|&id| stream.remove(id)).count(),
+        None => 0,
+    };
+    Ok(RespVal::Integer(deleted as i64))
+}
+
+fn xtrim(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (options, parsed) = AddOptions::parse(&args[2..], false)?;
+    let trim = match options.trim {
+        Some(trim) if parsed == args.len() - 2 => trim,
+        _ => return Err(syntax_error()),
+    };
+    let removed = ctx.get_stream_mut(&args[1])?.map_or(0, |stream| stream.trim(&trim));
+    Ok(RespVal::Integer(removed as i64))
+}
+
+/// Parses the `BLOCK` timeout of `XREAD` and `XREADGROUP`, in milliseconds; 0 means forever.
+fn parse_block_timeout(arg: &[u8]) -> Result<Option<Duration>> {
+    let millis = parse_integer(arg)
+        .map_err(|_| Error::ValidationError("timeout is not an integer or out of range".to_string()))?;
+    let millis = u64::try_from(millis).map_err(|_| Error::ValidationError("timeout is negative".to_string()))?;
+    Ok(Some(Duration::from_millis(millis)).filter(|timeout| !timeout.is_zero()))
+}
+
+/// Replies with the entries read from each stream, leaving out streams without any: as a map
+/// from key to entries in RESP3, as pairs in RESP2.
+fn streams_reply(protocol: ProtocolVersion, streams: Vec<(Vec<u8>, RespVal)>) -> RespVal {
+    match protocol {
+        ProtocolVersion::Resp3 => RespVal::Map(
+            streams
+                .into_iter()
+                .map(|(key, entries)| (RespVal::BulkString(key), entries))
+                .collect(),
+        ),
+        ProtocolVersion::Resp2 => RespVal::Array(
+            streams
+                .into_iter()
+                .map(|(key, entries)| RespVal::Array(vec![RespVal::BulkString(key), entries]))
+                .collect(),
+        ),
+    }
+}
+
+/// The arguments of `XREAD` and `XREADGROUP`: `[GROUP group consumer] [COUNT count] [BLOCK
+/// milliseconds] [NOACK] STREAMS key [key ...] id [id ...]`, where the group options are
+/// those of `XREADGROUP`.
+struct ReadArgs<'a> {
+    group: Option<(&'a [u8], &'a [u8])>,
+    /// 0 or less means no limit.
+    count: Option<usize>,
+    block: Option<Option<Duration>>,
+    no_ack: bool,
+    /// The position of `STREAMS`.
+    streams: usize,
+    keys: &'a [Vec<u8>],
+    ids: &'a [Vec<u8>],
+}
+
+impl ReadArgs<'_> {
+    fn parse(args: &[Vec<u8>], is_xreadgroup: bool) -> Result<ReadArgs<'_>> {
+        let mut read = ReadArgs {
+            group: None,
+            count: None,
+            block: None,
+            no_ack: false,
+            streams: 0,
+            keys: &[],
+            ids: &[],
+        };
+        let mut i = 1;
+        loop {
+            let option = args.get(i).ok_or_else(syntax_error)?;
+            match option.to_ascii_lowercase().as_slice() {
+                b"count" => {
+                    let value = parse_integer(args.get(i + 1).ok_or_else(syntax_error)?)?;
+                    read.count = usize::try_from(value).ok().filter(|&count| count > 0);
+                    i += 1;
+                }
+                b"block" => {
+                    read.block = Some(parse_block_timeout(args.get(i + 1).ok_or_else(syntax_error)?)?);
+                    i += 1;
+                }
+                b"group" if is_xreadgroup => {
+                    match &args[i + 1..] {
+                        [group, consumer, ..] => read.group = Some((group, consumer)),
+                        _ => return Err(syntax_error()),
+                    }
+                    i += 2;
+                }
+                b"noack" if is_xreadgroup => read.no_ack = true,
+                b"streams" => break,
+                _ => return Err(syntax_error()),
+            }
+            i += 1;
+        }
+        let streams = &args[i + 1..];
+        if streams.is_empty() || !streams.len().is_multiple_of(2) {
+            let (name, special_id) = if is_xreadgroup { ("xreadgroup", ">") } else { ("xread", "$") };
+            return Err(Error::ValidationError(format!(
+                "Unbalanced '{}' list of streams: for each stream key an ID or '{}' must be specified.",
+                name, special_id,
+            )));
+        }
+        if is_xreadgroup && read.group.is_none() {
+            return Err(Error::ValidationError("Missing GROUP option for XREADGROUP".to_string()));
+        }
+        (read.keys, read.ids) = streams.split_at(streams.len() / 2);
+        read.streams = i;
+        Ok(read)
+    }
+}
+
+/// Replies with the entries after the given IDs, where `$` is the last ID of a stream and `+`
+/// the ID before its last entry. If there are none, waits for entries if `BLOCK` is given.
+fn xread(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let read = ReadArgs::parse(args, false)?;
+    let mut after = Vec::with_capacity(read.keys.len());
+    for (key, id) in read.keys.iter().zip(read.ids) {
+        let stream = ctx.get_stream(key)?;
+        after.push(match id.as_slice() {
+            b"$" => stream.map_or(StreamId::MIN, Stream::last_id),
+            b"+" => match stream.and_then(Stream::last_entry) {
+                Some((last_id, _)) => last_id.previous().expect("0-0 is never an entry ID"),
+                None => stream.map_or(StreamId::MIN, Stream::last_id),
+            },
+            b">" => {
+                return Err(Error::ValidationError(
+                    "The > ID can be specified only when calling XREADGROUP using the GROUP <group> <consumer> option."
+                        .to_string(),
+                ))
+            }
+            _ => parse_id(id, 0)?,
+        });
+    }
+    let mut streams = Vec::new();
+    for (key, &after) in read.keys.iter().zip(&after) {
+        let (stream, start) = match (ctx.get_stream(key)?, after.next()) {
+            (Some(stream), Some(start)) => (stream, start),
+            _ => continue,
+        };
+        let entries = stream.range(start, StreamId::MAX, false, read.count);
+        if !entries.is_empty() {
+            streams.push((key.clone(), entries_reply(entries)));
+        }
+    }
+    if !streams.is_empty() {
+        return Ok(streams_reply(ctx.client.protocol, streams));
+    }
+    if let Some(timeout) = read.block {
+        // Wait for entries after the IDs `$` and `+` stand for now, not when woken up.
+        let mut resolved = args[..=read.streams + read.keys.len()].to_vec();
+        resolved.extend(after.iter().map(|id| id.to_string().into_bytes()));
+        ctx.block_on_with_args(read.keys, timeout, resolved);
+    }
+    Ok(RespVal::NullArray)
+}
+
+fn lossy(bytes: &[u8]) -> std::borrow::Cow<'_, str> {
+    String::from_utf8_lossy(bytes)
+}
+
+fn no_such_key_or_group(key: &[u8], group: &[u8]) -> Error {
+    Error::NoGroup(format!("No such key '{}' or consumer group '{}'", lossy(key), lossy(group)))
+}
+
+fn no_such_group(key: &[u8], group: &[u8]) -> Error {
+    Error::NoGroup(format!("No such consumer group '{}' for key name '{}'", lossy(group), lossy(key)))
+}
+
+/// Replies with new entries, for the ID `>`, or with entries pending for the consumer after
+/// the given ID. Waits for new entries if there are none and `BLOCK` is given.
+fn xreadgroup(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let read = ReadArgs::parse(args, true)?;
+    let (group, consumer) = read.group.expect("the group is mandatory");
+    // Every ID, key and group is checked before anything is delivered.
+    for (key, id) in read.keys.iter().zip(read.ids) {
+        match id.as_slice() {
+            b">" => {}
+            b"$" => {
+                return Err(Error::ValidationError(
+                    "The $ ID is meaningless in the context of XREADGROUP: you want to read the history of this consumer by \
+                     specifying a proper ID, or use the > ID to get new messages. The $ ID would just return an empty result set."
+                        .to_string(),
+                ))
+            }
+            _ => {
+                parse_id(id, 0)?;
+            }
+        }
+        if ctx.get_stream(key)?.is_none_or(|stream| stream.group(group).is_none()) {
+            return Err(Error::NoGroup(format!(
+                "No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                lossy(key),
+                lossy(group),
+            )));
+        }
+    }
+    let now = now_millis();
+    let mut streams = Vec::new();
+    for (key, id) in read.keys.iter().zip(read.ids) {
+        let stream = ctx.get_stream_mut(key)?.expect("the stream was checked");
+        stream.group_mut(group).expect("the group was checked").touch_consumer(consumer, now);
+        if id == b">" {
+            let entries = stream.deliver_new(group, consumer, read.count, read.no_ack, now);
+            if !entries.is_empty() {
+                let entries = entries.iter().map(|(id, fields)| entry_reply(*id, fields)).collect();
+                streams.push((key.clone(), RespVal::Array(entries)));
+            }
+            continue;
+        }
+        // The history is replied even if there is none, deleted entries without fields.
+        let group = stream.group_mut(group).expect("the group was checked");
+        let pending = group.consumer_pending_after(consumer, parse_id(id, 0)?, read.count);
+        for &id in &pending {
+            group.redeliver(id, now);
+        }
+        let entries = pending
+            .into_iter()
+            .map(|id| match stream.get(id) {
+                Some(fields) => entry_reply(id, fields),
+                None => RespVal::Array(vec![RespVal::BulkString(id.to_string().into_bytes()), RespVal::NullArray]),
+            })
+            .collect();
+        streams.push((key.clone(), RespVal::Array(entries)));
+    }
+    if !streams.is_empty() {
+        return Ok(streams_reply(ctx.client.protocol, streams));
+    }
+    if let Some(timeout) = read.block {
+        ctx.block_on(read.keys, timeout);
+    }
+    Ok(RespVal::NullArray)
+}
+
+fn xack(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    // Every ID is validated before anything is acknowledged.
+    let ids = args[3..].iter().map(|id| parse_id(id, 0)).collect::<Result<Vec<_>>>()?;
+    let group = match ctx.get_stream_mut(&args[1])?.and_then(|stream| stream.group_mut(&args[2])) {
+        Some(group) => group,
+        None => return Ok(RespVal::Integer(0)),
+    };
+    let acknowledged = ids.into_iter().filter(|&id| group.acknowledge(id)).count();
+    Ok(RespVal::Integer(acknowledged as i64))
+}
+
+fn bulk_id(id: StreamId) -> RespVal {
+    RespVal::BulkString(id.to_string().into_bytes())
+}
+
+/// Replies with a summary of the pending entries of a group, or with the pending entries in a
+/// range: `[[IDLE min-idle-time] start end count [consumer]]`.
+fn xpending(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (key, group_name) = (&args[1], &args[2]);
+    let (min_idle, range) = match &args[3..] {
+        [option, min_idle, range @ ..] if option.eq_ignore_ascii_case(b"idle") => (Some(parse_integer(min_idle)?), range),
+        range => (None, range),
+    };
+    let range = match range {
+        [] if min_idle.is_none() => None,
+        [start, end, count, consumer @ ..] if consumer.len() <= 1 => Some((
+            parse_interval_end(start, true)?,
+            parse_interval_end(end, false)?,
+            parse_integer(count)?.max(0) as usize,
+            consumer.first(),
+        )),
+        _ => return Err(syntax_error()),
+    };
+    let group = ctx
+        .get_stream(key)?
+        .and_then(|stream| stream.group(group_name))
+        .ok_or_else(|| no_such_key_or_group(key, group_name))?;
+    let now = now_millis();
+    let (start, end, count, consumer) = match range {
+        Some(range) => range,
+        None if group.pending_len() == 0 => {
+            return Ok(RespVal::Array(vec![RespVal::Integer(0), RespVal::Null, RespVal::Null, RespVal::NullArray]))
+        }
+        None => {
+            let mut pending = group.pending_range(StreamId::MIN, StreamId::MAX);
+            let (first, _) = pending.next().expect("there are pending entries");
+            let last = pending.next_back().map_or(first, |(last, _)| last);
+            let consumers = group
+                .consumers()
+                .filter(|(_, consumer)| consumer.pending_len() > 0)
+                .map(|(name, consumer)| {
+                    RespVal::Array(vec![
+                        RespVal::BulkString(name.clone()),
+                        RespVal::BulkString(consumer.pending_len().to_string().into_bytes()),
+                    ])
+                })
+                .collect();
+            return Ok(RespVal::Array(vec![
+                RespVal::Integer(group.pending_len() as i64),
+                bulk_id(first),
+                bulk_id(last),
+                RespVal::Array(consumers),
+            ]));
+        }
+    };
+    let min_idle = min_idle.unwrap_or(0);
+    let entries = group
+        .pending_range(start, end)
+        .filter(|(_, entry)| consumer.is_none_or(|consumer| &entry.consumer == consumer))
+        .filter(|(_, entry)| now.saturating_sub(entry.delivery_time) as i64 >= min_idle)
+        .take(count)
+        .map(|(id, entry)| {
+            RespVal::Array(vec![
+                bulk_id(id),
+                RespVal::BulkString(entry.consumer.clone()),
+                RespVal::Integer(now.saturating_sub(entry.delivery_time) as i64),
+                RespVal::Integer(entry.delivery_count as i64),
+            ])
+        })
+        .collect();
+    Ok(RespVal::Array(entries))
+}
+
+/// Parses a non-negative number of milliseconds, replying with `error` otherwise.
+fn parse_millis(arg: &[u8], error: &str) -> Result<u64> {
+    parse_integer(arg)
+        .ok()
+        .map(|millis| millis.max(0) as u64)
+        .ok_or_else(|| Error::ValidationError(error.to_string()))
+}
+
+/// Gives pending entries that have been idle long enough to another consumer: `key group
+/// consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-milliseconds] [RETRYCOUNT
+/// count] [FORCE] [JUSTID] [LASTID lastid]`.
+fn xclaim(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (key, group_name, consumer) = (&args[1], &args[2], &args[3]);
+    let min_idle = parse_millis(&args[4], "Invalid min-idle-time argument for XCLAIM")?;
+    let ids: Vec<StreamId> = args[5..].iter().map_while(|arg| StreamId::parse(arg, 0)).collect();
+    let now = now_millis();
+    let mut delivery_time = now;
+    let (mut retry_count, mut force, mut just_id, mut last_id) = (None, false, false, None);
+    let mut options = args[5 + ids.len()..].iter();
+    while let Some(option) = options.next() {
+        let mut value = || options.next().ok_or_else(syntax_error);
+        match option.to_ascii_lowercase().as_slice() {
+            b"idle" => delivery_time = now.saturating_sub(parse_millis(value()?, "Invalid IDLE option argument for XCLAIM")?),
+            b"time" => delivery_time = parse_millis(value()?, "Invalid TIME option argument for XCLAIM")?.min(now),
+            b"retrycount" => retry_count = Some(parse_millis(value()?, "Invalid RETRYCOUNT option argument for XCLAIM")?),
+            b"force" => force = true,
+            b"justid" => just_id = true,
+            b"lastid" => last_id = Some(parse_id(value()?, 0)?),
+            _ => {
+                return Err(Error::ValidationError(format!("Unrecognized XCLAIM option '{}'", lossy(option))));
+            }
+        }
+    }
+    let stream = match ctx.get_stream_mut(key)? {
+        Some(stream) if stream.group(group_name).is_some() => stream,
+        _ => return Err(no_such_key_or_group(key, group_name)),
+    };
+    let group = stream.group_mut(group_name).expect("the group was checked");
+    if let Some(last_id) = last_id.filter(|&last_id| last_id > group.last_delivered_id()) {
+        let entries_read = group.entries_read();
+        group.set_last_delivered_id(last_id, entries_read);
+    }
+    group.touch_consumer(consumer, now);
+    let mut claimed = Vec::new();
+    for id in ids {
+        let fields = stream.get(id).cloned();
+        let group = stream.group_mut(group_name).expect("the group was checked");
+        let delivery_count = match (group.pending_entry(id), &fields) {
+            // The entry was deleted, so there's nothing left to process.
+            (Some(_), None) => {
+                group.acknowledge(id);
+                continue;
+            }
+            (Some(entry), Some(_)) if now.saturating_sub(entry.delivery_time) < min_idle => continue,
+            (Some(entry), Some(_)) => entry.delivery_count,
+            (None, Some(_)) if force => 1,
+            (None, _) => continue,
+        };
+        let delivery_count = match retry_count {
+            Some(retry_count) => retry_count,
+            None if just_id => delivery_count,
+            None => delivery_count + 1,
+        };
+        group.assign(id, consumer, delivery_time, delivery_count);
+        group.mark_active(consumer, now);
+        claimed.push(match fields {
+            Some(fields) if !just_id => entry_reply(id, &fields),
+            _ => bulk_id(id),
+        });
+    }
+    Ok(RespVal::Array(claimed))
+}
+
+/// Like `XCLAIM` for the pending entries from `start` on: `key group consumer min-idle-time
+/// start [COUNT count] [JUSTID]`. Replies with the ID to continue from, the claimed entries,
+/// and the IDs of deleted entries that were dropped from the pending entries.
+fn xautoclaim(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (key, group_name, consumer) = (&args[1], &args[2], &args[3]);
+    let min_idle = parse_millis(&args[4], "Invalid min-idle-time argument for XAUTOCLAIM")?;
+    let start = parse_interval_end(&args[5], true)?;
+    let (mut count, mut just_id) = (100, false);
+    let mut options = args[6..].iter();
+    while let Some(option) = options.next() {
+        match option.to_ascii_lowercase().as_slice() {
+            b"count" => {
+                let value = parse_integer(options.next().ok_or_else(syntax_error)?)?;
+                // Redis limits the count so that the number of attempts can't overflow.
+                count = usize::try_from(value)
+                    .ok()
+                    .filter(|&count| count > 0 && count <= usize::MAX / 10)
+                    .ok_or_else(|| Error::ValidationError("COUNT must be > 0".to_string()))?;
+            }
+            b"justid" => just_id = true,
+            _ => return Err(syntax_error()),
+        }
+    }
+    let stream = match ctx.get_stream_mut(key)? {
+        Some(stream) if stream.group(group_name).is_some() => stream,
+        _ => return Err(no_such_key_or_group(key, group_name)),
+    };
+    let now = now_millis();
+    let group = stream.group_mut(group_name).expect("the group was checked");
+    group.touch_consumer(consumer, now);
+    // Look at no more than 10 entries per entry to claim, plus one for where to continue.
+    let attempts = count * 10;
+    let mut scanned: Vec<(StreamId, PendingEntry)> = group
+        .pending_range(start, StreamId::MAX)
+        .take(attempts + 1)
+        .map(|(id, entry)| (id, entry.clone()))
+        .collect();
+    let next = if scanned.len() > attempts { scanned.pop().map(|(id, _)| id) } else { None };
+    let (mut claimed, mut deleted) = (Vec::new(), Vec::new());
+    let mut scanned = scanned.into_iter();
+    for (id, entry) in scanned.by_ref() {
+        let fields = stream.get(id).cloned();
+        let group = stream.group_mut(group_name).expect("the group was checked");
+        let fields = match fields {
+            Some(fields) => fields,
+            None => {
+                group.acknowledge(id);
+                deleted.push(bulk_id(id));
+                continue;
+            }
+        };
+        if now.saturating_sub(entry.delivery_time) < min_idle {
+            continue;
+        }
+        let delivery_count = if just_id { entry.delivery_count } else { entry.delivery_count + 1 };
+        group.assign(id, consumer, now, delivery_count);
+        group.mark_active(consumer, now);
+        claimed.push(if just_id { bulk_id(id) } else { entry_reply(id, &fields) });
+        if claimed.len() == count {
+            break;
+        }
+    }
+    // Continue from the first entry not looked at, or start over.
+    let next = scanned.next().map(|(id, _)| id).or(next).unwrap_or(StreamId::MIN);
+    Ok(RespVal::Array(vec![bulk_id(next), RespVal::Array(claimed), RespVal::Array(deleted)]))
+}
+
+fn key_must_exist() -> Error {
+    Error::ValidationError(
+        "The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to \
+         create an empty stream automatically."
+            .to_string(),
+    )
+}
+
+/// Parses the options of `XGROUP CREATE` and `XGROUP SETID`: `MKSTREAM`, if `allow_mkstream`,
+/// and `ENTRIESREAD n`, where -1 means unknown.
+fn parse_group_options(args: &[Vec<u8>], allow_mkstream: bool) -> Result<(bool, Option<u64>)> {
+    let (mut mkstream, mut entries_read) = (false, None);
+    let mut options = args.iter();
+    while let Some(option) = options.next() {
+        match option.to_ascii_lowercase().as_slice() {
+            b"mkstream" if allow_mkstream => mkstream = true,
+            b"entriesread" => {
+                let value = parse_integer(options.next().ok_or_else(syntax_error)?)?;
+                entries_read = match value {
+                    -1 => None,
+                    value => Some(u64::try_from(value).map_err(|_| {
+                        Error::ValidationError("value for ENTRIESREAD must be positive or -1".to_string())
+                    })?),
+                };
+            }
+            _ => return Err(syntax_error()),
+        }
+    }
+    Ok((mkstream, entries_read))
+}
+
+/// Parses the ID a group starts from, `None` standing for the last ID with `$`.
+fn parse_group_id(arg: &[u8]) -> Result<Option<StreamId>> {
+    match arg {
+        b"$" => Ok(None),
+        _ => Ok(Some(parse_id(arg, 0)?)),
+    }
+}
+
+fn xgroup_create(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (key, name) = (&args[2], &args[3]);
+    let id = parse_group_id(&args[4])?;
+    let (mkstream, entries_read) = parse_group_options(&args[5..], true)?;
+    match ctx.get_stream(key)? {
+        Some(_) => {}
+        None if mkstream => {
+            ctx.db.insert(key.to_vec(), Value::new(Data::Stream(Stream::default())));
+        }
+        None => return Err(key_must_exist()),
+    }
+    let stream = ctx.get_stream_mut(key)?.expect("the stream exists");
+    let id = id.unwrap_or(stream.last_id());
+    let entries_read = entries_read.unwrap_or_else(|| stream.entries_read_up_to(id));
+    if !stream.create_group(name, ConsumerGroup::new(id, entries_read)) {
+        return Err(Error::BusyGroup);
+    }
+    Ok(ok())
+}
+
+fn xgroup_setid(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (key, name) = (&args[2], &args[3]);
+    let id = parse_group_id(&args[4])?;
+    let (_, entries_read) = parse_group_options(&args[5..], false)?;
+    let stream = ctx.get_stream_mut(key)?.ok_or_else(key_must_exist)?;
+    let id = id.unwrap_or(stream.last_id());
+    let entries_read = entries_read.unwrap_or_else(|| stream.entries_read_up_to(id));
+    let group = stream.group_mut(name).ok_or_else(|| no_such_group(key, name))?;
+    group.set_last_delivered_id(id, entries_read);
+    Ok(ok())
+}
+
+fn xgroup_destroy(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[2];
+    let destroyed = ctx.get_stream_mut(key)?.ok_or_else(key_must_exist)?.destroy_group(&args[3]);
+    if destroyed {
+        // Clients blocked reading from the group get an error.
+        ctx.db.signal_key_as_ready(key);
+    }
+    Ok(RespVal::Integer(destroyed as i64))
+}
+
+fn xgroup_createconsumer(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (key, name) = (&args[2], &args[3]);
+    let stream = ctx.get_stream_mut(key)?.ok_or_else(key_must_exist)?;
+    let group = stream.group_mut(name).ok_or_else(|| no_such_group(key, name))?;
+    Ok(RespVal::Integer(group.create_consumer(&args[4], now_millis()) as i64))
+}
+
+/// Deletes a consumer, replying with the number of entries that were pending for it.
+fn xgroup_delconsumer(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (key, name) = (&args[2], &args[3]);
+    let stream = ctx.get_stream_mut(key)?.ok_or_else(key_must_exist)?;
+    let group = stream.group_mut(name).ok_or_else(|| no_such_group(key, name))?;
+    Ok(RespVal::Integer(group.delete_consumer(&args[4]).unwrap_or(0) as i64))
+}
+
+fn no_such_key() -> Error {
+    Error::ValidationError("no such key".to_string())
+}
+
+fn info_map(fields: Vec<(&str, RespVal)>) -> RespVal {
+    RespVal::Map(
+        fields
+            .into_iter()
+            .map(|(name, value)| (RespVal::BulkString(name.as_bytes().to_vec()), value))
+            .collect(),
+    )
+}
+
+fn xinfo_stream(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    if args.len() > 3 {
+        return Err(syntax_error());
+    }
+    let stream = ctx.get_stream(&args[2])?.ok_or_else(no_such_key)?;
+    let first_entry = stream.first_entry();
+    // Like Redis, as if the entries were kept in radix tree nodes.
+    let nodes = stream.len().div_ceil(NODE_MAX_ENTRIES);
+    Ok(info_map(vec![
+        ("length", RespVal::Integer(stream.len() as i64)),
+        ("radix-tree-keys", RespVal::Integer(nodes as i64)),
+        ("radix-tree-nodes", RespVal::Integer(nodes as i64 + 1)),
+        ("last-generated-id", bulk_id(stream.last_id())),
+        ("max-deleted-entry-id", bulk_id(stream.max_deleted_id())),
+        ("entries-added", RespVal::Integer(stream.entries_added() as i64)),
+        ("recorded-first-entry-id", bulk_id(first_entry.map_or(StreamId::MIN, |(id, _)| id))),
+        ("groups", RespVal::Integer(stream.groups().count() as i64)),
+        ("first-entry", first_entry.map_or(RespVal::Null, |(id, fields)| entry_reply(id, fields))),
+        ("last-entry", stream.last_entry().map_or(RespVal::Null, |(id, fields)| entry_reply(id, fields))),
+    ]))
+}
+
+fn xinfo_groups(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let stream = ctx.get_stream(&args[2])?.ok_or_else(no_such_key)?;
+    let groups = stream
+        .groups()
+        .map(|(name, group)| {
+            info_map(vec![
+                ("name", RespVal::BulkString(name.clone())),
+                ("consumers", RespVal::Integer(group.consumers().count() as i64)),
+                ("pending", RespVal::Integer(group.pending_len() as i64)),
+                ("last-delivered-id", bulk_id(group.last_delivered_id())),
+                ("entries-read", RespVal::Integer(group.entries_read() as i64)),
+                ("lag", RespVal::Integer(stream.count_after(group.last_delivered_id()) as i64)),
+            ])
+        })
+        .collect();
+    Ok(RespVal::Array(groups))
+}
+
+fn xinfo_consumers(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (key, name) = (&args[2], &args[3]);
+    let stream = ctx.get_stream(key)?.ok_or_else(no_such_key)?;
+    let group = stream.group(name).ok_or_else(|| no_such_group(key, name))?;
+    let now = now_millis();
+    let consumers = group
+        .consumers()
+        .map(|(name, consumer)| {
+            let inactive = consumer.active_time.map_or(-1, |active_time| now.saturating_sub(active_time) as i64);
+            info_map(vec![
+                ("name", RespVal::BulkString(name.clone())),
+                ("pending", RespVal::Integer(consumer.pending_len() as i64)),
+                ("idle", RespVal::Integer(now.saturating_sub(consumer.seen_time) as i64)),
+                ("inactive", RespVal::Integer(inactive)),
+            ])
+        })
+        .collect();
+    Ok(RespVal::Array(consumers))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{new_database, run, run_blocked};
+    use super::*;
+    use crate::client::Client;
+
+    fn bulk(value: &str) -> RespVal {
+        RespVal::BulkString(value.as_bytes().to_vec())
+    }
+
+    fn entry(id: &str, fields: &[&str]) -> RespVal {
+        RespVal::Array(vec![bulk(id), RespVal::Array(fields.iter().map(|field| bulk(field)).collect())])
+    }
+
+    #[test]
+    fn test_xadd_ids() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(run(&map, &mut client, &["xadd", "s", "0-*", "f", "v"]).unwrap(), bulk("0-1"));
+        assert_eq!(run(&map, &mut client, &["xadd", "s", "5-*", "f", "v"]).unwrap(), bulk("5-0"));
+        assert_eq!(run(&map, &mut client, &["xadd", "s", "5-*", "f", "v"]).unwrap(), bulk("5-1"));
+        assert_eq!(run(&map, &mut client, &["xadd", "s", "7", "f", "v"]).unwrap(), bulk("7-0"));
+        assert!(run(&map, &mut client, &["xadd", "s", "7-0", "f", "v"]).is_err());
+        assert!(run(&map, &mut client, &["xadd", "s", "6-*", "f", "v"]).is_err());
+        assert!(run(&map, &mut client, &["xadd", "other", "0-0", "f", "v"]).is_err());
+        assert!(run(&map, &mut client, &["xadd", "s", "x-1", "f", "v"]).is_err());
+        assert!(run(&map, &mut client, &["xadd", "s", "*", "f"]).is_err());
+        let auto = match run(&map, &mut client, &["xadd", "s", "*", "f", "v"]).unwrap() {
+            RespVal::BulkString(id) => StreamId::parse(&id, 0).unwrap(),
+            reply => panic!("unexpected reply {:?}", reply),
+        };
+        assert!(auto > StreamId::new(7, 0) && auto.ms >= now_millis() - 1000);
+        run(&map, &mut client, &["xadd", "future", &format!("{}-5", u64::MAX - 1), "f", "v"]).unwrap();
+        // The clock is far behind the last ID, which keeps increasing.
+        assert_eq!(
+            run(&map, &mut client, &["xadd", "future", "*", "f", "v"]).unwrap(),
+            bulk(&format!("{}-6", u64::MAX - 1))
+        );
+        assert_eq!(run(&map, &mut client, &["xadd", "missing", "nomkstream", "*", "f", "v"]).unwrap(), RespVal::Null);
+        assert_eq!(run(&map, &mut client, &["exists", "missing"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["xlen", "s"]).unwrap(), RespVal::Integer(5));
+        assert_eq!(run(&map, &mut client, &["type", "s"]).unwrap(), RespVal::SimpleString(b"stream".to_vec()));
+    }
+
+    #[test]
+    fn test_ranges_and_trimming() {
+        let map = new_database();
+        let mut client = Client::new();
+        for id in ["1-1", "1-2", "2-1", "3-1"] {
+            run(&map, &mut client, &["xadd", "s", id, "id", id]).unwrap();
+        }
+        assert_eq!(
+            run(&map, &mut client, &["xrange", "s", "1", "(2-1"]).unwrap(),
+            RespVal::Array(vec![entry("1-1", &["id", "1-1"]), entry("1-2", &["id", "1-2"])])
+        );
+        assert_eq!(
+            run(&map, &mut client, &["xrevrange", "s", "+", "-", "count", "1"]).unwrap(),
+            RespVal::Array(vec![entry("3-1", &["id", "3-1"])])
+        );
+        assert_eq!(run(&map, &mut client, &["xrange", "s", "-", "+", "count", "0"]).unwrap(), RespVal::NullArray);
+        assert_eq!(run(&map, &mut client, &["xrange", "s", "3", "1"]).unwrap(), RespVal::Array(Vec::new()));
+        assert!(run(&map, &mut client, &["xrange", "s", "(-", "+"]).is_err());
+        assert_eq!(run(&map, &mut client, &["xdel", "s", "1-2", "9-9"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["xtrim", "s", "minid", "2"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["xadd", "s", "maxlen", "=", "1", "4-1", "id", "4-1"]).unwrap(), bulk("4-1"));
+        assert_eq!(run(&map, &mut client, &["xlen", "s"]).unwrap(), RespVal::Integer(1));
+        // Approximate trimming only removes whole nodes.
+        assert_eq!(run(&map, &mut client, &["xtrim", "s", "maxlen", "~", "0"]).unwrap(), RespVal::Integer(0));
+        assert!(run(&map, &mut client, &["xtrim", "s", "maxlen", "0", "limit", "10"]).is_err());
+        assert!(run(&map, &mut client, &["xtrim", "s", "maxlen", "-1"]).is_err());
+        assert!(run(&map, &mut client, &["xtrim", "s", "maxlen", "1", "minid", "1"]).is_err());
+        assert!(run(&map, &mut client, &["xtrim", "s", "limit", "1"]).is_err());
+        assert_eq!(run(&map, &mut client, &["xtrim", "s", "maxlen", "0"]).unwrap(), RespVal::Integer(1));
+        // Empty streams are kept, along with their last ID.
+        assert_eq!(run(&map, &mut client, &["xlen", "s"]).unwrap(), RespVal::Integer(0));
+        assert!(run(&map, &mut client, &["xadd", "s", "4-1", "f", "v"]).is_err());
+    }
+
+    #[test]
+    fn test_xread() {
+        let map = new_database();
+        let (mut reader, mut writer) = (Client::new(), Client::new());
+        run(&map, &mut writer, &["xadd", "a", "1-1", "f", "1"]).unwrap();
+        run(&map, &mut writer, &["xadd", "a", "1-2", "f", "2"]).unwrap();
+        assert_eq!(
+            run(&map, &mut reader, &["xread", "count", "1", "streams", "a", "b", "0", "0"]).unwrap(),
+            RespVal::Array(vec![RespVal::Array(vec![bulk("a"), RespVal::Array(vec![entry("1-1", &["f", "1"])])])])
+        );
+        reader.protocol = ProtocolVersion::Resp3;
+        assert_eq!(
+            run(&map, &mut reader, &["xread", "streams", "a", "+"]).unwrap(),
+            RespVal::Map(vec![(bulk("a"), RespVal::Array(vec![entry("1-2", &["f", "2"])]))])
+        );
+        assert_eq!(run(&map, &mut reader, &["xread", "streams", "a", "$"]).unwrap(), RespVal::NullArray);
+        assert!(run(&map, &mut reader, &["xread", "streams", "a", "b", "0"]).is_err());
+        assert!(run(&map, &mut reader, &["xread", "block", "-1", "streams", "a", "0"]).is_err());
+        let mut blocked = run_blocked(&map, &mut reader, &["xread", "block", "0", "streams", "b", "a", "$", "$"]);
+        assert_eq!(blocked.timeout, None);
+        // `$` stands for 1-2, the last ID when the client blocked.
+        run(&map, &mut writer, &["xadd", "a", "2-1", "f", "3"]).unwrap();
+        assert_eq!(
+            blocked.receiver.try_recv().unwrap().unwrap(),
+            RespVal::Map(vec![(bulk("a"), RespVal::Array(vec![entry("2-1", &["f", "3"])]))])
+        );
+        assert_eq!(map.lock().unwrap().blocked.count(), 0);
+    }
+
+    #[test]
+    fn test_consumer_groups() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert!(run(&map, &mut client, &["xgroup", "create", "s", "g", "$"]).is_err());
+        assert_eq!(run(&map, &mut client, &["xgroup", "create", "s", "g", "$", "mkstream"]).unwrap(), ok());
+        assert!(matches!(run(&map, &mut client, &["xgroup", "create", "s", "g", "0"]), Err(Error::BusyGroup)));
+        for id in ["1-1", "1-2", "1-3"] {
+            run(&map, &mut client, &["xadd", "s", id, "id", id]).unwrap();
+        }
+        assert_eq!(
+            run(&map, &mut client, &["xreadgroup", "group", "g", "alice", "count", "2", "streams", "s", ">"]).unwrap(),
+            RespVal::Array(vec![RespVal::Array(vec![
+                bulk("s"),
+                RespVal::Array(vec![entry("1-1", &["id", "1-1"]), entry("1-2", &["id", "1-2"])]),
+            ])])
+        );
+        run(&map, &mut client, &["xreadgroup", "group", "g", "bob", "streams", "s", ">"]).unwrap();
+        assert_eq!(
+            run(&map, &mut client, &["xreadgroup", "group", "g", "bob", "streams", "s", ">"]).unwrap(),
+            RespVal::NullArray
+        );
+        assert_eq!(
+            run(&map, &mut client, &["xpending", "s", "g"]).unwrap(),
+            RespVal::Array(vec![
+                RespVal::Integer(3),
+                bulk("1-1"),
+                bulk("1-3"),
+                RespVal::Array(vec![
+                    RespVal::Array(vec![bulk("alice"), bulk("2")]),
+                    RespVal::Array(vec![bulk("bob"), bulk("1")]),
+                ]),
+            ])
+        );
+        assert_eq!(run(&map, &mut client, &["xack", "s", "g", "1-1", "1-3", "9-9"]).unwrap(), RespVal::Integer(2));
+        assert!(run(&map, &mut client, &["xack", "s", "g", "bad"]).is_err());
+        // Reading the history redelivers what is still pending.
+        assert_eq!(
+            run(&map, &mut client, &["xreadgroup", "group", "g", "alice", "streams", "s", "0"]).unwrap(),
+            RespVal::Array(vec![RespVal::Array(vec![bulk("s"), RespVal::Array(vec![entry("1-2", &["id", "1-2"])])])])
+        );
+        match run(&map, &mut client, &["xpending", "s", "g", "-", "+", "10", "alice"]).unwrap() {
+            RespVal::Array(entries) => match entries.as_slice() {
+                [RespVal::Array(entry)] => {
+                    assert_eq!(entry[0], bulk("1-2"));
+                    assert_eq!(entry[3], RespVal::Integer(2));
+                }
+                _ => panic!("unexpected entries {:?}", entries),
+            },
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+        assert_eq!(
+            run(&map, &mut client, &["xreadgroup", "group", "g", "bob", "streams", "s", "0"]).unwrap(),
+            RespVal::Array(vec![RespVal::Array(vec![bulk("s"), RespVal::Array(Vec::new())])])
+        );
+        assert!(matches!(
+            run(&map, &mut client, &["xreadgroup", "group", "nope", "bob", "streams", "s", ">"]),
+            Err(Error::NoGroup(_))
+        ));
+        assert!(run(&map, &mut client, &["xreadgroup", "group", "g", "bob", "streams", "s", "$"]).is_err());
+        assert!(run(&map, &mut client, &["xread", "streams", "s", ">"]).is_err());
+        assert_eq!(run(&map, &mut client, &["xgroup", "delconsumer", "s", "g", "alice"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(
+            run(&map, &mut client, &["xpending", "s", "g"]).unwrap(),
+            RespVal::Array(vec![RespVal::Integer(0), RespVal::Null, RespVal::Null, RespVal::NullArray])
+        );
+        assert_eq!(run(&map, &mut client, &["xgroup", "setid", "s", "g", "1-1"]).unwrap(), ok());
+        assert_eq!(
+            run(&map, &mut client, &["xinfo", "groups", "s"]).unwrap(),
+            RespVal::Array(vec![info_map(vec![
+                ("name", bulk("g")),
+                ("consumers", RespVal::Integer(1)),
+                ("pending", RespVal::Integer(0)),
+                ("last-delivered-id", bulk("1-1")),
+                ("entries-read", RespVal::Integer(1)),
+                ("lag", RespVal::Integer(2)),
+            ])])
+        );
+        assert_eq!(run(&map, &mut client, &["xgroup", "destroy", "s", "g"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["xgroup", "destroy", "s", "g"]).unwrap(), RespVal::Integer(0));
+    }
+
+    #[test]
+    fn test_claiming() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["xgroup", "create", "s", "g", "0", "mkstream"]).unwrap();
+        for id in ["1-1", "1-2", "1-3"] {
+            run(&map, &mut client, &["xadd", "s", id, "id", id]).unwrap();
+        }
+        run(&map, &mut client, &["xreadgroup", "group", "g", "alice", "streams", "s", ">"]).unwrap();
+        // Nothing has been idle for an hour yet.
+        assert_eq!(
+            run(&map, &mut client, &["xclaim", "s", "g", "bob", "3600000", "1-1"]).unwrap(),
+            RespVal::Array(Vec::new())
+        );
+        assert_eq!(
+            run(&map, &mut client, &["xclaim", "s", "g", "bob", "0", "1-1", "9-9", "justid"]).unwrap(),
+            RespVal::Array(vec![bulk("1-1")])
+        );
+        assert_eq!(
+            run(&map, &mut client, &["xclaim", "s", "g", "bob", "0", "9-9", "force"]).unwrap(),
+            RespVal::Array(Vec::new())
+        );
+        assert!(run(&map, &mut client, &["xclaim", "s", "g", "bob", "0", "1-1", "bogus"]).is_err());
+        assert!(run(&map, &mut client, &["xclaim", "s", "g", "bob", "-", "1-1"]).is_err());
+        run(&map, &mut client, &["xdel", "s", "1-2"]).unwrap();
+        assert_eq!(
+            run(&map, &mut client, &["xautoclaim", "s", "g", "carol", "0", "0", "count", "1"]).unwrap(),
+            RespVal::Array(vec![
+                bulk("1-2"),
+                RespVal::Array(vec![entry("1-1", &["id", "1-1"])]),
+                RespVal::Array(Vec::new()),
+            ])
+        );
+        assert_eq!(
+            run(&map, &mut client, &["xautoclaim", "s", "g", "carol", "0", "1-2", "justid"]).unwrap(),
+            RespVal::Array(vec![bulk("0-0"), RespVal::Array(vec![bulk("1-3")]), RespVal::Array(vec![bulk("1-2")])])
+        );
+        assert!(run(&map, &mut client, &["xautoclaim", "s", "g", "carol", "0", "0", "count", "0"]).is_err());
+        match run(&map, &mut client, &["xinfo", "consumers", "s", "g"]).unwrap() {
+            RespVal::Array(consumers) => assert_eq!(consumers.len(), 3),
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+        match run(&map, &mut client, &["xpending", "s", "g", "-", "+", "10", "carol"]).unwrap() {
+            RespVal::Array(entries) => assert_eq!(entries.len(), 2),
+            reply => panic!("unexpected reply {:?}", reply),
+        }
+    }
+
+    #[test]
+    fn test_blocking_xreadgroup() {
+        let map = new_database();
+        let (mut reader, mut writer) = (Client::new(), Client::new());
+        run(&map, &mut writer, &["xgroup", "create", "s", "g", "$", "mkstream"]).unwrap();
+        let mut blocked = run_blocked(&map, &mut reader, &["xreadgroup", "group", "g", "c", "block", "0", "streams", "s", ">"]);
+        run(&map, &mut writer, &["xadd", "s", "1-1", "f", "v"]).unwrap();
+        assert_eq!(
+            blocked.receiver.try_recv().unwrap().unwrap(),
+            RespVal::Array(vec![RespVal::Array(vec![bulk("s"), RespVal::Array(vec![entry("1-1", &["f", "v"])])])])
+        );
+        let mut blocked = run_blocked(&map, &mut reader, &["xreadgroup", "group", "g", "c", "block", "0", "streams", "s", ">"]);
+        run(&map, &mut writer, &["xgroup", "destroy", "s", "g"]).unwrap();
+        assert!(matches!(blocked.receiver.try_recv().unwrap(), Err(Error::NoGroup(_))));
+        assert_eq!(map.lock().unwrap().blocked.count(), 0);
+    }
+}
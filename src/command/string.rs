@@ -0,0 +1,621 @@
+use super::{invalid_expire_time, is_infinity, ok, parse_integer, syntax_error, CommandFlag::*, CommandSpec, Context};
+use crate::decimal::Decimal;
+use crate::error::{Error, Result};
+use crate::resp::RespVal;
+use crate::{Data, Value};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Largest string value, matching Redis' default `proto-max-bulk-len`.
+pub const MAX_STRING_LENGTH: usize = 512 * 1024 * 1024;
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("get", 2, get)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("string", "1.0.0", "Returns the string value of a key."),
+    CommandSpec::new("set", -3, set)
+        .flags(&[Write, DenyOom])
+        .keys(1, 1, 1)
+        .docs("string", "1.0.0", "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist."),
+    CommandSpec::new("setnx", 3, setnx)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("string", "1.0.0", "Set the string value of a key only when the key doesn't exist."),
+    CommandSpec::new("setex", 4, setex)
+        .flags(&[Write, DenyOom])
+        .keys(1, 1, 1)
+        .docs("string", "2.0.0", "Sets the string value and expiration time of a key. Creates the key if it doesn't exist."),
+    CommandSpec::new("psetex", 4, psetex)
+        .flags(&[Write, DenyOom])
+        .keys(1, 1, 1)
+        .docs("string", "2.6.0", "Sets both string value and expiration time in milliseconds of a key. The key is created if it doesn't exist."),
+    CommandSpec::new("mget", -2, mget)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, -1, 1)
+        .docs("string", "1.0.0", "Atomically returns the string values of one or more keys."),
+    CommandSpec::new("mset", -3, mset)
+        .flags(&[Write, DenyOom])
+        .keys(1, -1, 2)
+        .docs("string", "1.0.1", "Atomically creates or modifies the string values of one or more keys."),
+    CommandSpec::new("msetnx", -3, msetnx)
+        .flags(&[Write, DenyOom])
+        .keys(1, -1, 2)
+        .docs("string", "1.0.1", "Atomically modifies the string values of one or more keys only when all keys don't exist."),
+    CommandSpec::new("append", 3, append)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("string", "2.0.0", "Appends a string to the value of a key. Creates the key if it doesn't exist."),
+    CommandSpec::new("strlen", 2, strlen)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("string", "2.2.0", "Returns the length of a string value."),
+    CommandSpec::new("getrange", 4, getrange)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("string", "2.4.0", "Returns a substring of the string stored at a key."),
+    CommandSpec::new("setrange", 4, setrange)
+        .flags(&[Write, DenyOom])
+        .keys(1, 1, 1)
+        .docs("string", "2.2.0", "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist."),
+    CommandSpec::new("getdel", 2, getdel)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("string", "6.2.0", "Returns the string value of a key after deleting the key."),
+    CommandSpec::new("getex", -2, getex)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("string", "6.2.0", "Returns the string value of a key after setting its expiration time."),
+    CommandSpec::new("incr", 2, incr)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("string", "1.0.0", "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist."),
+    CommandSpec::new("decr", 2, decr)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("string", "1.0.0", "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist."),
+    CommandSpec::new("incrby", 3, incrby)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("string", "1.0.0", "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist."),
+    CommandSpec::new("decrby", 3, decrby)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("string", "1.0.0", "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist."),
+    CommandSpec::new("incrbyfloat", 3, incrbyfloat)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("string", "2.6.0", "Increment the floating point value of a key by a number. Uses 0 as initial value if the key doesn't exist."),
+];
+
+fn get(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    match ctx.get_string(&args[1])? {
+        Some(data) => Ok(RespVal::BulkString(data.clone())),
+        None => Ok(RespVal::Null),
+    }
+}
+
+fn set(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let options = SetOption::parse_all(&args[3..])?;
+    let key = &args[1];
+    let now = SystemTime::now();
+    let existing = ctx.get_value(key);
+    let old_data = if options.contains(&SetOption::Get) {
+        existing.map(|value| value.as_string().cloned()).transpose()?
+    } else {
+        None
+    };
+    let condition_met = options.iter().all(|option| match option {
+        SetOption::Nx => existing.is_none(),
+        SetOption::Xx => existing.is_some(),
+        _ => true,
+    });
+    let mut expiration_time = None;
+    for option in &options {
+        expiration_time = match option {
+            SetOption::KeepTtl => existing.and_then(|value| value.expiration_time),
+            option => option.expiration_time(now)?.or(expiration_time),
+        };
+    }
+    if condition_met {
+        let value = Value {
+            data: Data::String(args[2].clone()),
+            expiration_time,
+        };
+        ctx.db.insert(key.clone(), value);
+    }
+    match (options.contains(&SetOption::Get), condition_met) {
+        (true, _) => Ok(old_data.map_or(RespVal::Null, RespVal::BulkString)),
+        (false, true) => Ok(ok()),
+        (false, false) => Ok(RespVal::Null),
+    }
+}
+
+fn setnx(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    if ctx.get_value(&args[1]).is_some() {
+        return Ok(RespVal::Integer(0));
+    }
+    ctx.db.insert(args[1].clone(), Value::string(args[2].clone()));
+    Ok(RespVal::Integer(1))
+}
+
+fn setex(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    set_expiring(ctx, args, SetOption::Ex)
+}
+
+fn psetex(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    set_expiring(ctx, args, SetOption::Px)
+}
+
+/// Implements SETEX and PSETEX, which take the TTL as second and the value as third argument.
+fn set_expiring(ctx: &mut Context, args: &[Vec<u8>], ttl_option: fn(i64) -> SetOption) -> Result<RespVal> {
+    let command_name = String::from_utf8_lossy(&args[0]).to_ascii_lowercase();
+    let ttl = parse_integer(&args[2])?;
+    if ttl <= 0 {
+        return Err(invalid_expire_time(&command_name));
+    }
+    let expiration_time = ttl_option(ttl)
+        .expiration_time(SystemTime::now())
+        .map_err(|_| invalid_expire_time(&command_name))?;
+    let value = Value {
+        data: Data::String(args[3].clone()),
+        expiration_time,
+    };
+    ctx.db.insert(args[1].clone(), value);
+    Ok(ok())
+}
+
+fn mget(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let values = args[1..]
+        .iter()
+        .map(|key| match ctx.get_string(key) {
+            Ok(Some(data)) => RespVal::BulkString(data.clone()),
+            // Keys holding other types read as missing rather than failing the whole command.
+            Ok(None) | Err(_) => RespVal::Null,
+        })
+        .collect();
+    Ok(RespVal::Array(values))
+}
+
+fn mset(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    if args.len().is_multiple_of(2) {
+        return Err(super::wrong_arity("mset"));
+    }
+    for pair in args[1..].chunks_exact(2) {
+        ctx.db.insert(pair[0].clone(), Value::string(pair[1].clone()));
+    }
+    Ok(ok())
+}
+
+fn msetnx(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    if args.len().is_multiple_of(2) {
+        return Err(super::wrong_arity("msetnx"));
+    }
+    let any_exists = args[1..]
+        .iter()
+        .step_by(2)
+        .any(|key| ctx.get_value(key).is_some());
+    if any_exists {
+        return Ok(RespVal::Integer(0));
+    }
+    for pair in args[1..].chunks_exact(2) {
+        ctx.db.insert(pair[0].clone(), Value::string(pair[1].clone()));
+    }
+    Ok(RespVal::Integer(1))
+}
+
+fn append(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let (key, suffix) = (&args[1], &args[2]);
+    match ctx.get_string_mut(key)? {
+        Some(data) => {
+            check_string_length(data.len() + suffix.len())?;
+            data.extend_from_slice(suffix);
+            Ok(RespVal::Integer(data.len() as i64))
+        }
+        None => {
+            ctx.db.insert(key.clone(), Value::string(suffix.clone()));
+            Ok(RespVal::Integer(suffix.len() as i64))
+        }
+    }
+}
+
+fn strlen(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let length = ctx.get_string(&args[1])?.map_or(0, Vec::len);
+    Ok(RespVal::Integer(length as i64))
+}
+
+fn getrange(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let start = parse_integer(&args[2])?;
+    let end = parse_integer(&args[3])?;
+    let data = ctx.get_string(&args[1])?.map_or(&[][..], Vec::as_slice);
+    let length = data.len() as i64;
+    // Negative offsets count from the end; the range is clamped to the string.
+    let start = if start < 0 { (length + start).max(0) } else { start };
+    let end = if end < 0 { (length + end).max(0) } else { end.min(length - 1) };
+    if length == 0 || start > end {
+        return Ok(RespVal::BulkString(Vec::new()));
+    }
+    Ok(RespVal::BulkString(data[start as usize..=end as usize].to_vec()))
+}
+
+fn setrange(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    let offset = parse_integer(&args[2])?;
+    let patch = &args[3];
+    if offset < 0 {
+        return Err(Error::ValidationError("offset is out of range".to_string()));
+    }
+    let offset = offset as usize;
+    let current_length = ctx.get_string(key)?.map_or(0, Vec::len);
+    // An empty patch doesn't change the string and doesn't create the key.
+    if patch.is_empty() {
+        return Ok(RespVal::Integer(current_length as i64));
+    }
+    check_string_length(offset.saturating_add(patch.len()))?;
+    if ctx.get_value(key).is_none() {
+        ctx.db.insert(key.clone(), Value::string(Vec::new()));
+    }
+    let data = ctx.get_string_mut(key)?.expect("the key was just created");
+    let end = offset + patch.len();
+    if data.len() < end {
+        // The gap between the end of the string and the offset is padded with zero bytes.
+        data.resize(end, 0);
+    }
+    data[offset..end].copy_from_slice(patch);
+    Ok(RespVal::Integer(data.len() as i64))
+}
+
+fn getdel(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    if ctx.get_string(key)?.is_none() {
+        return Ok(RespVal::Null);
+    }
+    let value = ctx.db.remove(key).expect("the key exists");
+    Ok(RespVal::BulkString(value.into_string()?))
+}
+
+fn getex(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    let now = SystemTime::now();
+    // `None` keeps the TTL, `Some(None)` removes it.
+    let mut new_expiration_time: Option<Option<SystemTime>> = None;
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        if new_expiration_time.is_some() {
+            return Err(syntax_error());
+        }
+        let option = option.to_ascii_lowercase();
+        let ttl_option: fn(i64) -> SetOption = match option.as_slice() {
+            b"persist" => {
+                new_expiration_time = Some(None);
+                continue;
+            }
+            b"ex" => SetOption::Ex,
+            b"px" => SetOption::Px,
+            b"exat" => SetOption::ExAt,
+            b"pxat" => SetOption::PxAt,
+            _ => return Err(syntax_error()),
+        };
+        let time = parse_integer(options.next().ok_or_else(syntax_error)?)?;
+        if time <= 0 {
+            return Err(invalid_expire_time("getex"));
+        }
+        let expiration_time = ttl_option(time)
+            .expiration_time(now)
+            .map_err(|_| invalid_expire_time("getex"))?;
+        new_expiration_time = Some(expiration_time);
+    }
+    let data = match ctx.get_string(key)? {
+        Some(data) => data.clone(),
+        None => return Ok(RespVal::Null),
+    };
+    if let Some(expiration_time) = new_expiration_time {
+        // An expiration time in the past deletes the key right away.
+        if expiration_time.is_some_and(|time| time <= now) {
+            ctx.db.remove(key);
+        } else {
+            ctx.db.set_expiration_time(key, expiration_time);
+        }
+    }
+    Ok(RespVal::BulkString(data))
+}
+
+fn incr(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    increment_by(ctx, &args[1], 1)
+}
+
+fn decr(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    increment_by(ctx, &args[1], -1)
+}
+
+fn incrby(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let increment = parse_integer(&args[2])?;
+    increment_by(ctx, &args[1], increment)
+}
+
+fn decrby(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let decrement = parse_integer(&args[2])?;
+    let increment = decrement
+        .checked_neg()
+        .ok_or_else(|| Error::ValidationError("decrement would overflow".to_string()))?;
+    increment_by(ctx, &args[1], increment)
+}
+
+/// Adds `increment` to the integer stored at `key`, keeping its TTL.
+fn increment_by(ctx: &mut Context, key: &[u8], increment: i64) -> Result<RespVal> {
+    let current = match ctx.get_string(key)? {
+        Some(data) => parse_integer(data)?,
+        None => 0,
+    };
+    let new = current
+        .checked_add(increment)
+        .ok_or_else(|| Error::ValidationError("increment or decrement would overflow".to_string()))?;
+    store_keeping_ttl(ctx, key, new.to_string().into_bytes());
+    Ok(RespVal::Integer(new))
+}
+
+fn incrbyfloat(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    let not_a_float = || Error::ValidationError("value is not a valid float".to_string());
+    let nan_or_infinity = || Error::ValidationError("increment would produce NaN or Infinity".to_string());
+    let increment = match Decimal::parse(&args[2]) {
+        Some(increment) => increment,
+        None if is_infinity(&args[2]) => return Err(nan_or_infinity()),
+        None => return Err(not_a_float()),
+    };
+    let current = match ctx.get_string(key)? {
+        Some(data) => Decimal::parse(data).ok_or_else(not_a_float)?,
+        None => Decimal::ZERO,
+    };
+    let new = current.checked_add(increment).ok_or_else(nan_or_infinity)?;
+    let new = new.to_human_string().into_bytes();
+    store_keeping_ttl(ctx, key, new.clone());
+    Ok(RespVal::BulkString(new))
+}
+
+/// Replaces the string at `key`, keeping the expiration time of an existing value.
+fn store_keeping_ttl(ctx: &mut Context, key: &[u8], data: Vec<u8>) {
+    match ctx.get_value_mut(key) {
+        Some(value) => value.data = Data::String(data),
+        None => {
+            ctx.db.insert(key.to_vec(), Value::string(data));
+        }
+    }
+}
+
+fn check_string_length(length: usize) -> Result<()> {
+    if length > MAX_STRING_LENGTH {
+        return Err(Error::ValidationError(
+            "string exceeds maximum allowed size (proto-max-bulk-len)".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOption {
+    /// Only set the key if it does not exist.
+    Nx,
+    /// Only set the key if it already exists.
+    Xx,
+    /// Reply with the old value instead of `OK`.
+    Get,
+    Ex(i64),
+    Px(i64),
+    ExAt(i64),
+    PxAt(i64),
+    KeepTtl,
+}
+
+impl SetOption {
+    /// Parses all options of a SET command and rejects conflicting combinations, such as NX
+    /// together with XX, or more than one way to set the expiration.
+    fn parse_all(args: &[Vec<u8>]) -> Result<Vec<SetOption>> {
+        let mut options: Vec<SetOption> = Vec::with_capacity(args.len());
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let option = match arg.to_ascii_lowercase().as_slice() {
+                b"nx" => SetOption::Nx,
+                b"xx" => SetOption::Xx,
+                b"get" => SetOption::Get,
+                b"keepttl" => SetOption::KeepTtl,
+                name @ (b"ex" | b"px" | b"exat" | b"pxat") => {
+                    let time = parse_integer(args.next().ok_or_else(syntax_error)?)?;
+                    if time <= 0 {
+                        return Err(invalid_expire_time("set"));
+                    }
+                    match name {
+                        b"ex" => SetOption::Ex(time),
+                        b"px" => SetOption::Px(time),
+                        b"exat" => SetOption::ExAt(time),
+                        _ => SetOption::PxAt(time),
+                    }
+                }
+                _ => return Err(syntax_error()),
+            };
+            let conflicts = options.iter().any(|other| {
+                (option.is_condition() && other.is_condition())
+                    || (option.is_expiration() && other.is_expiration())
+                    || (option == SetOption::Get && *other == SetOption::Get)
+            });
+            if conflicts {
+                return Err(syntax_error());
+            }
+            options.push(option);
+        }
+        Ok(options)
+    }
+
+    fn is_condition(self) -> bool {
+        matches!(self, SetOption::Nx | SetOption::Xx)
+    }
+
+    fn is_expiration(self) -> bool {
+        matches!(
+            self,
+            SetOption::Ex(_) | SetOption::Px(_) | SetOption::ExAt(_) | SetOption::PxAt(_) | SetOption::KeepTtl
+        )
+    }
+
+    /// The absolute expiration time this option sets, if it is a relative or absolute TTL.
+    fn expiration_time(self, now: SystemTime) -> Result<Option<SystemTime>> {
+        let (base, millis) = match self {
+            SetOption::Ex(seconds) => (now, seconds.checked_mul(1000)),
+            SetOption::Px(millis) => (now, Some(millis)),
+            SetOption::ExAt(seconds) => (UNIX_EPOCH, seconds.checked_mul(1000)),
+            SetOption::PxAt(millis) => (UNIX_EPOCH, Some(millis)),
+            _ => return Ok(None),
+        };
+        let millis = millis.ok_or_else(|| invalid_expire_time("set"))?;
+        base.checked_add(Duration::from_millis(millis as u64))
+            .map(Some)
+            .ok_or_else(|| invalid_expire_time("set"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{new_database, run};
+    use super::*;
+    use crate::client::Client;
+
+    #[test]
+    fn test_set_conditions() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(run(&map, &mut client, &["set", "k", "1", "XX"]).unwrap(), RespVal::Null);
+        assert_eq!(run(&map, &mut client, &["set", "k", "1", "nx", "ex", "30"]).unwrap(), ok());
+        assert_eq!(run(&map, &mut client, &["set", "k", "2", "NX"]).unwrap(), RespVal::Null);
+        assert_eq!(
+            run(&map, &mut client, &["set", "k", "3", "xx", "get"]).unwrap(),
+            RespVal::BulkString(b"1".to_vec())
+        );
+        assert_eq!(run(&map, &mut client, &["get", "k"]).unwrap(), RespVal::BulkString(b"3".to_vec()));
+    }
+
+    #[test]
+    fn test_set_expiration() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["set", "k", "v", "px", "100000"]).unwrap();
+        let expiration_time = map.lock().unwrap()[b"k".as_slice()].expiration_time;
+        assert!(expiration_time.is_some());
+        run(&map, &mut client, &["set", "k", "w", "keepttl"]).unwrap();
+        assert_eq!(map.lock().unwrap()[b"k".as_slice()].expiration_time, expiration_time);
+        run(&map, &mut client, &["set", "k", "x"]).unwrap();
+        assert_eq!(map.lock().unwrap()[b"k".as_slice()].expiration_time, None);
+        run(&map, &mut client, &["set", "k", "y", "pxat", "1"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["get", "k"]).unwrap(), RespVal::Null);
+    }
+
+    #[test]
+    fn test_set_rejects_invalid_options() {
+        let map = new_database();
+        let mut client = Client::new();
+        for args in [
+            &["set", "k", "v", "nx", "xx"][..],
+            &["set", "k", "v", "ex", "10", "px", "100"],
+            &["set", "k", "v", "ex", "10", "keepttl"],
+            &["set", "k", "v", "ex"],
+            &["set", "k", "v", "ex", "0"],
+            &["set", "k", "v", "ex", "ten"],
+            &["set", "k", "v", "ex", "9223372036854775807"],
+            &["set", "k", "v", "bogus"],
+        ] {
+            assert!(run(&map, &mut client, args).is_err(), "{:?} should fail", args);
+        }
+        assert_eq!(run(&map, &mut client, &["get", "k"]).unwrap(), RespVal::Null);
+    }
+
+    fn bulk(data: &str) -> RespVal {
+        RespVal::BulkString(data.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_getrange_and_setrange() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["set", "k", "Hello World"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["getrange", "k", "0", "4"]).unwrap(), bulk("Hello"));
+        assert_eq!(run(&map, &mut client, &["getrange", "k", "-5", "-1"]).unwrap(), bulk("World"));
+        assert_eq!(run(&map, &mut client, &["getrange", "k", "5", "1"]).unwrap(), bulk(""));
+        assert_eq!(run(&map, &mut client, &["getrange", "k", "-100", "100"]).unwrap(), bulk("Hello World"));
+        assert_eq!(run(&map, &mut client, &["getrange", "missing", "0", "-1"]).unwrap(), bulk(""));
+        assert_eq!(run(&map, &mut client, &["setrange", "k", "6", "Redis"]).unwrap(), RespVal::Integer(11));
+        assert_eq!(run(&map, &mut client, &["get", "k"]).unwrap(), bulk("Hello Redis"));
+        assert_eq!(run(&map, &mut client, &["setrange", "pad", "3", "x"]).unwrap(), RespVal::Integer(4));
+        assert_eq!(run(&map, &mut client, &["get", "pad"]).unwrap(), bulk("\0\0\0x"));
+        assert_eq!(run(&map, &mut client, &["setrange", "empty", "3", ""]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["get", "empty"]).unwrap(), RespVal::Null);
+        assert!(run(&map, &mut client, &["setrange", "k", "-1", "x"]).is_err());
+        assert!(run(&map, &mut client, &["setrange", "k", "536870912", "x"]).is_err());
+    }
+
+    #[test]
+    fn test_multi_key_commands() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(run(&map, &mut client, &["mset", "a", "1", "b", "2"]).unwrap(), ok());
+        assert!(run(&map, &mut client, &["mset", "a", "1", "b"]).is_err());
+        assert_eq!(
+            run(&map, &mut client, &["mget", "a", "missing", "b"]).unwrap(),
+            RespVal::Array(vec![bulk("1"), RespVal::Null, bulk("2")])
+        );
+        assert_eq!(run(&map, &mut client, &["msetnx", "c", "3", "a", "4"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["get", "c"]).unwrap(), RespVal::Null);
+        assert_eq!(run(&map, &mut client, &["msetnx", "c", "3", "d", "4"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["append", "c", "33"]).unwrap(), RespVal::Integer(3));
+        assert_eq!(run(&map, &mut client, &["strlen", "c"]).unwrap(), RespVal::Integer(3));
+    }
+
+    #[test]
+    fn test_getex_and_getdel() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["setex", "k", "100", "v"]).unwrap();
+        assert!(map.lock().unwrap()[b"k".as_slice()].expiration_time.is_some());
+        assert_eq!(run(&map, &mut client, &["getex", "k", "persist"]).unwrap(), bulk("v"));
+        assert!(map.lock().unwrap()[b"k".as_slice()].expiration_time.is_none());
+        assert!(run(&map, &mut client, &["getex", "k", "ex", "10", "px", "10"]).is_err());
+        assert!(run(&map, &mut client, &["setex", "k", "0", "v"]).is_err());
+        assert_eq!(run(&map, &mut client, &["getex", "k", "pxat", "1"]).unwrap(), bulk("v"));
+        assert_eq!(run(&map, &mut client, &["getdel", "k"]).unwrap(), RespVal::Null);
+        run(&map, &mut client, &["setnx", "k", "w"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["getdel", "k"]).unwrap(), bulk("w"));
+        assert_eq!(run(&map, &mut client, &["get", "k"]).unwrap(), RespVal::Null);
+    }
+
+    #[test]
+    fn test_counters() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(run(&map, &mut client, &["incr", "n"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["incrby", "n", "41"]).unwrap(), RespVal::Integer(42));
+        assert_eq!(run(&map, &mut client, &["decrby", "n", "50"]).unwrap(), RespVal::Integer(-8));
+        assert_eq!(run(&map, &mut client, &["decr", "n"]).unwrap(), RespVal::Integer(-9));
+        run(&map, &mut client, &["set", "n", "9223372036854775806", "ex", "100"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["incr", "n"]).unwrap(), RespVal::Integer(i64::MAX));
+        assert!(map.lock().unwrap()[b"n".as_slice()].expiration_time.is_some());
+        assert!(run(&map, &mut client, &["incr", "n"]).is_err());
+        assert!(run(&map, &mut client, &["decrby", "n", "-9223372036854775808"]).is_err());
+        run(&map, &mut client, &["set", "s", "12a"]).unwrap();
+        assert!(run(&map, &mut client, &["incr", "s"]).is_err());
+        assert!(run(&map, &mut client, &["incrby", "n", "1.5"]).is_err());
+    }
+
+    #[test]
+    fn test_incrbyfloat() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(run(&map, &mut client, &["incrbyfloat", "f", "0.1"]).unwrap(), bulk("0.1"));
+        assert_eq!(run(&map, &mut client, &["incrbyfloat", "f", "0.2"]).unwrap(), bulk("0.3"));
+        run(&map, &mut client, &["set", "f", "10.50", "px", "100000"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["incrbyfloat", "f", "0.1"]).unwrap(), bulk("10.6"));
+        assert!(map.lock().unwrap()[b"f".as_slice()].expiration_time.is_some());
+        assert_eq!(run(&map, &mut client, &["incrbyfloat", "f", "-5.0e3"]).unwrap(), bulk("-4989.4"));
+        assert_eq!(run(&map, &mut client, &["incrbyfloat", "i", "5.0e3"]).unwrap(), bulk("5000"));
+        assert!(run(&map, &mut client, &["incrbyfloat", "f", "inf"]).is_err());
+        assert!(run(&map, &mut client, &["incrbyfloat", "f", "abc"]).is_err());
+        run(&map, &mut client, &["set", "s", "1.2.3"]).unwrap();
+        assert!(run(&map, &mut client, &["incrbyfloat", "s", "1"]).is_err());
+    }
+}
@@ -0,0 +1,1144 @@
+use super::{parse_integer, parse_random_count, parse_timeout, random_indices, resolve_range, syntax_error, CommandFlag::*, CommandSpec, Context};
+use crate::error::{Error, Result};
+use crate::resp::{ProtocolVersion, RespVal};
+use crate::random;
+use crate::set::Set;
+use crate::zset::{LexBound, Range, ScoreBound, SortedSet};
+use crate::{Data, Value};
+use std::collections::HashMap;
+
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec::new("zadd", -4, zadd)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "1.2.0", "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist."),
+    CommandSpec::new("zincrby", 4, zincrby)
+        .flags(&[Write, DenyOom, Fast])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "1.2.0", "Increments the score of a member in a sorted set."),
+    CommandSpec::new("zrem", -3, zrem)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "1.2.0", "Removes one or more members from a sorted set. Deletes the sorted set if all members were removed."),
+    CommandSpec::new("zcard", 2, zcard)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "1.2.0", "Returns the number of members in a sorted set."),
+    CommandSpec::new("zscore", 3, zscore)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "1.2.0", "Returns the score of a member in a sorted set."),
+    CommandSpec::new("zrank", -3, zrank)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "2.0.0", "Returns the index of a member in a sorted set ordered by ascending scores."),
+    CommandSpec::new("zrevrank", -3, zrevrank)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "2.0.0", "Returns the index of a member in a sorted set ordered by descending scores."),
+    CommandSpec::new("zcount", 4, zcount)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "2.0.0", "Returns the count of members in a sorted set that have scores within a range."),
+    CommandSpec::new("zrange", -4, zrange)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "1.2.0", "Returns members in a sorted set within a range of indexes."),
+    CommandSpec::new("zrevrange", -4, zrevrange)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "1.2.0", "Returns members in a sorted set within a range of indexes in reverse order."),
+    CommandSpec::new("zrangebyscore", -4, zrangebyscore)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "1.0.5", "Returns members in a sorted set within a range of scores."),
+    CommandSpec::new("zrevrangebyscore", -4, zrevrangebyscore)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "2.2.0", "Returns members in a sorted set within a range of scores in reverse order."),
+    CommandSpec::new("zrangebylex", -4, zrangebylex)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "2.8.9", "Returns members in a sorted set within a lexicographical range."),
+    CommandSpec::new("zrevrangebylex", -4, zrevrangebylex)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "2.8.9", "Returns members in a sorted set within a lexicographical range in reverse order."),
+    CommandSpec::new("zrangestore", -5, zrangestore)
+        .flags(&[Write, DenyOom])
+        .keys(1, 2, 1)
+        .docs("sorted-set", "6.2.0", "Stores a range of members from sorted set in a key."),
+    CommandSpec::new("zmscore", -3, zmscore)
+        .flags(&[ReadOnly, Fast])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "6.2.0", "Returns the score of one or more members in a sorted set."),
+    CommandSpec::new("zrandmember", -2, zrandmember)
+        .flags(&[ReadOnly])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "6.2.0", "Returns one or more random members from a sorted set."),
+    CommandSpec::new("zpopmin", -2, zpopmin)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "5.0.0", "Returns the lowest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped."),
+    CommandSpec::new("zpopmax", -2, zpopmax)
+        .flags(&[Write, Fast])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "5.0.0", "Returns the highest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped."),
+    CommandSpec::new("zmpop", -4, zmpop)
+        .flags(&[Write, MovableKeys])
+        .keys(0, 0, 0)
+        .docs("sorted-set", "7.0.0", "Returns the highest- or lowest-scoring members from one or more sorted sets after removing them. Deletes the sorted set if the last member was popped."),
+    CommandSpec::new("bzpopmin", -3, bzpopmin)
+        .flags(&[Write, Fast, Blocking])
+        .keys(1, -2, 1)
+        .docs("sorted-set", "5.0.0", "Removes and returns the member with the lowest score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped."),
+    CommandSpec::new("bzpopmax", -3, bzpopmax)
+        .flags(&[Write, Fast, Blocking])
+        .keys(1, -2, 1)
+        .docs("sorted-set", "5.0.0", "Removes and returns the member with the highest score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped."),
+    CommandSpec::new("bzmpop", -5, bzmpop)
+        .flags(&[Write, Blocking, MovableKeys])
+        .keys(0, 0, 0)
+        .docs("sorted-set", "7.0.0", "Removes and returns a member by score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped."),
+    CommandSpec::new("zunion", -3, zunion)
+        .flags(&[ReadOnly, MovableKeys])
+        .keys(0, 0, 0)
+        .docs("sorted-set", "6.2.0", "Returns the union of multiple sorted sets."),
+    CommandSpec::new("zunionstore", -4, zunionstore)
+        .flags(&[Write, DenyOom, MovableKeys])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "2.0.0", "Stores the union of multiple sorted sets in a key."),
+    CommandSpec::new("zinter", -3, zinter)
+        .flags(&[ReadOnly, MovableKeys])
+        .keys(0, 0, 0)
+        .docs("sorted-set", "6.2.0", "Returns the intersect of multiple sorted sets."),
+    CommandSpec::new("zinterstore", -4, zinterstore)
+        .flags(&[Write, DenyOom, MovableKeys])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "2.0.0", "Stores the intersect of multiple sorted sets in a key."),
+    CommandSpec::new("zintercard", -3, zintercard)
+        .flags(&[ReadOnly, MovableKeys])
+        .keys(0, 0, 0)
+        .docs("sorted-set", "7.0.0", "Returns the number of members of the intersect of multiple sorted sets."),
+    CommandSpec::new("zdiff", -3, zdiff)
+        .flags(&[ReadOnly, MovableKeys])
+        .keys(0, 0, 0)
+        .docs("sorted-set", "6.2.0", "Returns the difference between multiple sorted sets."),
+    CommandSpec::new("zdiffstore", -4, zdiffstore)
+        .flags(&[Write, DenyOom, MovableKeys])
+        .keys(1, 1, 1)
+        .docs("sorted-set", "6.2.0", "Stores the difference of multiple sorted sets in a key."),
+];
+
+/// Parses a score, which may be `inf` or `-inf` but not NaN.
+fn parse_score(arg: &[u8]) -> Result<f64> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|arg| arg.parse::<f64>().ok())
+        .filter(|score| !score.is_nan())
+        .ok_or_else(|| Error::ValidationError("value is not a valid float".to_string()))
+}
+
+/// Parses one end of a score range: a score, optionally preceded by `(` to exclude it.
+fn parse_score_bound(arg: &[u8]) -> Result<ScoreBound> {
+    let (value, exclusive) = match arg.strip_prefix(b"(") {
+        Some(value) => (value, true),
+        None => (arg, false),
+    };
+    let value = parse_score(value).map_err(|_| Error::ValidationError("min or max is not a float".to_string()))?;
+    Ok(ScoreBound { value, exclusive })
+}
+
+fn parse_lex_bound(arg: &[u8]) -> Result<LexBound> {
+    match arg {
+        b"-" => Ok(LexBound::Min),
+        b"+" => Ok(LexBound::Max),
+        [b'[', member @ ..] => Ok(LexBound::Inclusive(member.to_vec())),
+        [b'(', member @ ..] => Ok(LexBound::Exclusive(member.to_vec())),
+        _ => Err(Error::ValidationError("min or max not valid string range item".to_string())),
+    }
+}
+
+/// Replies with members, and with their scores if `with_scores`: RESP3 pairs each member with
+/// its score, RESP2 has them alternate in a flat array.
+fn scored_members_reply(protocol: ProtocolVersion, entries: Vec<(Vec<u8>, f64)>, with_scores: bool) -> RespVal {
+    let mut reply = Vec::with_capacity(entries.len() * if with_scores { 2 } else { 1 });
+    for (member, score) in entries {
+        let member = RespVal::BulkString(member);
+        match (with_scores, protocol) {
+            (false, _) => reply.push(member),
+            (true, ProtocolVersion::Resp3) => reply.push(RespVal::Array(vec![member, RespVal::Double(score)])),
+            (true, ProtocolVersion::Resp2) => reply.extend([member, RespVal::Double(score)]),
+        }
+    }
+    RespVal::Array(reply)
+}
+
+/// Deletes the sorted set at `key` if it is empty: like in Redis, a key never holds an empty
+/// sorted set.
+fn remove_if_empty(ctx: &mut Context, key: &[u8]) {
+    let is_empty = matches!(ctx.db.get(key), Some(Value { data: Data::SortedSet(set), .. }) if set.is_empty());
+    if is_empty {
+        ctx.db.remove(key);
+    }
+}
+
+/// The sorted set at `key`, which is created if it doesn't exist.
+fn sorted_set_or_create<'a>(ctx: &'a mut Context, key: &[u8]) -> Result<&'a mut SortedSet> {
+    if ctx.get_sorted_set(key)?.is_none() {
+        ctx.db.insert(key.to_vec(), Value::new(Data::SortedSet(SortedSet::default())));
+    }
+    Ok(ctx.get_sorted_set_mut(key)?.expect("the key was just created"))
+}
+
+/// The options of `ZADD`.
+#[derive(Debug, Default)]
+struct AddOptions {
+    nx: bool,
+    xx: bool,
+    gt: bool,
+    lt: bool,
+    ch: bool,
+    incr: bool,
+}
+
+impl AddOptions {
+    /// Parses the options at the start of `args` and returns them with the number of arguments
+    /// they took.
+    fn parse(args: &[Vec<u8>]) -> Result<(AddOptions, usize)> {
+        let mut options = AddOptions::default();
+        let mut parsed = 0;
+        for arg in args {
+            match arg.to_ascii_lowercase().as_slice() {
+                b"nx" => options.nx = true,
+                b"xx" => options.xx = true,
+                b"gt" => options.gt = true,
+                b"lt" => options.lt = true,
+                b"ch" => options.ch = true,
+                b"incr" => options.incr = true,
+                _ => break,
+            }
+            parsed += 1;
+        }
+        if options.nx && options.xx {
+            return Err(Error::ValidationError("XX and NX options at the same time are not compatible".to_string()));
+        }
+        if [options.nx, options.gt, options.lt].iter().filter(|&&option| option).count() > 1 {
+            return Err(Error::ValidationError(
+                "GT, LT, and/or NX options at the same time are not compatible".to_string(),
+            ));
+        }
+        Ok((options, parsed))
+    }
+}
+
+fn nan_score() -> Error {
+    Error::ValidationError("resulting score is not a number (NaN)".to_string())
+}
+
+fn zadd(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    let (options, parsed) = AddOptions::parse(&args[2..])?;
+    let pairs = &args[2 + parsed..];
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return Err(syntax_error());
+    }
+    if options.incr && pairs.len() > 2 {
+        return Err(Error::ValidationError("INCR option supports a single increment-element pair".to_string()));
+    }
+    // Every score is validated before anything changes.
+    let elements = pairs
+        .chunks(2)
+        .map(|pair| Ok((parse_score(&pair[0])?, &pair[1])))
+        .collect::<Result<Vec<_>>>()?;
+    if options.xx && ctx.get_sorted_set(key)?.is_none() {
+        return Ok(if options.incr { RespVal::Null } else { RespVal::Integer(0) });
+    }
+    let set = sorted_set_or_create(ctx, key)?;
+    let (mut added, mut updated) = (0, 0);
+    let mut incremented = None;
+    for (score, member) in elements {
+        let new_score = match set.score(member) {
+            None if options.xx => continue,
+            None => score,
+            Some(_) if options.nx => continue,
+            Some(current) => {
+                let new_score = if options.incr { current + score } else { score };
+                if new_score.is_nan() {
+                    return Err(nan_score());
+                }
+                if (options.gt && new_score <= current) || (options.lt && new_score >= current) {
+                    continue;
+                }
+                if new_score != current {
+                    updated += 1;
+                }
+                new_score
+            }
+        };
+        if set.insert(member.clone(), new_score) {
+            added += 1;
+        }
+        incremented = Some(new_score);
+    }
+    remove_if_empty(ctx, key);
+    if options.incr {
+        return Ok(incremented.map_or(RespVal::Null, RespVal::Double));
+    }
+    Ok(RespVal::Integer(if options.ch { added + updated } else { added }))
+}
+
+fn zincrby(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let increment = parse_score(&args[2])?;
+    let set = sorted_set_or_create(ctx, &args[1])?;
+    let score = set.score(&args[3]).unwrap_or(0.0) + increment;
+    if score.is_nan() {
+        return Err(nan_score());
+    }
+    set.insert(args[3].clone(), score);
+    Ok(RespVal::Double(score))
+}
+
+fn zrem(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let key = &args[1];
+    let set = match ctx.get_sorted_set_mut(key)? {
+        Some(set) => set,
+        None => return Ok(RespVal::Integer(0)),
+    };
+    let removed = args[2..].iter().filter(|member| set.remove(member)).count();
+    remove_if_empty(ctx, key);
+    Ok(RespVal::Integer(removed as i64))
+}
+
+fn zcard(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let length = ctx.get_sorted_set(&args[1])?.map_or(0, SortedSet::len);
+    Ok(RespVal::Integer(length as i64))
+}
+
+fn zscore(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let score = ctx.get_sorted_set(&args[1])?.and_then(|set| set.score(&args[2]));
+    Ok(score.map_or(RespVal::Null, RespVal::Double))
+}
+
+fn zrank(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    rank(ctx, args, false)
+}
+
+fn zrevrank(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    rank(ctx, args, true)
+}
+
+/// Replies with the rank of a member, and with its score if `WITHSCORE` is given.
+fn rank(ctx: &mut Context, args: &[Vec<u8>], reverse: bool) -> Result<RespVal> {
+    let with_score = match args.get(3) {
+        None => false,
+        Some(option) if args.len() == 4 && option.eq_ignore_ascii_case(b"withscore") => true,
+        Some(_) => return Err(syntax_error()),
+    };
+    let set = ctx.get_sorted_set(&args[1])?;
+    let rank = set.and_then(|set| {
+        let rank = set.rank(&args[2])?;
+        Some((if reverse { set.len() - 1 - rank } else { rank }, set.score(&args[2])?))
+    });
+    Ok(match rank {
+        None if with_score => RespVal::NullArray,
+        None => RespVal::Null,
+        Some((rank, score)) if with_score => RespVal::Array(vec![RespVal::Integer(rank as i64), RespVal::Double(score)]),
+        Some((rank, _)) => RespVal::Integer(rank as i64),
+    })
+}
+
+fn zcount(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let range = Range::Score(parse_score_bound(&args[2])?, parse_score_bound(&args[3])?);
+    let count = ctx.get_sorted_set(&args[1])?.map_or(0, |set| set.count(&range));
+    Ok(RespVal::Integer(count as i64))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RangeKind {
+    Rank,
+    Score,
+    Lex,
+}
+
+/// What `ZRANGE` and its older variants select. For score and member ranges `start` is the
+/// end the range is read from, so the maximum if `reverse`.
+#[derive(Debug)]
+struct RangeQuery<'a> {
+    kind: RangeKind,
+    start: &'a [u8],
+    stop: &'a [u8],
+    reverse: bool,
+    /// Offset and count; a negative count means all of the remaining elements.
+    limit: Option<(i64, i64)>,
+    with_scores: bool,
+}
+
+impl RangeQuery<'_> {
+    /// Parses `start stop [options]`. The `BYSCORE`, `BYLEX` and `REV` options are only
+    /// accepted by the unified `ZRANGE`, the older commands imply them.
+    fn parse(args: &[Vec<u8>], kind: RangeKind, reverse: bool, unified: bool) -> Result<RangeQuery<'_>> {
+        let mut query = RangeQuery {
+            kind,
+            start: &args[0],
+            stop: &args[1],
+            reverse,
+            limit: None,
+            with_scores: false,
+        };
+        let mut options = args[2..].iter();
+        while let Some(option) = options.next() {
+            match option.to_ascii_lowercase().as_slice() {
+                b"byscore" if unified => query.kind = RangeKind::Score,
+                b"bylex" if unified => query.kind = RangeKind::Lex,
+                b"rev" if unified => query.reverse = true,
+                b"withscores" => query.with_scores = true,
+                b"limit" => {
+                    let offset = parse_integer(options.next().ok_or_else(syntax_error)?)?;
+                    let count = parse_integer(options.next().ok_or_else(syntax_error)?)?;
+                    query.limit = Some((offset, count));
+                }
+                _ => return Err(syntax_error()),
+            }
+        }
+        if query.limit.is_some() && query.kind == RangeKind::Rank {
+            return Err(Error::ValidationError(
+                "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX".to_string(),
+            ));
+        }
+        if query.with_scores && query.kind == RangeKind::Lex {
+            return Err(Error::ValidationError(
+                "syntax error, WITHSCORES not supported in combination with BYLEX".to_string(),
+            ));
+        }
+        Ok(query)
+    }
+
+    /// The selected elements of the sorted set at `key`, in reply order.
+    fn run(&self, ctx: &mut Context, key: &[u8]) -> Result<Vec<(Vec<u8>, f64)>> {
+        let (min, max) = if self.reverse { (self.stop, self.start) } else { (self.start, self.stop) };
+        let range = match self.kind {
+            RangeKind::Rank => {
+                let (start, stop) = (parse_integer(self.start)?, parse_integer(self.stop)?);
+                let set = match ctx.get_sorted_set(key)? {
+                    Some(set) => set,
+                    None => return Ok(Vec::new()),
+                };
+                return Ok(match resolve_range(start, stop, set.len()) {
+                    Some((start, stop)) => set.range_by_rank(start, stop, self.reverse),
+                    None => Vec::new(),
+                });
+            }
+            RangeKind::Score => Range::Score(parse_score_bound(min)?, parse_score_bound(max)?),
+            RangeKind::Lex => Range::Lex(parse_lex_bound(min)?, parse_lex_bound(max)?),
+        };
+        let (offset, limit) = match self.limit {
+            Some((offset, _)) if offset < 0 => return Ok(Vec::new()),
+            Some((offset, count)) => (offset as usize, usize::try_from(count).ok()),
+            None => (0, None),
+        };
+        Ok(ctx
+            .get_sorted_set(key)?
+            .map_or_else(Vec::new, |set| set.range(&range, self.reverse, offset, limit)))
+    }
+}
+
+fn zrange(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    range(ctx, args, RangeKind::Rank, false, true)
+}
+
+fn zrevrange(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    range(ctx, args, RangeKind::Rank, true, false)
+}
+
+fn zrangebyscore(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    range(ctx, args, RangeKind::Score, false, false)
+}
+
+fn zrevrangebyscore(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    range(ctx, args, RangeKind::Score, true, false)
+}
+
+fn zrangebylex(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    range(ctx, args, RangeKind::Lex, false, false)
+}
+
+fn zrevrangebylex(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    range(ctx, args, RangeKind::Lex, true, false)
+}
+
+fn range(ctx: &mut Context, args: &[Vec<u8>], kind: RangeKind, reverse: bool, unified: bool) -> Result<RespVal> {
+    let query = RangeQuery::parse(&args[2..], kind, reverse, unified)?;
+    let entries = query.run(ctx, &args[1])?;
+    Ok(scored_members_reply(ctx.client.protocol, entries, query.with_scores))
+}
+
+fn zrangestore(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let query = RangeQuery::parse(&args[3..], RangeKind::Rank, false, true)?;
+    if query.with_scores {
+        return Err(syntax_error());
+    }
+    let result: SortedSet = query.run(ctx, &args[2])?.into_iter().collect();
+    Ok(RespVal::Integer(store(ctx, &args[1], result) as i64))
+}
+
+/// Stores `result` at `destination`, replacing whatever is there, or deletes it if the result
+/// is empty. Returns the number of members stored.
+fn store(ctx: &mut Context, destination: &[u8], result: SortedSet) -> usize {
+    let length = result.len();
+    if result.is_empty() {
+        ctx.db.remove(destination);
+    } else {
+        ctx.db.insert(destination.to_vec(), Value::new(Data::SortedSet(result)));
+    }
+    length
+}
+
+fn zmscore(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let set = ctx.get_sorted_set(&args[1])?;
+    let scores = args[2..]
+        .iter()
+        .map(|member| set.and_then(|set| set.score(member)).map_or(RespVal::Null, RespVal::Double))
+        .collect();
+    Ok(RespVal::Array(scores))
+}
+
+/// Replies with a random member, or with a count, up to that many distinct members; a
+/// negative count allows repeated members and always returns exactly that many.
+fn zrandmember(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let with_scores = match args.get(3) {
+        None => false,
+        Some(option) if args.len() == 4 && option.eq_ignore_ascii_case(b"withscores") => true,
+        Some(_) => return Err(syntax_error()),
+    };
+    let count = args.get(2).map(|count| parse_random_count(count, with_scores)).transpose()?;
+    let protocol = ctx.client.protocol;
+    let set = match (ctx.get_sorted_set(&args[1])?, count) {
+        (Some(set), _) => set,
+        (None, Some(_)) => return Ok(RespVal::Array(Vec::new())),
+        (None, None) => return Ok(RespVal::Null),
+    };
+    let entry_at = |rank| {
+        let (member, score) = set.entry_at(rank);
+        (member.clone(), score)
+    };
+    let count = match count {
+        Some(count) => count,
+        None => return Ok(RespVal::BulkString(entry_at(random::random_below(set.len())).0)),
+    };
+    let entries = random_indices(set.len(), count)?.into_iter().map(entry_at).collect();
+    Ok(scored_members_reply(protocol, entries, with_scores))
+}
+
+/// Removes up to `count` of the lowest scoring members of the sorted set at `key`, or of the
+/// highest if `highest`, and returns them in the order they were popped.
+fn pop_entries(ctx: &mut Context, key: &[u8], highest: bool, count: usize) -> Result<Vec<(Vec<u8>, f64)>> {
+    let set = match ctx.get_sorted_set_mut(key)? {
+        Some(set) => set,
+        None => return Ok(Vec::new()),
+    };
+    let popped = (0..count).map_while(|_| set.pop(highest)).collect();
+    remove_if_empty(ctx, key);
+    Ok(popped)
+}
+
+fn zpopmin(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    pop(ctx, args, false)
+}
+
+fn zpopmax(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    pop(ctx, args, true)
+}
+
+/// Replies with a member and its score, or with a count, with up to that many pairs.
+fn pop(ctx: &mut Context, args: &[Vec<u8>], highest: bool) -> Result<RespVal> {
+    if args.len() > 3 {
+        return Err(syntax_error());
+    }
+    let count = match args.get(2) {
+        Some(count) => Some(
+            usize::try_from(parse_integer(count)?)
+                .map_err(|_| Error::ValidationError("value is out of range, must be positive".to_string()))?,
+        ),
+        None => None,
+    };
+    let popped = pop_entries(ctx, &args[1], highest, count.unwrap_or(1))?;
+    match count {
+        Some(_) => Ok(scored_members_reply(ctx.client.protocol, popped, true)),
+        // A single pair is flat in RESP3 too.
+        None => Ok(scored_members_reply(ProtocolVersion::Resp2, popped, true)),
+    }
+}
+
+/// The arguments of `ZMPOP` and `BZMPOP` after the timeout: `numkeys key [key ...] MIN|MAX
+/// [COUNT count]`.
+struct MultiPop<'a> {
+    keys: &'a [Vec<u8>],
+    highest: bool,
+    count: usize,
+}
+
+impl MultiPop<'_> {
+    fn parse(args: &[Vec<u8>]) -> Result<MultiPop<'_>> {
+        let numkeys = parse_integer(&args[0])?;
+        if numkeys <= 0 {
+            return Err(Error::ValidationError("numkeys should be greater than 0".to_string()));
+        }
+        // The keys have to be followed by at least the end to pop from.
+        let numkeys = usize::try_from(numkeys)
+            .ok()
+            .filter(|&numkeys| numkeys < args.len() - 1)
+            .ok_or_else(syntax_error)?;
+        let keys = &args[1..=numkeys];
+        let highest = match args[numkeys + 1].to_ascii_lowercase().as_slice() {
+            b"min" => false,
+            b"max" => true,
+            _ => return Err(syntax_error()),
+        };
+        let count = match &args[numkeys + 2..] {
+            [] => 1,
+            [option, count] if option.eq_ignore_ascii_case(b"count") => usize::try_from(parse_integer(count)?)
+                .ok()
+                .filter(|&count| count > 0)
+                .ok_or_else(|| Error::ValidationError("count should be greater than 0".to_string()))?,
+            _ => return Err(syntax_error()),
+        };
+        Ok(MultiPop { keys, highest, count })
+    }
+
+    /// Pops from the first non-empty sorted set, replying with its key and the popped pairs.
+    fn pop(&self, ctx: &mut Context) -> Result<Option<RespVal>> {
+        for key in self.keys {
+            if ctx.get_sorted_set(key)?.is_some() {
+                let pairs = pop_entries(ctx, key, self.highest, self.count)?
+                    .into_iter()
+                    .map(|(member, score)| RespVal::Array(vec![RespVal::BulkString(member), RespVal::Double(score)]))
+                    .collect();
+                return Ok(Some(RespVal::Array(vec![RespVal::BulkString(key.clone()), RespVal::Array(pairs)])));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn zmpop(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let reply = MultiPop::parse(&args[1..])?.pop(ctx)?;
+    Ok(reply.unwrap_or(RespVal::NullArray))
+}
+
+fn bzmpop(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let timeout = parse_timeout(&args[1])?;
+    let multi_pop = MultiPop::parse(&args[2..])?;
+    match multi_pop.pop(ctx)? {
+        Some(reply) => Ok(reply),
+        None => {
+            ctx.block_on(multi_pop.keys, timeout);
+            Ok(RespVal::NullArray)
+        }
+    }
+}
+
+fn bzpopmin(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    blocking_pop(ctx, args, false)
+}
+
+fn bzpopmax(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    blocking_pop(ctx, args, true)
+}
+
+/// Pops a member from the first non-empty sorted set of the keys, replying with the key, the
+/// member and its score, or waits for one of the keys to be added to.
+fn blocking_pop(ctx: &mut Context, args: &[Vec<u8>], highest: bool) -> Result<RespVal> {
+    let (keys, timeout) = args[1..].split_at(args.len() - 2);
+    let timeout = parse_timeout(&timeout[0])?;
+    for key in keys {
+        if let Some((member, score)) = pop_entries(ctx, key, highest, 1)?.pop() {
+            return Ok(RespVal::Array(vec![
+                RespVal::BulkString(key.clone()),
+                RespVal::BulkString(member),
+                RespVal::Double(score),
+            ]));
+        }
+    }
+    ctx.block_on(keys, timeout);
+    Ok(RespVal::NullArray)
+}
+
+/// An input of `ZUNION` and the other operations on several keys: a sorted set, or a set whose
+/// members all score 1 like in Redis.
+#[derive(Clone, Copy)]
+enum Input<'a> {
+    Set(&'a Set),
+    SortedSet(&'a SortedSet),
+}
+
+impl Input<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Input::Set(set) => set.len(),
+            Input::SortedSet(set) => set.len(),
+        }
+    }
+
+    fn score(&self, member: &[u8]) -> Option<f64> {
+        match self {
+            Input::Set(set) => set.contains(member).then_some(1.0),
+            Input::SortedSet(set) => set.score(member),
+        }
+    }
+
+    fn entries(&self) -> Vec<(Vec<u8>, f64)> {
+        match self {
+            Input::Set(set) => set.members().into_iter().map(|member| (member, 1.0)).collect(),
+            Input::SortedSet(set) => set.iter().map(|(member, score)| (member.clone(), score)).collect(),
+        }
+    }
+}
+
+fn inputs_at<'a>(ctx: &'a mut Context, keys: &[Vec<u8>]) -> Result<Vec<Option<Input<'a>>>> {
+    // Expire and check every key first, the values are borrowed together afterwards.
+    for key in keys {
+        if let Some(value) = ctx.get_value(key) {
+            if !matches!(value.data, Data::Set(_) | Data::SortedSet(_)) {
+                return Err(Error::WrongType);
+            }
+        }
+    }
+    let db = &*ctx.db;
+    Ok(keys
+        .iter()
+        .map(|key| {
+            db.get(key).map(|value| match &value.data {
+                Data::Set(set) => Input::Set(set),
+                Data::SortedSet(set) => Input::SortedSet(set),
+                _ => unreachable!("the type was checked"),
+            })
+        })
+        .collect())
+}
+
+/// How the scores of a member in several inputs combine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    fn apply(self, score: f64, other: f64) -> f64 {
+        let result = match self {
+            Aggregate::Sum => score + other,
+            Aggregate::Min => score.min(other),
+            Aggregate::Max => score.max(other),
+        };
+        // Like Redis, `inf + -inf` counts as 0.
+        if result.is_nan() {
+            0.0
+        } else {
+            result
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortedSetOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+/// The arguments of `ZUNION` and the other operations from `numkeys` on: `numkeys key [key
+/// ...] [WEIGHTS weight [weight ...]] [AGGREGATE SUM|MIN|MAX] [WITHSCORES]`. The difference
+/// takes neither weights nor an aggregate, the variants that store take no `WITHSCORES`.
+struct Combination<'a> {
+    operation: SortedSetOperation,
+    keys: &'a [Vec<u8>],
+    weights: Vec<f64>,
+    aggregate: Aggregate,
+    with_scores: bool,
+}
+
+impl Combination<'_> {
+    fn parse<'a>(command_name: &[u8], args: &'a [Vec<u8>], operation: SortedSetOperation, store: bool) -> Result<Combination<'a>> {
+        let numkeys = parse_integer(&args[0])?;
+        if numkeys <= 0 {
+            return Err(Error::ValidationError(format!(
+                "at least 1 input key is needed for '{}' command",
+                String::from_utf8_lossy(command_name).to_lowercase(),
+            )));
+        }
+        let numkeys = usize::try_from(numkeys)
+            .ok()
+            .filter(|&numkeys| numkeys < args.len())
+            .ok_or_else(syntax_error)?;
+        let mut combination = Combination {
+            operation,
+            keys: &args[1..=numkeys],
+            weights: vec![1.0; numkeys],
+            aggregate: Aggregate::Sum,
+            with_scores: false,
+        };
+        let accepts_weights = operation != SortedSetOperation::Difference;
+        let mut options = args[numkeys + 1..].iter();
+        while let Some(option) = options.next() {
+            match option.to_ascii_lowercase().as_slice() {
+                b"weights" if accepts_weights => {
+                    for weight in combination.weights.iter_mut() {
+                        let arg = options.next().ok_or_else(syntax_error)?;
+                        *weight = parse_score(arg)
+                            .map_err(|_| Error::ValidationError("weight value is not a float".to_string()))?;
+                    }
+                }
+                b"aggregate" if accepts_weights => {
+                    let aggregate = options.next().ok_or_else(syntax_error)?;
+                    combination.aggregate = match aggregate.to_ascii_lowercase().as_slice() {
+                        b"sum" => Aggregate::Sum,
+                        b"min" => Aggregate::Min,
+                        b"max" => Aggregate::Max,
+                        _ => return Err(syntax_error()),
+                    };
+                }
+                b"withscores" if !store => combination.with_scores = true,
+                _ => return Err(syntax_error()),
+            }
+        }
+        Ok(combination)
+    }
+
+    fn apply(&self, inputs: &[Option<Input>]) -> SortedSet {
+        let weighted = |score: f64, weight: f64| {
+            let score = score * weight;
+            // `0 * inf` counts as 0 too.
+            if score.is_nan() {
+                0.0
+            } else {
+                score
+            }
+        };
+        match self.operation {
+            SortedSetOperation::Union => {
+                let mut scores: HashMap<Vec<u8>, f64> = HashMap::new();
+                for (input, &weight) in inputs.iter().zip(&self.weights) {
+                    for (member, score) in input.iter().flat_map(Input::entries) {
+                        let score = weighted(score, weight);
+                        scores
+                            .entry(member)
+                            .and_modify(|total| *total = self.aggregate.apply(*total, score))
+                            .or_insert(score);
+                    }
+                }
+                scores.into_iter().collect()
+            }
+            SortedSetOperation::Intersection => {
+                let inputs = match inputs.iter().copied().collect::<Option<Vec<Input>>>() {
+                    Some(inputs) => inputs,
+                    None => return SortedSet::default(),
+                };
+                // Only members of the smallest input can be in the intersection.
+                let smallest = inputs.iter().min_by_key(|input| input.len()).expect("there is at least one key");
+                smallest
+                    .entries()
+                    .into_iter()
+                    .filter_map(|(member, _)| {
+                        let mut scores = inputs
+                            .iter()
+                            .zip(&self.weights)
+                            .map(|(input, &weight)| Some(weighted(input.score(&member)?, weight)));
+                        let first = scores.next().expect("there is at least one key")?;
+                        let total = scores.try_fold(first, |total, score| Some(self.aggregate.apply(total, score?)))?;
+                        Some((member, total))
+                    })
+                    .collect()
+            }
+            SortedSetOperation::Difference => match inputs.split_first() {
+                Some((Some(first), others)) => first
+                    .entries()
+                    .into_iter()
+                    .filter(|(member, _)| !others.iter().flatten().any(|input| input.score(member).is_some()))
+                    .collect(),
+                _ => SortedSet::default(),
+            },
+        }
+    }
+}
+
+fn zunion(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    combine(ctx, args, SortedSetOperation::Union)
+}
+
+fn zinter(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    combine(ctx, args, SortedSetOperation::Intersection)
+}
+
+fn zdiff(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    combine(ctx, args, SortedSetOperation::Difference)
+}
+
+fn combine(ctx: &mut Context, args: &[Vec<u8>], operation: SortedSetOperation) -> Result<RespVal> {
+    let combination = Combination::parse(&args[0], &args[1..], operation, false)?;
+    let result = combination.apply(&inputs_at(ctx, combination.keys)?);
+    let entries = result.iter().map(|(member, score)| (member.clone(), score)).collect();
+    Ok(scored_members_reply(ctx.client.protocol, entries, combination.with_scores))
+}
+
+fn zunionstore(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    combine_and_store(ctx, args, SortedSetOperation::Union)
+}
+
+fn zinterstore(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    combine_and_store(ctx, args, SortedSetOperation::Intersection)
+}
+
+fn zdiffstore(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    combine_and_store(ctx, args, SortedSetOperation::Difference)
+}
+
+fn combine_and_store(ctx: &mut Context, args: &[Vec<u8>], operation: SortedSetOperation) -> Result<RespVal> {
+    let combination = Combination::parse(&args[0], &args[2..], operation, true)?;
+    let result = combination.apply(&inputs_at(ctx, combination.keys)?);
+    Ok(RespVal::Integer(store(ctx, &args[1], result) as i64))
+}
+
+/// Replies with the size of the intersection, counting no further than a non-zero `LIMIT`.
+fn zintercard(ctx: &mut Context, args: &[Vec<u8>]) -> Result<RespVal> {
+    let numkeys = parse_integer(&args[1])?;
+    if numkeys <= 0 {
+        return Err(Error::ValidationError("numkeys should be greater than 0".to_string()));
+    }
+    let numkeys = usize::try_from(numkeys)
+        .ok()
+        .filter(|&numkeys| numkeys <= args.len() - 2)
+        .ok_or_else(|| Error::ValidationError("Number of keys can't be greater than number of args".to_string()))?;
+    let keys = &args[2..2 + numkeys];
+    let limit = match &args[2 + numkeys..] {
+        [] => 0,
+        [option, limit] if option.eq_ignore_ascii_case(b"limit") => usize::try_from(parse_integer(limit)?)
+            .map_err(|_| Error::ValidationError("LIMIT can't be negative".to_string()))?,
+        _ => return Err(syntax_error()),
+    };
+    let inputs = match inputs_at(ctx, keys)?.into_iter().collect::<Option<Vec<Input>>>() {
+        Some(inputs) => inputs,
+        None => return Ok(RespVal::Integer(0)),
+    };
+    let smallest = inputs.iter().min_by_key(|input| input.len()).expect("there is at least one key");
+    let limit = if limit == 0 { usize::MAX } else { limit };
+    let count = smallest
+        .entries()
+        .into_iter()
+        .filter(|(member, _)| inputs.iter().all(|input| input.score(member).is_some()))
+        .take(limit)
+        .count();
+    Ok(RespVal::Integer(count as i64))
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::test::{new_database, run, run_blocked};
+    use super::*;
+    use crate::client::Client;
+
+    fn bulk_array(members: &[&str]) -> RespVal {
+        RespVal::Array(members.iter().map(|member| RespVal::BulkString(member.as_bytes().to_vec())).collect())
+    }
+
+    #[test]
+    fn test_zadd_options() {
+        let map = new_database();
+        let mut client = Client::new();
+        assert_eq!(run(&map, &mut client, &["zadd", "z", "1", "a", "2", "b"]).unwrap(), RespVal::Integer(2));
+        assert_eq!(run(&map, &mut client, &["zadd", "z", "xx", "ch", "5", "a", "3", "c"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["zadd", "z", "nx", "9", "a", "3", "c"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["zadd", "z", "gt", "ch", "4", "a", "6", "b"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["zadd", "z", "lt", "incr", "1", "a"]).unwrap(), RespVal::Null);
+        assert_eq!(run(&map, &mut client, &["zadd", "z", "incr", "-1.5", "a"]).unwrap(), RespVal::Double(3.5));
+        assert_eq!(run(&map, &mut client, &["zscore", "z", "b"]).unwrap(), RespVal::Double(6.0));
+        assert_eq!(run(&map, &mut client, &["zincrby", "z", "+inf", "c"]).unwrap(), RespVal::Double(f64::INFINITY));
+        assert!(run(&map, &mut client, &["zincrby", "z", "-inf", "c"]).is_err());
+        assert!(run(&map, &mut client, &["zadd", "z", "nx", "xx", "1", "a"]).is_err());
+        assert!(run(&map, &mut client, &["zadd", "z", "gt", "lt", "1", "a"]).is_err());
+        assert!(run(&map, &mut client, &["zadd", "z", "incr", "1", "a", "2", "b"]).is_err());
+        assert!(run(&map, &mut client, &["zadd", "z", "1", "a", "nan", "b"]).is_err());
+        assert!(run(&map, &mut client, &["zadd", "z", "1"]).is_err());
+        assert_eq!(run(&map, &mut client, &["zadd", "missing", "xx", "1", "a"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["exists", "missing"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["zrem", "z", "a", "b", "x"]).unwrap(), RespVal::Integer(2));
+        assert_eq!(run(&map, &mut client, &["zcard", "z"]).unwrap(), RespVal::Integer(1));
+    }
+
+    #[test]
+    fn test_rank_and_count() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["zadd", "z", "1", "a", "2", "b", "2", "c", "3", "d"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["zrank", "z", "c"]).unwrap(), RespVal::Integer(2));
+        assert_eq!(run(&map, &mut client, &["zrevrank", "z", "c"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(
+            run(&map, &mut client, &["zrank", "z", "d", "withscore"]).unwrap(),
+            RespVal::Array(vec![RespVal::Integer(3), RespVal::Double(3.0)])
+        );
+        assert_eq!(run(&map, &mut client, &["zrank", "z", "x"]).unwrap(), RespVal::Null);
+        assert_eq!(run(&map, &mut client, &["zcount", "z", "(1", "+inf"]).unwrap(), RespVal::Integer(3));
+        assert_eq!(run(&map, &mut client, &["zcount", "z", "-inf", "(2"]).unwrap(), RespVal::Integer(1));
+        assert!(run(&map, &mut client, &["zcount", "z", "x", "1"]).is_err());
+    }
+
+    #[test]
+    fn test_zrange() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["zadd", "z", "1", "a", "2", "b", "2", "c", "3", "d"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["zrange", "z", "1", "-2"]).unwrap(), bulk_array(&["b", "c"]));
+        assert_eq!(run(&map, &mut client, &["zrange", "z", "0", "1", "rev"]).unwrap(), bulk_array(&["d", "c"]));
+        assert_eq!(run(&map, &mut client, &["zrange", "z", "(1", "2", "byscore"]).unwrap(), bulk_array(&["b", "c"]));
+        assert_eq!(
+            run(&map, &mut client, &["zrange", "z", "+inf", "-inf", "byscore", "rev", "limit", "1", "2"]).unwrap(),
+            bulk_array(&["c", "b"])
+        );
+        assert_eq!(run(&map, &mut client, &["zrangebyscore", "z", "2", "2", "limit", "1", "-1"]).unwrap(), bulk_array(&["c"]));
+        assert_eq!(
+            run(&map, &mut client, &["zrange", "z", "0", "0", "withscores"]).unwrap(),
+            RespVal::Array(vec![RespVal::BulkString(b"a".to_vec()), RespVal::Double(1.0)])
+        );
+        client.protocol = ProtocolVersion::Resp3;
+        assert_eq!(
+            run(&map, &mut client, &["zrevrange", "z", "0", "0", "withscores"]).unwrap(),
+            RespVal::Array(vec![RespVal::Array(vec![RespVal::BulkString(b"d".to_vec()), RespVal::Double(3.0)])])
+        );
+        run(&map, &mut client, &["zadd", "lex", "0", "a", "0", "b", "0", "c"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["zrange", "lex", "(a", "+", "bylex"]).unwrap(), bulk_array(&["b", "c"]));
+        assert_eq!(run(&map, &mut client, &["zrevrangebylex", "lex", "[b", "-"]).unwrap(), bulk_array(&["b", "a"]));
+        assert!(run(&map, &mut client, &["zrange", "lex", "a", "+", "bylex"]).is_err());
+        assert!(run(&map, &mut client, &["zrange", "z", "0", "1", "limit", "0", "1"]).is_err());
+        assert!(run(&map, &mut client, &["zrange", "lex", "-", "+", "bylex", "withscores"]).is_err());
+        assert!(run(&map, &mut client, &["zrangebyscore", "z", "0", "1", "rev"]).is_err());
+        assert_eq!(run(&map, &mut client, &["zrange", "missing", "0", "-1"]).unwrap(), bulk_array(&[]));
+    }
+
+    #[test]
+    fn test_combinations() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["zadd", "a", "1", "x", "2", "y", "3", "z"]).unwrap();
+        run(&map, &mut client, &["zadd", "b", "4", "y", "1", "z", "5", "w"]).unwrap();
+        run(&map, &mut client, &["sadd", "s", "y", "v"]).unwrap();
+        assert_eq!(run(&map, &mut client, &["zunion", "2", "a", "b"]).unwrap(), bulk_array(&["x", "z", "w", "y"]));
+        assert_eq!(
+            run(&map, &mut client, &["zunion", "3", "a", "b", "s", "weights", "1", "0", "2", "aggregate", "max", "withscores"]).unwrap(),
+            RespVal::Array(vec![
+                RespVal::BulkString(b"w".to_vec()),
+                RespVal::Double(0.0),
+                RespVal::BulkString(b"x".to_vec()),
+                RespVal::Double(1.0),
+                RespVal::BulkString(b"v".to_vec()),
+                RespVal::Double(2.0),
+                RespVal::BulkString(b"y".to_vec()),
+                RespVal::Double(2.0),
+                RespVal::BulkString(b"z".to_vec()),
+                RespVal::Double(3.0),
+            ])
+        );
+        assert_eq!(run(&map, &mut client, &["zinterstore", "out", "3", "a", "b", "s"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["zscore", "out", "y"]).unwrap(), RespVal::Double(7.0));
+        assert_eq!(run(&map, &mut client, &["zinter", "2", "a", "b", "aggregate", "min"]).unwrap(), bulk_array(&["z", "y"]));
+        assert_eq!(run(&map, &mut client, &["zdiff", "2", "a", "b"]).unwrap(), bulk_array(&["x"]));
+        assert_eq!(run(&map, &mut client, &["zdiffstore", "out", "2", "a", "a"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["exists", "out"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["zintercard", "2", "a", "b", "limit", "1"]).unwrap(), RespVal::Integer(1));
+        assert_eq!(run(&map, &mut client, &["zintercard", "2", "a", "missing"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["zrangestore", "out", "b", "(1", "+inf", "byscore"]).unwrap(), RespVal::Integer(2));
+        assert_eq!(run(&map, &mut client, &["zrange", "out", "0", "-1"]).unwrap(), bulk_array(&["y", "w"]));
+        assert!(run(&map, &mut client, &["zrangestore", "out", "b", "0", "-1", "withscores"]).is_err());
+        assert!(run(&map, &mut client, &["zunion", "0", "a"]).is_err());
+        assert!(run(&map, &mut client, &["zunion", "3", "a", "b"]).is_err());
+        assert!(run(&map, &mut client, &["zunion", "2", "a", "b", "weights", "1"]).is_err());
+        assert!(run(&map, &mut client, &["zdiff", "2", "a", "b", "aggregate", "sum"]).is_err());
+        assert!(run(&map, &mut client, &["zunionstore", "out", "1", "a", "withscores"]).is_err());
+        run(&map, &mut client, &["set", "string", "v"]).unwrap();
+        assert!(run(&map, &mut client, &["zunion", "2", "a", "string"]).is_err());
+    }
+
+    #[test]
+    fn test_pop_and_random() {
+        let map = new_database();
+        let mut client = Client::new();
+        run(&map, &mut client, &["zadd", "z", "1", "a", "2", "b", "3", "c", "4", "d"]).unwrap();
+        assert_eq!(
+            run(&map, &mut client, &["zpopmin", "z"]).unwrap(),
+            RespVal::Array(vec![RespVal::BulkString(b"a".to_vec()), RespVal::Double(1.0)])
+        );
+        client.protocol = ProtocolVersion::Resp3;
+        assert_eq!(
+            run(&map, &mut client, &["zpopmax", "z", "1"]).unwrap(),
+            RespVal::Array(vec![RespVal::Array(vec![RespVal::BulkString(b"d".to_vec()), RespVal::Double(4.0)])])
+        );
+        assert_eq!(
+            run(&map, &mut client, &["zmscore", "z", "b", "x"]).unwrap(),
+            RespVal::Array(vec![RespVal::Double(2.0), RespVal::Null])
+        );
+        let reply = run(&map, &mut client, &["zrandmember", "z", "-5", "withscores"]).unwrap();
+        assert!(matches!(reply, RespVal::Array(pairs) if pairs.len() == 5));
+        assert_eq!(run(&map, &mut client, &["zrandmember", "z", "5"]).unwrap(), bulk_array(&["b", "c"]));
+        assert!(run(&map, &mut client, &["zrandmember", "z", "5000000000000000000", "withscores"]).is_err());
+        assert!(run(&map, &mut client, &["zpopmin", "z", "-1"]).is_err());
+        assert_eq!(
+            run(&map, &mut client, &["zmpop", "2", "missing", "z", "max", "count", "5"]).unwrap(),
+            RespVal::Array(vec![
+                RespVal::BulkString(b"z".to_vec()),
+                RespVal::Array(vec![
+                    RespVal::Array(vec![RespVal::BulkString(b"c".to_vec()), RespVal::Double(3.0)]),
+                    RespVal::Array(vec![RespVal::BulkString(b"b".to_vec()), RespVal::Double(2.0)]),
+                ]),
+            ])
+        );
+        assert_eq!(run(&map, &mut client, &["exists", "z"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(run(&map, &mut client, &["zmpop", "1", "z", "min"]).unwrap(), RespVal::NullArray);
+        assert_eq!(run(&map, &mut client, &["zpopmin", "z"]).unwrap(), bulk_array(&[]));
+        assert_eq!(run(&map, &mut client, &["zrandmember", "z"]).unwrap(), RespVal::Null);
+    }
+
+    #[test]
+    fn test_blocking_pop() {
+        let map = new_database();
+        let (mut first, mut second, mut writer) = (Client::new(), Client::new(), Client::new());
+        run(&map, &mut writer, &["zadd", "ready", "1", "x"]).unwrap();
+        assert_eq!(
+            run(&map, &mut first, &["bzpopmin", "empty", "ready", "0"]).unwrap(),
+            RespVal::Array(vec![
+                RespVal::BulkString(b"ready".to_vec()),
+                RespVal::BulkString(b"x".to_vec()),
+                RespVal::Double(1.0),
+            ])
+        );
+        let mut first_blocked = run_blocked(&map, &mut first, &["bzpopmin", "a", "b", "0"]);
+        let mut second_blocked = run_blocked(&map, &mut second, &["bzpopmax", "b", "0.5"]);
+        assert_eq!(second_blocked.timeout_reply, RespVal::NullArray);
+        // The client that blocked first is served first.
+        run(&map, &mut writer, &["zadd", "b", "1", "one", "2", "two", "3", "three"]).unwrap();
+        let popped = |member: &str, score| {
+            RespVal::Array(vec![
+                RespVal::BulkString(b"b".to_vec()),
+                RespVal::BulkString(member.as_bytes().to_vec()),
+                RespVal::Double(score),
+            ])
+        };
+        assert_eq!(first_blocked.receiver.try_recv().unwrap().unwrap(), popped("one", 1.0));
+        assert_eq!(second_blocked.receiver.try_recv().unwrap().unwrap(), popped("three", 3.0));
+        assert_eq!(run(&map, &mut writer, &["zrange", "b", "0", "-1"]).unwrap(), bulk_array(&["two"]));
+        let mut blocked = run_blocked(&map, &mut first, &["bzmpop", "0", "2", "c", "d", "max", "count", "2"]);
+        run(&map, &mut writer, &["zadd", "d", "1", "x", "2", "y"]).unwrap();
+        assert_eq!(
+            blocked.receiver.try_recv().unwrap().unwrap(),
+            RespVal::Array(vec![
+                RespVal::BulkString(b"d".to_vec()),
+                RespVal::Array(vec![
+                    RespVal::Array(vec![RespVal::BulkString(b"y".to_vec()), RespVal::Double(2.0)]),
+                    RespVal::Array(vec![RespVal::BulkString(b"x".to_vec()), RespVal::Double(1.0)]),
+                ]),
+            ])
+        );
+        assert_eq!(run(&map, &mut writer, &["exists", "d"]).unwrap(), RespVal::Integer(0));
+        assert_eq!(map.lock().unwrap().blocked.count(), 0);
+        assert!(run(&map, &mut first, &["bzpopmin", "a", "-1"]).is_err());
+    }
+}
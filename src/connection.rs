@@ -0,0 +1,58 @@
+use crate::error::{Error, Result};
+use crate::resp::{RequestDecoder, RespVal};
+use bytes::{Buf, BytesMut};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+const INITIAL_BUFFER_CAPACITY: usize = 16 * 1024;
+
+/// A client connection that decodes RESP requests from a byte stream.
+///
+/// Incoming bytes are accumulated in `buffer` until they form complete frames, so requests may
+/// be split across reads and several pipelined requests may arrive in a single read. The
+/// arguments of a request are moved out of the buffer as they arrive.
+pub struct Connection {
+    stream: TcpStream,
+    buffer: BytesMut,
+    decoder: RequestDecoder,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Connection {
+        Connection {
+            stream,
+            buffer: BytesMut::with_capacity(INITIAL_BUFFER_CAPACITY),
+            decoder: RequestDecoder::default(),
+        }
+    }
+
+    /// Removes the next complete frame from the buffer.
+    ///
+    /// Returns `Ok(None)` if the buffered bytes are only the beginning of a frame.
+    pub fn next_frame(&mut self) -> Result<Option<RespVal>> {
+        let (consumed, frame) = self.decoder.decode(&self.buffer)?;
+        self.buffer.advance(consumed);
+        Ok(frame)
+    }
+
+    /// Reads more bytes from the socket into the buffer.
+    ///
+    /// Returns `Ok(false)` once the peer closed the connection. Closing in the middle of a
+    /// frame is reported as an error.
+    pub async fn fill_buffer(&mut self) -> Result<bool> {
+        if self.stream.read_buf(&mut self.buffer).await? == 0 {
+            if self.buffer.is_empty() && !self.decoder.is_partial() {
+                return Ok(false);
+            }
+            return Err(Error::ParseError(
+                "Connection closed in the middle of a frame".to_string(),
+            ));
+        }
+        Ok(true)
+    }
+
+    pub async fn write_all(&mut self, bytes: &[u8]) -> Result<()> {
+        self.stream.write_all(bytes).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,367 @@
+use crate::blocking::BlockedClients;
+use crate::dict::Dict;
+use crate::random;
+use crate::{Data, Value};
+use std::collections::HashMap;
+use std::ops::Index;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Keys sampled per round of the active expire cycle.
+const ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP: usize = 20;
+/// Another round is sampled as long as more than this percentage of the sample was expired.
+const ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE: usize = 10;
+/// The elapsed time is only checked every this many rounds, as reading the clock isn't free.
+const ACTIVE_EXPIRE_CYCLE_TIME_CHECK_INTERVAL: usize = 16;
+/// How often the active expire cycle runs, like Redis' default `hz` of 10.
+pub const ACTIVE_EXPIRE_CYCLE_PERIOD: Duration = Duration::from_millis(100);
+/// How long one run of the active expire cycle may take: 25% of its period.
+pub const ACTIVE_EXPIRE_CYCLE_BUDGET: Duration = Duration::from_millis(25);
+/// Time spent per period on rehashing the keyspace, if it is being resized.
+pub const REHASH_BUDGET: Duration = Duration::from_millis(1);
+
+/// Counters reported in the stats section of `INFO`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ExpireStats {
+    /// Keys deleted because their expiration time passed, either on access or actively.
+    pub expired_keys: u64,
+    /// Moving average of the percentage of expired keys among the sampled ones.
+    pub expired_stale_perc: f64,
+    /// How often the active expire cycle stopped because it ran out of time.
+    pub expired_time_cap_reached_count: u64,
+    /// Hash fields deleted because their expiration time passed.
+    pub expired_subkeys: u64,
+}
+
+/// A set of keys that can be sampled at random in O(1).
+#[derive(Debug, Default)]
+struct KeySample {
+    keys: Vec<Vec<u8>>,
+    /// Position of each key in `keys`.
+    positions: HashMap<Vec<u8>, usize>,
+}
+
+impl KeySample {
+    fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn insert(&mut self, key: &[u8]) {
+        if !self.positions.contains_key(key) {
+            self.positions.insert(key.to_vec(), self.keys.len());
+            self.keys.push(key.to_vec());
+        }
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        if let Some(position) = self.positions.remove(key) {
+            self.keys.swap_remove(position);
+            if let Some(moved_key) = self.keys.get(position) {
+                *self.positions.get_mut(moved_key).expect("every key has a position") = position;
+            }
+        }
+    }
+
+    fn random(&self) -> Option<&Vec<u8>> {
+        (!self.is_empty()).then(|| &self.keys[random::random_below(self.keys.len())])
+    }
+}
+
+/// The keyspace.
+///
+/// Besides the values, it keeps the keys that have an expiration time and the hashes with
+/// fields that have one, so that the active expire cycle can sample them at random, and the
+/// clients blocked on its keys.
+#[derive(Debug, Default)]
+pub struct Db {
+    /// The database index, as selected by `SELECT`.
+    id: usize,
+    entries: Dict<Value>,
+    volatile_keys: KeySample,
+    /// Hashes that may have fields with an expiration time.
+    volatile_hashes: KeySample,
+    pub stats: ExpireStats,
+    pub blocked: BlockedClients,
+}
+
+impl Db {
+    pub fn new() -> Db {
+        Db::default()
+    }
+
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The value stored at `key`, even if it has expired.
+    pub fn get(&self, key: &[u8]) -> Option<&Value> {
+        self.entries.get(key)
+    }
+
+    /// The value stored at `key`, even if it has expired. Its expiration time must be changed
+    /// through [`Db::set_expiration_time`].
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut Value> {
+        self.entries.get_mut(key)
+    }
+
+    /// Stores `value` at `key`. Clients blocked on `key` are woken up to check the new value.
+    pub fn insert(&mut self, key: Vec<u8>, value: Value) -> Option<Value> {
+        if value.expiration_time.is_some() {
+            self.volatile_keys.insert(&key);
+        } else {
+            self.volatile_keys.remove(&key);
+        }
+        match &value.data {
+            Data::Hash(hash) if hash.has_volatile_fields() => self.volatile_hashes.insert(&key),
+            _ => self.volatile_hashes.remove(&key),
+        }
+        self.blocked.signal_key_as_ready(self.id, &key);
+        self.entries.insert(key, value)
+    }
+
+    /// Wakes up the clients blocked on `key` after its value was changed in place.
+    pub fn signal_key_as_ready(&mut self, key: &[u8]) {
+        self.blocked.signal_key_as_ready(self.id, key);
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<Value> {
+        self.volatile_keys.remove(key);
+        self.volatile_hashes.remove(key);
+        self.entries.remove(key)
+    }
+
+    /// Changes the expiration time of the value at `key`; returns false if there is none.
+    pub fn set_expiration_time(&mut self, key: &[u8], expiration_time: Option<SystemTime>) -> bool {
+        let value = match self.entries.get_mut(key) {
+            Some(value) => value,
+            None => return false,
+        };
+        value.expiration_time = expiration_time;
+        if expiration_time.is_some() {
+            self.volatile_keys.insert(key);
+        } else {
+            self.volatile_keys.remove(key);
+        }
+        true
+    }
+
+    /// Notes that fields of the hash at `key` may have been given an expiration time, so that
+    /// the active expire cycle looks at it.
+    pub fn track_volatile_fields(&mut self, key: &[u8]) {
+        if self.entries.get(key).is_some() {
+            self.volatile_hashes.insert(key);
+        }
+    }
+
+    /// Number of keys, including expired ones that haven't been reclaimed yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of keys with an expiration time.
+    pub fn volatile_len(&self) -> usize {
+        self.volatile_keys.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Value)> {
+        self.entries.iter()
+    }
+
+    /// A random key and its value, which may have expired.
+    pub fn random_entry(&self) -> Option<(&Vec<u8>, &Value)> {
+        self.entries.random_entry()
+    }
+
+    /// Visits a part of the keyspace, see [`Dict::scan`].
+    pub fn scan(&self, cursor: u64, visit: impl FnMut(&Vec<u8>, &Value)) -> u64 {
+        self.entries.scan(cursor, visit)
+    }
+
+    /// Deletes the value at `key` if it has expired, and returns whether it did. Expired hash
+    /// fields are deleted too, and with them the hash if no fields remain.
+    pub fn expire_if_needed(&mut self, key: &[u8], now: SystemTime) -> bool {
+        match self.entries.get(key) {
+            Some(value) if value.is_expired_at(now) => {
+                self.remove(key);
+                self.stats.expired_keys += 1;
+                true
+            }
+            Some(Value { data: Data::Hash(hash), .. }) if hash.next_expiration_time().is_some_and(|time| time <= now) => {
+                self.expire_fields(key, now) == FieldsExpired::All
+            }
+            _ => false,
+        }
+    }
+
+    /// Deletes the expired fields of the hash at `key`, and the hash if none remain.
+    fn expire_fields(&mut self, key: &[u8], now: SystemTime) -> FieldsExpired {
+        let hash = match self.entries.get_mut(key) {
+            Some(Value { data: Data::Hash(hash), .. }) => hash,
+            _ => return FieldsExpired::None,
+        };
+        let expired = hash.remove_expired(now);
+        self.stats.expired_subkeys += expired as u64;
+        if hash.is_empty() {
+            self.remove(key);
+            FieldsExpired::All
+        } else if expired > 0 {
+            FieldsExpired::Some
+        } else {
+            FieldsExpired::None
+        }
+    }
+
+    /// Spends up to `budget` on rehashing the keyspace, see [`Dict::rehash_for`].
+    pub fn rehash_for(&mut self, budget: Duration) {
+        self.entries.rehash_for(budget);
+    }
+
+    /// Reclaims expired keys the way Redis' slow active expire cycle does: it samples keys with
+    /// an expiration time, deletes the expired ones, and repeats while the sample contained a
+    /// considerable share of expired keys, until `budget` is used up. Whatever is left of the
+    /// budget goes to expiring hash fields the same way.
+    pub fn active_expire_cycle(&mut self, budget: Duration) {
+        let start = Instant::now();
+        let mut rounds = 0;
+        let (mut total_sampled, mut total_expired) = (0, 0);
+        while !self.volatile_keys.is_empty() {
+            let now = SystemTime::now();
+            let sampled = self.volatile_keys.len().min(ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP);
+            let mut expired = 0;
+            for _ in 0..sampled {
+                let key = match self.volatile_keys.random() {
+                    Some(key) => key.clone(),
+                    None => break,
+                };
+                if self.expire_if_needed(&key, now) {
+                    expired += 1;
+                }
+            }
+            total_sampled += sampled;
+            total_expired += expired;
+            rounds += 1;
+            if rounds % ACTIVE_EXPIRE_CYCLE_TIME_CHECK_INTERVAL == 0 && start.elapsed() > budget {
+                self.stats.expired_time_cap_reached_count += 1;
+                break;
+            }
+            if expired * 100 <= sampled * ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE {
+                break;
+            }
+        }
+        let current_perc = if total_sampled == 0 {
+            0.0
+        } else {
+            total_expired as f64 / total_sampled as f64
+        };
+        self.stats.expired_stale_perc = current_perc * 0.05 + self.stats.expired_stale_perc * 0.95;
+        if start.elapsed() < budget {
+            self.active_expire_fields_cycle(start, budget);
+        }
+    }
+
+    /// Samples hashes with expiring fields and deletes their expired fields, until few of the
+    /// sampled hashes had any or the time since `start` exceeds `budget`. Hashes whose fields
+    /// no longer expire are forgotten.
+    fn active_expire_fields_cycle(&mut self, start: Instant, budget: Duration) {
+        let mut rounds = 0;
+        while !self.volatile_hashes.is_empty() {
+            let now = SystemTime::now();
+            let sampled = self.volatile_hashes.len().min(ACTIVE_EXPIRE_CYCLE_KEYS_PER_LOOP);
+            let mut expired = 0;
+            for _ in 0..sampled {
+                let key = match self.volatile_hashes.random() {
+                    Some(key) => key.clone(),
+                    None => break,
+                };
+                let still_volatile = matches!(
+                    self.entries.get(&key),
+                    Some(Value { data: Data::Hash(hash), .. }) if hash.has_volatile_fields()
+                );
+                if !still_volatile {
+                    self.volatile_hashes.remove(&key);
+                } else if self.expire_fields(&key, now) != FieldsExpired::None {
+                    expired += 1;
+                }
+            }
+            rounds += 1;
+            if rounds % ACTIVE_EXPIRE_CYCLE_TIME_CHECK_INTERVAL == 0 && start.elapsed() > budget {
+                self.stats.expired_time_cap_reached_count += 1;
+                break;
+            }
+            if expired * 100 <= sampled * ACTIVE_EXPIRE_CYCLE_ACCEPTABLE_STALE {
+                break;
+            }
+        }
+    }
+}
+
+/// What [`Db::expire_fields`] deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldsExpired {
+    None,
+    Some,
+    /// All fields, and with them the hash.
+    All,
+}
+
+impl FromIterator<(Vec<u8>, Value)> for Db {
+    fn from_iter<I: IntoIterator<Item = (Vec<u8>, Value)>>(entries: I) -> Db {
+        let mut db = Db::new();
+        for (key, value) in entries {
+            db.insert(key, value);
+        }
+        db
+    }
+}
+
+impl Index<&[u8]> for Db {
+    type Output = Value;
+
+    fn index(&self, key: &[u8]) -> &Value {
+        self.entries.get(key).expect("the key exists")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expired(data: &[u8]) -> Value {
+        Value::expiring_from_millis(data.to_vec(), 1)
+    }
+
+    #[test]
+    fn test_volatile_keys_follow_expiration_times() {
+        let mut db = Db::new();
+        db.insert(b"a".to_vec(), expired(b"1"));
+        db.insert(b"b".to_vec(), expired(b"2"));
+        db.insert(b"c".to_vec(), Value::string(b"3".to_vec()));
+        assert_eq!(db.volatile_len(), 2);
+        db.remove(b"a");
+        db.insert(b"b".to_vec(), Value::string(b"2".to_vec()));
+        assert_eq!(db.volatile_len(), 0);
+        assert!(db.set_expiration_time(b"c", Some(SystemTime::now())));
+        assert!(!db.set_expiration_time(b"missing", None));
+        assert_eq!(db.volatile_keys.keys, vec![b"c".to_vec()]);
+        assert_eq!(db.volatile_keys.positions[b"c".as_slice()], 0);
+    }
+
+    #[test]
+    fn test_active_expire_cycle_reclaims_expired_keys() {
+        let mut db: Db = (0..1000).map(|i| (i.to_string().into_bytes(), expired(b"v"))).collect();
+        db.insert(b"persistent".to_vec(), Value::string(b"v".to_vec()));
+        db.active_expire_cycle(Duration::from_secs(60));
+        // Every sample is expired, so the cycle keeps going until none are left.
+        assert_eq!(db.len(), 1);
+        assert_eq!(db.volatile_len(), 0);
+        assert_eq!(db.stats.expired_keys, 1000);
+        assert!(db.stats.expired_stale_perc > 0.0);
+    }
+}
@@ -0,0 +1,244 @@
+/// A decimal floating point number `mantissa * 10^exponent`.
+///
+/// Redis computes INCRBYFLOAT with `long double`, which has enough precision that results
+/// like `0.1 + 0.2` print as `0.3` with 17 decimals. An `f64` can't reproduce that, so the
+/// increment is instead computed exactly in decimal, keeping up to ~36 significant digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decimal {
+    mantissa: i128,
+    exponent: i32,
+}
+
+/// Significant digits kept when parsing; leaves headroom in an `i128` for additions.
+const MAX_DIGITS: u32 = 36;
+/// Decimal places printed, like Redis' `%.17Lf`.
+const FRACTION_DIGITS: i32 = 17;
+/// Magnitudes from here on overflow a `long double`, so they count as infinite.
+const MAX_DECIMAL_EXPONENT: i64 = 4932;
+
+impl Decimal {
+    pub const ZERO: Decimal = Decimal {
+        mantissa: 0,
+        exponent: 0,
+    };
+
+    /// Parses a float in the syntax `strtold` accepts for decimal numbers, such as `-1.5`,
+    /// `.5`, `3.` or `5.0e3`. Returns `None` for anything else and for infinite values.
+    pub fn parse(input: &[u8]) -> Option<Decimal> {
+        let (negative, rest) = match input {
+            [b'-', rest @ ..] => (true, rest),
+            [b'+', rest @ ..] => (false, rest),
+            rest => (false, rest),
+        };
+        let mut mantissa: i128 = 0;
+        let mut exponent: i64 = 0;
+        let mut digits = 0;
+        let mut significant_digits = 0;
+        let mut seen_point = false;
+        let mut position = 0;
+        while let Some(&byte) = rest.get(position) {
+            match byte {
+                b'0'..=b'9' => {
+                    digits += 1;
+                    if significant_digits < MAX_DIGITS {
+                        mantissa = mantissa * 10 + i128::from(byte - b'0');
+                        if mantissa != 0 {
+                            significant_digits += 1;
+                        }
+                        if seen_point {
+                            exponent -= 1;
+                        }
+                    } else if !seen_point {
+                        // Digits beyond the precision still scale the integer part.
+                        exponent += 1;
+                    }
+                }
+                b'.' if !seen_point => seen_point = true,
+                _ => break,
+            }
+            position += 1;
+        }
+        if digits == 0 {
+            return None;
+        }
+        if let Some(&(b'e' | b'E')) = rest.get(position) {
+            let exponent_part = &rest[position + 1..];
+            let (exponent_negative, exponent_digits) = match exponent_part {
+                [b'-', digits @ ..] => (true, digits),
+                [b'+', digits @ ..] => (false, digits),
+                digits => (false, digits),
+            };
+            if exponent_digits.is_empty() || !exponent_digits.iter().all(u8::is_ascii_digit) {
+                return None;
+            }
+            let explicit_exponent = exponent_digits
+                .iter()
+                .try_fold(0i64, |acc, digit| acc.checked_mul(10)?.checked_add(i64::from(digit - b'0')))
+                .unwrap_or(i64::MAX / 2);
+            exponent = if exponent_negative {
+                exponent - explicit_exponent
+            } else {
+                exponent + explicit_exponent
+            };
+            position = rest.len();
+        }
+        if position != rest.len() {
+            return None;
+        }
+        if mantissa == 0 {
+            return Some(Decimal::ZERO);
+        }
+        if exponent + i64::from(significant_digits) > MAX_DECIMAL_EXPONENT {
+            return None;
+        }
+        if exponent < -(MAX_DECIMAL_EXPONENT + i64::from(MAX_DIGITS)) {
+            return Some(Decimal::ZERO);
+        }
+        let mantissa = if negative { -mantissa } else { mantissa };
+        Some(Decimal {
+            mantissa,
+            exponent: exponent as i32,
+        }.normalized())
+    }
+
+    /// Adds two decimals, rounding away the least significant digits of the operand with
+    /// the smaller exponent if the exact sum doesn't fit. Returns `None` on overflow.
+    pub fn checked_add(self, other: Decimal) -> Option<Decimal> {
+        let (high, mut low) = if self.exponent >= other.exponent {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        loop {
+            if low.mantissa == 0 {
+                return high.checked_magnitude();
+            }
+            let difference = (high.exponent - low.exponent) as u32;
+            let scaled = 10i128
+                .checked_pow(difference)
+                .and_then(|factor| high.mantissa.checked_mul(factor))
+                .and_then(|scaled| scaled.checked_add(low.mantissa));
+            if let Some(mantissa) = scaled {
+                let sum = Decimal {
+                    mantissa,
+                    exponent: low.exponent,
+                };
+                return sum.normalized().checked_magnitude();
+            }
+            low = low.rounded_to_exponent(low.exponent + 1);
+        }
+    }
+
+    /// Formats like Redis' human readable long double output: fixed point with up to 17
+    /// decimal places and without trailing zeros.
+    pub fn to_human_string(self) -> String {
+        let decimal = if self.exponent < -FRACTION_DIGITS {
+            self.rounded_to_exponent(-FRACTION_DIGITS)
+        } else {
+            self
+        };
+        if decimal.mantissa == 0 {
+            return "0".to_string();
+        }
+        let digits = decimal.mantissa.unsigned_abs().to_string();
+        let mut result = String::with_capacity(digits.len() + 20);
+        if decimal.mantissa < 0 {
+            result.push('-');
+        }
+        if decimal.exponent >= 0 {
+            result.push_str(&digits);
+            result.extend(std::iter::repeat_n('0', decimal.exponent as usize));
+        } else {
+            let fraction_length = (-decimal.exponent) as usize;
+            if digits.len() > fraction_length {
+                let (integer_part, fraction) = digits.split_at(digits.len() - fraction_length);
+                result.push_str(integer_part);
+                result.push('.');
+                result.push_str(fraction);
+            } else {
+                result.push_str("0.");
+                result.extend(std::iter::repeat_n('0', fraction_length - digits.len()));
+                result.push_str(&digits);
+            }
+        }
+        result
+    }
+
+    /// Strips trailing zeros from the mantissa.
+    fn normalized(mut self) -> Decimal {
+        if self.mantissa == 0 {
+            return Decimal::ZERO;
+        }
+        while self.mantissa % 10 == 0 {
+            self.mantissa /= 10;
+            self.exponent += 1;
+        }
+        self
+    }
+
+    /// Drops the digits below `10^exponent`, rounding half away from zero.
+    fn rounded_to_exponent(self, exponent: i32) -> Decimal {
+        let divisor = match 10i128.checked_pow((exponent - self.exponent) as u32) {
+            Some(divisor) => divisor,
+            None => return Decimal { mantissa: 0, exponent },
+        };
+        let remainder = self.mantissa % divisor;
+        let mut mantissa = self.mantissa / divisor;
+        if remainder.unsigned_abs() * 2 >= divisor.unsigned_abs() {
+            mantissa += self.mantissa.signum();
+        }
+        Decimal { mantissa, exponent }
+    }
+
+    fn checked_magnitude(self) -> Option<Decimal> {
+        let digits = self.mantissa.unsigned_abs().checked_ilog10().unwrap_or(0) as i64 + 1;
+        if i64::from(self.exponent) + digits > MAX_DECIMAL_EXPONENT {
+            None
+        } else {
+            Some(self)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn add(a: &str, b: &str) -> String {
+        let a = Decimal::parse(a.as_bytes()).unwrap();
+        let b = Decimal::parse(b.as_bytes()).unwrap();
+        a.checked_add(b).unwrap().to_human_string()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Decimal::parse(b"5.0e3").unwrap().to_human_string(), "5000");
+        assert_eq!(Decimal::parse(b"-.5").unwrap().to_human_string(), "-0.5");
+        assert_eq!(Decimal::parse(b"+3.").unwrap().to_human_string(), "3");
+        assert_eq!(Decimal::parse(b"1E-3").unwrap().to_human_string(), "0.001");
+        assert_eq!(Decimal::parse(b"000").unwrap(), Decimal::ZERO);
+        for invalid in [&b""[..], b"abc", b"1.2.3", b"1e", b" 1", b"1 ", b".", b"-", b"1e99999", b"inf"] {
+            assert_eq!(Decimal::parse(invalid), None, "{:?}", String::from_utf8_lossy(invalid));
+        }
+    }
+
+    #[test]
+    fn test_add_is_exact() {
+        assert_eq!(add("0.1", "0.2"), "0.3");
+        assert_eq!(add("10.5", "0.1"), "10.6");
+        assert_eq!(add("1", "-1"), "0");
+        assert_eq!(add("-1.25", "0.25"), "-1");
+        assert_eq!(add("9007199254740993", "1"), "9007199254740994");
+        assert_eq!(add("1e20", "0.5"), "100000000000000000000.5");
+    }
+
+    #[test]
+    fn test_add_rounds_to_available_precision() {
+        assert_eq!(add("1e100", "1"), format!("1{}", "0".repeat(100)));
+        assert_eq!(add("0.000000000000000001", "0"), "0");
+        assert_eq!(add("0.000000000000000005", "0"), "0.00000000000000001");
+        assert_eq!(add("0.0000000000000000049", "0"), "0");
+        let half_max = Decimal::parse(b"5e4931").unwrap();
+        assert_eq!(half_max.checked_add(half_max), None);
+    }
+}
@@ -0,0 +1,342 @@
+use crate::random;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::{Duration, Instant};
+
+/// Number of buckets of a table that holds anything.
+const INITIAL_SIZE: usize = 4;
+/// A table is shrunk once it is filled to less than one in this many buckets.
+const MIN_FILL_RATIO: usize = 8;
+/// Empty buckets a rehash step may visit per bucket it is supposed to move.
+const EMPTY_VISITS_PER_STEP: usize = 10;
+/// Buckets moved between checks of the clock in [`Dict::rehash_for`].
+const REHASH_STEPS_PER_CLOCK_CHECK: usize = 100;
+
+type Bucket<V> = Vec<(Vec<u8>, V)>;
+
+/// A chained hash table with binary keys, modeled on Redis' `dict`.
+///
+/// Resizing doesn't move all entries at once: a second table is allocated and every write
+/// moves a bucket over, so that no single request pays for rehashing a large keyspace. Table
+/// sizes are powers of two, which is what lets [`Dict::scan`] iterate with a cursor that
+/// survives resizes.
+#[derive(Debug)]
+pub struct Dict<V> {
+    tables: [Vec<Bucket<V>>; 2],
+    len: usize,
+    /// While rehashing, the buckets of the first table before this index have been moved to
+    /// the second table.
+    rehash_index: Option<usize>,
+    hasher: RandomState,
+}
+
+impl<V> Default for Dict<V> {
+    fn default() -> Dict<V> {
+        Dict {
+            tables: [Vec::new(), Vec::new()],
+            len: 0,
+            rehash_index: None,
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+impl<V> Dict<V> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&V> {
+        let (table, bucket, position) = self.find(key)?;
+        Some(&self.tables[table][bucket][position].1)
+    }
+
+    pub fn get_mut(&mut self, key: &[u8]) -> Option<&mut V> {
+        self.rehash_step(1);
+        let (table, bucket, position) = self.find(key)?;
+        Some(&mut self.tables[table][bucket][position].1)
+    }
+
+    /// Inserts or replaces the value at `key`, returning the replaced value.
+    pub fn insert(&mut self, key: Vec<u8>, value: V) -> Option<V> {
+        self.rehash_step(1);
+        if let Some((table, bucket, position)) = self.find(&key) {
+            return Some(std::mem::replace(&mut self.tables[table][bucket][position].1, value));
+        }
+        self.expand_if_needed();
+        // New entries go to the new table while rehashing, so the old one only ever drains.
+        let table = if self.rehash_index.is_some() { 1 } else { 0 };
+        let bucket = self.bucket_index(&key, table);
+        self.tables[table][bucket].push((key, value));
+        self.len += 1;
+        None
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> Option<V> {
+        self.rehash_step(1);
+        let (table, bucket, position) = self.find(key)?;
+        let (_, value) = self.tables[table][bucket].swap_remove(position);
+        self.len -= 1;
+        self.shrink_if_needed();
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &V)> {
+        self.tables
+            .iter()
+            .flatten()
+            .flatten()
+            .map(|(key, value)| (key, value))
+    }
+
+    /// Rehashes in steps until the rehash is complete or `budget` is used up, so that an idle
+    /// table doesn't keep two tables around forever.
+    pub fn rehash_for(&mut self, budget: Duration) {
+        let start = Instant::now();
+        while self.rehash_index.is_some() && start.elapsed() < budget {
+            self.rehash_step(REHASH_STEPS_PER_CLOCK_CHECK);
+        }
+    }
+
+    /// A random entry. Entries in short chains are slightly more likely to be picked.
+    pub fn random_entry(&self) -> Option<(&Vec<u8>, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        // Buckets of the first table before the rehash index are empty and never picked.
+        let first = self.rehash_index.unwrap_or(0);
+        let candidates = self.tables[0].len() + self.tables[1].len() - first;
+        loop {
+            let index = first + random::random_below(candidates);
+            let bucket = match index.checked_sub(self.tables[0].len()) {
+                Some(index) => &self.tables[1][index],
+                None => &self.tables[0][index],
+            };
+            if !bucket.is_empty() {
+                let (key, value) = &bucket[random::random_below(bucket.len())];
+                return Some((key, value));
+            }
+        }
+    }
+
+    /// Calls `visit` with the entries of one or more buckets and returns the cursor to continue
+    /// with, or 0 once the iteration is complete. An iteration starts with cursor 0.
+    ///
+    /// Like Redis' `dictScan`, the cursor counts up in reverse bit order: incrementing the high
+    /// bits first means that after a resize to another power of two, the buckets the cursor has
+    /// already covered map exactly to buckets that have been covered in the new table. Thus
+    /// every entry present during the whole iteration is visited at least once, though some may
+    /// be visited more than once if the table shrinks.
+    pub fn scan(&self, mut cursor: u64, mut visit: impl FnMut(&Vec<u8>, &V)) -> u64 {
+        if self.is_empty() {
+            return 0;
+        }
+        let mut visit_bucket = |bucket: &Bucket<V>| bucket.iter().for_each(|(key, value)| visit(key, value));
+        if self.rehash_index.is_none() {
+            let mask = self.tables[0].len() as u64 - 1;
+            visit_bucket(&self.tables[0][(cursor & mask) as usize]);
+            return next_cursor(cursor, mask);
+        }
+        let (small, large) = if self.tables[0].len() <= self.tables[1].len() {
+            (&self.tables[0], &self.tables[1])
+        } else {
+            (&self.tables[1], &self.tables[0])
+        };
+        let small_mask = small.len() as u64 - 1;
+        let large_mask = large.len() as u64 - 1;
+        visit_bucket(&small[(cursor & small_mask) as usize]);
+        // Also visit every bucket of the larger table that the small table's bucket expands to.
+        loop {
+            visit_bucket(&large[(cursor & large_mask) as usize]);
+            cursor = next_cursor(cursor, large_mask);
+            if cursor & (small_mask ^ large_mask) == 0 {
+                return cursor;
+            }
+        }
+    }
+
+    fn hash(&self, key: &[u8]) -> u64 {
+        self.hasher.hash_one(key)
+    }
+
+    fn bucket_index(&self, key: &[u8], table: usize) -> usize {
+        (self.hash(key) & (self.tables[table].len() as u64 - 1)) as usize
+    }
+
+    /// The table, bucket and position in the bucket of `key`.
+    fn find(&self, key: &[u8]) -> Option<(usize, usize, usize)> {
+        if self.is_empty() {
+            return None;
+        }
+        let hash = self.hash(key);
+        let tables = if self.rehash_index.is_some() { 2 } else { 1 };
+        (0..tables).find_map(|table| {
+            let bucket = (hash & (self.tables[table].len() as u64 - 1)) as usize;
+            let position = self.tables[table][bucket].iter().position(|(existing, _)| existing == key)?;
+            Some((table, bucket, position))
+        })
+    }
+
+    fn expand_if_needed(&mut self) {
+        if self.rehash_index.is_some() {
+            return;
+        }
+        if self.tables[0].is_empty() {
+            self.tables[0] = empty_table(INITIAL_SIZE);
+        } else if self.len >= self.tables[0].len() {
+            self.start_rehash((self.len + 1).next_power_of_two());
+        }
+    }
+
+    fn shrink_if_needed(&mut self) {
+        let size = self.tables[0].len();
+        if self.rehash_index.is_none() && size > INITIAL_SIZE && self.len * MIN_FILL_RATIO < size {
+            self.start_rehash(self.len.next_power_of_two().max(INITIAL_SIZE));
+        }
+    }
+
+    fn start_rehash(&mut self, size: usize) {
+        self.tables[1] = empty_table(size);
+        self.rehash_index = Some(0);
+    }
+
+    /// Moves `buckets` non-empty buckets to the new table, giving up after visiting a bounded
+    /// number of empty ones.
+    fn rehash_step(&mut self, buckets: usize) {
+        let mut index = match self.rehash_index {
+            Some(index) => index,
+            None => return,
+        };
+        let mut empty_visits = buckets * EMPTY_VISITS_PER_STEP;
+        let mut moved = 0;
+        while moved < buckets && index < self.tables[0].len() {
+            if self.tables[0][index].is_empty() {
+                empty_visits -= 1;
+                index += 1;
+                if empty_visits == 0 {
+                    break;
+                }
+                continue;
+            }
+            for (key, value) in std::mem::take(&mut self.tables[0][index]) {
+                let bucket = self.bucket_index(&key, 1);
+                self.tables[1][bucket].push((key, value));
+            }
+            index += 1;
+            moved += 1;
+        }
+        if index == self.tables[0].len() {
+            self.tables[0] = std::mem::take(&mut self.tables[1]);
+            self.rehash_index = None;
+        } else {
+            self.rehash_index = Some(index);
+        }
+    }
+}
+
+fn empty_table<V>(size: usize) -> Vec<Bucket<V>> {
+    (0..size).map(|_| Vec::new()).collect()
+}
+
+/// Increments the bits of `cursor` covered by `mask` in reverse order, so that the highest
+/// bit is the least significant one.
+fn next_cursor(cursor: u64, mask: u64) -> u64 {
+    let cursor = cursor | !mask;
+    cursor.reverse_bits().wrapping_add(1).reverse_bits()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+
+    fn key(i: usize) -> Vec<u8> {
+        format!("key:{}", i).into_bytes()
+    }
+
+    #[test]
+    fn test_insert_get_remove() {
+        let mut dict = Dict::default();
+        for i in 0..1000 {
+            assert_eq!(dict.insert(key(i), i), None);
+        }
+        assert_eq!(dict.insert(key(7), 70), Some(7));
+        assert_eq!(dict.len(), 1000);
+        assert_eq!(dict.get(&key(7)), Some(&70));
+        *dict.get_mut(&key(8)).unwrap() += 1;
+        assert_eq!(dict.get(&key(8)), Some(&9));
+        for i in 0..990 {
+            assert!(dict.remove(&key(i)).is_some());
+        }
+        assert_eq!(dict.remove(&key(0)), None);
+        assert_eq!(dict.len(), 10);
+        assert_eq!(dict.iter().count(), 10);
+        dict.rehash_for(Duration::from_secs(60));
+        assert_eq!(dict.tables[0].len(), 128);
+        assert!(dict.tables[1].is_empty());
+        assert!(dict.random_entry().is_some());
+    }
+
+    /// Scans `dict` to completion, calling `between_calls` after every call to `scan`.
+    fn scan_with(dict: &mut Dict<usize>, mut between_calls: impl FnMut(&mut Dict<usize>, usize)) -> HashSet<usize> {
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut calls = 0;
+        loop {
+            cursor = dict.scan(cursor, |_, value| {
+                seen.insert(*value);
+            });
+            if cursor == 0 {
+                return seen;
+            }
+            between_calls(dict, calls);
+            calls += 1;
+        }
+    }
+
+    #[test]
+    fn test_scan_visits_everything() {
+        let mut dict = Dict::default();
+        assert_eq!(dict.scan(0, |_, _| panic!("the dict is empty")), 0);
+        for i in 0..500 {
+            dict.insert(key(i), i);
+        }
+        assert_eq!(scan_with(&mut dict, |_, _| {}), (0..500).collect());
+    }
+
+    #[test]
+    fn test_scan_survives_growing() {
+        let mut dict = Dict::default();
+        for i in 0..100 {
+            dict.insert(key(i), i);
+        }
+        // Growing faster than the scan advances would keep it from ever finishing.
+        let seen = scan_with(&mut dict, |dict, call| {
+            if call < 50 {
+                for i in 0..20 {
+                    let i = 100 + call * 20 + i;
+                    dict.insert(key(i), i);
+                }
+            }
+        });
+        assert!((0..100).all(|i| seen.contains(&i)));
+    }
+
+    #[test]
+    fn test_scan_survives_shrinking() {
+        let mut dict = Dict::default();
+        for i in 0..2000 {
+            dict.insert(key(i), i);
+        }
+        let seen = scan_with(&mut dict, |dict, call| {
+            for i in 0..50 {
+                dict.remove(&key(100 + call * 50 + i));
+            }
+        });
+        assert!((0..100).all(|i| seen.contains(&i)));
+    }
+}
@@ -2,16 +2,56 @@ use std::fmt;
 use std::io;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum Error {
     Io(io::Error),
+    /// The input ends before a complete value; more bytes have to be read first.
+    Incomplete,
     ParseError(String),
     ValidationError(String),
+    /// `HELLO` asked for a protocol version other than 2 or 3.
+    UnsupportedProtocol,
     StateError(String),
-    RdbError(String)
+    RdbError(String),
+    /// A command was run against a key holding a value of another type.
+    WrongType,
+    /// A stream command named a consumer group that doesn't exist.
+    NoGroup(String),
+    /// `XGROUP CREATE` named a consumer group that already exists.
+    BusyGroup,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+impl Error {
+    /// The message of the RESP error reply for this error, starting with the error code
+    /// (`ERR`, `NOPROTO`, ...) that clients dispatch on.
+    pub fn reply_message(&self) -> String {
+        match self {
+            Error::Io(io_err) => format!("ERR {}", io_err),
+            Error::Incomplete => "ERR Protocol error: unexpected end of request".to_string(),
+            Error::ParseError(reason) => format!("ERR Protocol error: {}", reason),
+            Error::ValidationError(reason) => format!("ERR {}", reason),
+            Error::UnsupportedProtocol => "NOPROTO unsupported protocol version".to_string(),
+            Error::StateError(reason) => format!("ERR {}", reason),
+            Error::RdbError(reason) => format!("ERR {}", reason),
+            Error::WrongType => {
+                "WRONGTYPE Operation against a key holding the wrong kind of value".to_string()
+            }
+            Error::NoGroup(reason) => format!("NOGROUP {}", reason),
+            Error::BusyGroup => "BUSYGROUP Consumer Group name already exists".to_string(),
+        }
+    }
+
+    /// Whether the connection can't be used any further after this error.
+    ///
+    /// After a protocol error the request boundaries in the input are lost, so like Redis we
+    /// reply and close the connection. All other errors only fail the current command.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, Error::Io(_) | Error::Incomplete | Error::ParseError(_))
+    }
+}
+
 impl From<std::string::FromUtf8Error> for Error {
     fn from(err: std::string::FromUtf8Error) -> Error {
         Error::ValidationError(format!("Invalid UTF-8 sequence: {}", err))
@@ -28,10 +68,15 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::Io(io_err) => write!(f, "IO error: {}", io_err),
+            Error::Incomplete => write!(f, "Incomplete input"),
             Error::ParseError(reason) => write!(f, "Parse error: {}", reason),
             Error::ValidationError(reason) => write!(f, "Validation error: {}", reason),
+            Error::UnsupportedProtocol => write!(f, "Unsupported protocol version"),
             Error::StateError(reason) => write!(f, "State error: {}", reason),
             Error::RdbError(reason) => write!(f, "Rdb error: {}", reason),
+            Error::WrongType => write!(f, "Wrong type"),
+            Error::NoGroup(reason) => write!(f, "No group: {}", reason),
+            Error::BusyGroup => write!(f, "Busy group"),
         }
     }
 }
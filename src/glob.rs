@@ -0,0 +1,130 @@
+/// Matches `string` against a glob-style `pattern` the way Redis' `stringmatchlen` does.
+///
+/// Supports `*` (any sequence), `?` (any single byte), bracket classes like `[abc]`, `[^a]`
+/// and `[a-z]` (reversed ranges like `[z-a]` work too), and backslash escapes, both inside and
+/// outside of classes. An unterminated class extends to the end of the pattern. Patterns and
+/// strings are arbitrary bytes.
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    let mut pattern_position = 0;
+    let mut string_position = 0;
+    // Where to resume after the last `*` if the rest fails to match: the pattern position
+    // after the star and the string position the star's match currently ends at. Only the last
+    // star ever needs to be backtracked, so this runs in O(pattern * string) at worst.
+    let mut backtrack: Option<(usize, usize)> = None;
+    while string_position < string.len() {
+        if pattern.get(pattern_position) == Some(&b'*') {
+            while pattern.get(pattern_position) == Some(&b'*') {
+                pattern_position += 1;
+            }
+            if pattern_position == pattern.len() {
+                return true;
+            }
+            backtrack = Some((pattern_position, string_position));
+            continue;
+        }
+        if let Some(next) = match_single(pattern, pattern_position, string[string_position]) {
+            pattern_position = next;
+            string_position += 1;
+            continue;
+        }
+        match backtrack {
+            Some((star_end, star_match_end)) => {
+                pattern_position = star_end;
+                string_position = star_match_end + 1;
+                backtrack = Some((star_end, star_match_end + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[pattern_position..].iter().all(|&byte| byte == b'*')
+}
+
+/// Matches `byte` against the single byte token at `position` of `pattern`, returning the
+/// position of the next token if it matches.
+fn match_single(pattern: &[u8], position: usize, byte: u8) -> Option<usize> {
+    match *pattern.get(position)? {
+        b'?' => Some(position + 1),
+        b'[' => match_class(pattern, position + 1, byte),
+        // A trailing backslash matches itself.
+        b'\\' if position + 1 < pattern.len() => (pattern[position + 1] == byte).then_some(position + 2),
+        literal => (literal == byte).then_some(position + 1),
+    }
+}
+
+/// Matches `byte` against the class starting after the `[` at `position - 1`.
+fn match_class(pattern: &[u8], mut position: usize, byte: u8) -> Option<usize> {
+    let negated = pattern.get(position) == Some(&b'^');
+    if negated {
+        position += 1;
+    }
+    let mut matched = false;
+    loop {
+        match pattern.get(position..).unwrap_or_default() {
+            [] => break,
+            [b']', ..] => {
+                position += 1;
+                break;
+            }
+            [b'\\', escaped, ..] => {
+                matched |= *escaped == byte;
+                position += 2;
+            }
+            [start, b'-', end, ..] => {
+                let (low, high) = if start <= end { (start, end) } else { (end, start) };
+                matched |= (*low..=*high).contains(&byte);
+                position += 3;
+            }
+            [literal, ..] => {
+                matched |= *literal == byte;
+                position += 1;
+            }
+        }
+    }
+    (matched != negated).then_some(position)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wildcards() {
+        assert!(matches(b"*", b""));
+        assert!(matches(b"*", b"anything"));
+        assert!(matches(b"user:*", b"user:42"));
+        assert!(!matches(b"user:*", b"users:42"));
+        assert!(matches(b"h?llo", b"hello"));
+        assert!(!matches(b"h?llo", b"hllo"));
+        assert!(matches(b"*a*b*c", b"xxaxxbxxbc"));
+        assert!(!matches(b"*a*b*c", b"xxaxxbxxbcd"));
+        assert!(matches(b"a**", b"a"));
+        assert!(!matches(b"", b"a"));
+        assert!(matches(b"*\xff?", b"\x00\xff\x01"));
+        let long_string = vec![b'a'; 1000];
+        assert!(!matches(b"*a*a*a*a*a*a*a*a*a*b", &long_string));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(matches(b"h[ae]llo", b"hallo"));
+        assert!(!matches(b"h[ae]llo", b"hillo"));
+        assert!(matches(b"h[^e]llo", b"hallo"));
+        assert!(!matches(b"h[^e]llo", b"hello"));
+        assert!(matches(b"h[a-b]llo", b"hbllo"));
+        assert!(matches(b"h[b-a]llo", b"hallo"));
+        assert!(!matches(b"h[a-b]llo", b"hcllo"));
+        assert!(matches(b"[\\]]", b"]"));
+        assert!(!matches(b"[]a", b"a"));
+        // An unterminated class takes up the rest of the pattern.
+        assert!(matches(b"x[ab", b"xb"));
+        assert!(!matches(b"x[ab", b"xc"));
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert!(matches(b"\\*", b"*"));
+        assert!(!matches(b"\\*", b"a"));
+        assert!(matches(b"a\\?", b"a?"));
+        assert!(matches(b"a\\", b"a\\"));
+    }
+}
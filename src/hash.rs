@@ -0,0 +1,119 @@
+use std::collections::{BTreeSet, HashMap};
+use std::time::SystemTime;
+
+/// The fields of a hash value.
+///
+/// Fields are kept in a vector, in insertion order until fields are deleted, with an index from
+/// field name to position; deleting swaps the last field into the gap. Positions make picking
+/// random fields for `HRANDFIELD` O(1).
+///
+/// Fields may have their own expiration time. Besides the time per field, the expiring fields
+/// are ordered by time, so that expired fields are found without looking at the others.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Hash {
+    entries: Vec<(Vec<u8>, Vec<u8>)>,
+    positions: HashMap<Vec<u8>, usize>,
+    expiration_times: HashMap<Vec<u8>, SystemTime>,
+    expiration_order: BTreeSet<(SystemTime, Vec<u8>)>,
+}
+
+impl Hash {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, field: &[u8]) -> Option<&Vec<u8>> {
+        let position = *self.positions.get(field)?;
+        Some(&self.entries[position].1)
+    }
+
+    pub fn contains(&self, field: &[u8]) -> bool {
+        self.positions.contains_key(field)
+    }
+
+    /// Sets `field` to `value`, returning true if the field is new. Like `HSET`, this removes
+    /// the expiration time of an existing field.
+    pub fn insert(&mut self, field: Vec<u8>, value: Vec<u8>) -> bool {
+        self.set_expiration_time(&field, None);
+        self.insert_keeping_ttl(field, value)
+    }
+
+    /// Sets `field` to `value` like [`Hash::insert`], but an existing field keeps its expiration
+    /// time, as when `HINCRBY` changes it.
+    pub fn insert_keeping_ttl(&mut self, field: Vec<u8>, value: Vec<u8>) -> bool {
+        if let Some(&position) = self.positions.get(&field) {
+            self.entries[position].1 = value;
+            return false;
+        }
+        self.positions.insert(field.clone(), self.entries.len());
+        self.entries.push((field, value));
+        true
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<Vec<u8>> {
+        self.set_expiration_time(field, None);
+        let position = self.positions.remove(field)?;
+        let (_, value) = self.entries.swap_remove(position);
+        if let Some((moved_field, _)) = self.entries.get(position) {
+            *self.positions.get_mut(moved_field).expect("every field has a position") = position;
+        }
+        Some(value)
+    }
+
+    /// The field and value at `index`, which must be less than the length.
+    pub fn entry_at(&self, index: usize) -> (&Vec<u8>, &Vec<u8>) {
+        let (field, value) = &self.entries[index];
+        (field, value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, &Vec<u8>)> {
+        self.entries.iter().map(|(field, value)| (field, value))
+    }
+
+    pub fn expiration_time(&self, field: &[u8]) -> Option<SystemTime> {
+        self.expiration_times.get(field).copied()
+    }
+
+    /// Changes the expiration time of `field`; returns false if there is no such field.
+    pub fn set_expiration_time(&mut self, field: &[u8], expiration_time: Option<SystemTime>) -> bool {
+        if !self.contains(field) {
+            return false;
+        }
+        if let Some(previous) = self.expiration_times.remove(field) {
+            self.expiration_order.remove(&(previous, field.to_vec()));
+        }
+        if let Some(expiration_time) = expiration_time {
+            self.expiration_times.insert(field.to_vec(), expiration_time);
+            self.expiration_order.insert((expiration_time, field.to_vec()));
+        }
+        true
+    }
+
+    /// Whether any field has an expiration time.
+    pub fn has_volatile_fields(&self) -> bool {
+        !self.expiration_times.is_empty()
+    }
+
+    /// The earliest expiration time of any field.
+    pub fn next_expiration_time(&self) -> Option<SystemTime> {
+        self.expiration_order.first().map(|(time, _)| *time)
+    }
+
+    /// Deletes the fields whose expiration time is at or before `now` and returns how many.
+    pub fn remove_expired(&mut self, now: SystemTime) -> usize {
+        let mut removed = 0;
+        while let Some((time, field)) = self.expiration_order.first() {
+            if *time > now {
+                break;
+            }
+            let field = field.clone();
+            self.remove(&field);
+            removed += 1;
+        }
+        removed
+    }
+}
@@ -0,0 +1,33 @@
+use crate::Value;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+
+/// Values that take at least this much effort to free are dropped on a background thread.
+const LAZYFREE_THRESHOLD: usize = 64;
+
+/// Frees `values`, on a background thread if that takes long enough to stall the
+/// request path, like Redis' lazyfree for UNLINK.
+pub fn free_values(values: Vec<Value>) {
+    let effort: usize = values.iter().map(Value::free_effort).sum();
+    if effort < LAZYFREE_THRESHOLD {
+        return;
+    }
+    let sender = lazyfree_sender();
+    let sender = sender.lock().expect("the lazyfree sender is never poisoned");
+    if let Err(mpsc::SendError(values)) = sender.send(values) {
+        drop(values);
+    }
+}
+
+fn lazyfree_sender() -> &'static Mutex<Sender<Vec<Value>>> {
+    static SENDER: OnceLock<Mutex<Sender<Vec<Value>>>> = OnceLock::new();
+    SENDER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<Vec<Value>>();
+        thread::Builder::new()
+            .name("lazyfree".to_string())
+            .spawn(move || receiver.into_iter().for_each(drop))
+            .expect("Failed to spawn the lazyfree thread");
+        Mutex::new(sender)
+    })
+}
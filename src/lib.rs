@@ -1,21 +1,39 @@
-use crate::command::{RedisCommand, SetData, SetOption};
+use crate::client::Client;
+use crate::db::Db;
+use crate::hash::Hash;
+use crate::set::Set;
+use crate::stream::Stream;
+use crate::zset::SortedSet;
 use crate::error::{Error, Result};
-use std::collections::HashMap;
-use std::io::{Read, Write};
-use std::net::{SocketAddr, TcpListener, TcpStream};
-use std::ops::Add;
+use std::collections::VecDeque;
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
-use std::thread;
 use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
 // use clap::Parser;
 use std::path::PathBuf;
 use std::time::UNIX_EPOCH;
+use connection::Connection;
 use resp::RespVal;
+use command::Response;
 
+mod blocking;
+mod client;
 mod command;
+mod connection;
+mod db;
+mod decimal;
+mod dict;
+mod lazyfree;
 mod error;
-mod resp;
+mod glob;
+mod hash;
+pub mod resp;
 mod persistence;
+mod random;
+mod set;
+mod stream;
+mod zset;
 
 // #[derive(Parser, Debug, Clone)]
 // #[command(version, about, long_about = None)]
@@ -27,30 +45,184 @@ pub struct Config {
     pub dbfilename: PathBuf,
 }
 
+/// The data stored at a key, one variant per Redis type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Data {
+    String(Vec<u8>),
+    List(VecDeque<Vec<u8>>),
+    Hash(Hash),
+    Set(Set),
+    SortedSet(SortedSet),
+    Stream(Stream),
+}
+
 #[derive(Clone, Debug)]
 #[derive(PartialEq, Eq)]
 pub struct Value {
-    data: Vec<u8>,
+    data: Data,
     expiration_time: Option<SystemTime>,
 }
 
 impl Value {
-    fn move_out_data_if_valid(self) -> Option<Vec<u8>> {
-        // TODO: use Option::take_if once it is in stable Rust
-        dbg!(self.expiration_time);
-        dbg!(SystemTime::now());
-        match self.expiration_time {
-            None => Some(self.data),
-            Some(expiration_time) if expiration_time > SystemTime::now() => Some(self.data),
-            _ => None,
+    fn new(data: Data) -> Value {
+        Value {
+            data,
+            expiration_time: None,
+        }
+    }
+
+    fn string(data: Vec<u8>) -> Value {
+        Value::new(Data::String(data))
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self.data {
+            Data::String(_) => "string",
+            Data::List(_) => "list",
+            Data::Hash(_) => "hash",
+            Data::Set(_) => "set",
+            Data::SortedSet(_) => "zset",
+            Data::Stream(_) => "stream",
+        }
+    }
+
+    /// Roughly how many allocations have to be freed to drop this value; strings count one
+    /// unit per 16KiB, as freeing large buffers returns memory to the OS page by page, and
+    /// collections one unit per element.
+    fn free_effort(&self) -> usize {
+        match &self.data {
+            Data::String(data) => 1 + data.len() / (16 * 1024),
+            Data::List(list) => 1 + list.len(),
+            Data::Hash(hash) => 1 + hash.len(),
+            Data::Set(set) => 1 + set.len(),
+            Data::SortedSet(sorted_set) => 1 + sorted_set.len(),
+            Data::Stream(stream) => 1 + stream.len() / crate::stream::NODE_MAX_ENTRIES,
+        }
+    }
+
+    /// The name `OBJECT ENCODING` reports. Only sets switch representations here; for the other
+    /// types the name Redis would use is inferred from the size with Redis' default limits.
+    fn encoding_name(&self) -> &'static str {
+        match &self.data {
+            Data::String(data) if command::parse_integer(data).is_ok() => "int",
+            Data::String(data) if data.len() <= 44 => "embstr",
+            Data::String(_) => "raw",
+            Data::List(list) if list.iter().map(Vec::len).sum::<usize>() <= 8 * 1024 => "listpack",
+            Data::List(_) => "quicklist",
+            Data::Hash(hash) if hash.len() > 128 || hash.iter().any(|(field, value)| field.len() > 64 || value.len() > 64) => {
+                "hashtable"
+            }
+            Data::Hash(hash) if hash.has_volatile_fields() => "listpackex",
+            Data::Hash(_) => "listpack",
+            Data::Set(set) => set.encoding_name(),
+            Data::SortedSet(sorted_set) if sorted_set.len() > 128 || sorted_set.iter().any(|(member, _)| member.len() > 64) => {
+                "skiplist"
+            }
+            Data::SortedSet(_) => "listpack",
+            Data::Stream(_) => "stream",
+        }
+    }
+
+    fn as_string(&self) -> Result<&Vec<u8>> {
+        match &self.data {
+            Data::String(data) => Ok(data),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    fn as_string_mut(&mut self) -> Result<&mut Vec<u8>> {
+        match &mut self.data {
+            Data::String(data) => Ok(data),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    fn into_string(self) -> Result<Vec<u8>> {
+        match self.data {
+            Data::String(data) => Ok(data),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    fn as_list(&self) -> Result<&VecDeque<Vec<u8>>> {
+        match &self.data {
+            Data::List(list) => Ok(list),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    fn as_list_mut(&mut self) -> Result<&mut VecDeque<Vec<u8>>> {
+        match &mut self.data {
+            Data::List(list) => Ok(list),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    fn as_hash(&self) -> Result<&Hash> {
+        match &self.data {
+            Data::Hash(hash) => Ok(hash),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    fn as_hash_mut(&mut self) -> Result<&mut Hash> {
+        match &mut self.data {
+            Data::Hash(hash) => Ok(hash),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    fn as_set(&self) -> Result<&Set> {
+        match &self.data {
+            Data::Set(set) => Ok(set),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    fn as_set_mut(&mut self) -> Result<&mut Set> {
+        match &mut self.data {
+            Data::Set(set) => Ok(set),
+            _ => Err(Error::WrongType),
         }
     }
 
+    fn as_sorted_set(&self) -> Result<&SortedSet> {
+        match &self.data {
+            Data::SortedSet(sorted_set) => Ok(sorted_set),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    fn as_sorted_set_mut(&mut self) -> Result<&mut SortedSet> {
+        match &mut self.data {
+            Data::SortedSet(sorted_set) => Ok(sorted_set),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    fn as_stream(&self) -> Result<&Stream> {
+        match &self.data {
+            Data::Stream(stream) => Ok(stream),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    fn as_stream_mut(&mut self) -> Result<&mut Stream> {
+        match &mut self.data {
+            Data::Stream(stream) => Ok(stream),
+            _ => Err(Error::WrongType),
+        }
+    }
+
+    fn is_expired_at(&self, now: SystemTime) -> bool {
+        matches!(self.expiration_time, Some(expiration_time) if expiration_time <= now)
+    }
+
     fn expiring_from_millis(data: Vec<u8>, millis: u64) -> Value {
-        let expiration_time = UNIX_EPOCH + Duration::from_millis(millis.into());
+        let expiration_time = UNIX_EPOCH + Duration::from_millis(millis);
         let expiration_time = Some(expiration_time);
         Value {
-            data,
+            data: Data::String(data),
             expiration_time,
         }
     }
@@ -58,106 +230,102 @@ impl Value {
 
 const CRLF: [u8; 2] = [b'\r', b'\n'];
 
-fn handle_client_connection(
-    stream: &mut TcpStream,
-    map: Arc<Mutex<HashMap<Vec<u8>, Value>>>,
+/// The Redis version this server reports to clients, e.g. in the `HELLO` reply.
+const REDIS_VERSION: &str = "7.4.0";
+
+type Database = Arc<Mutex<Db>>;
+
+async fn handle_client_connection(
+    stream: TcpStream,
+    map: Database,
     config: Config,
 ) -> Result<()> {
+    let mut connection = Connection::new(stream);
+    let mut client = Client::new();
+    let mut responses: Vec<u8> = Vec::new();
     loop {
-        let mut buffer: Vec<u8> = vec![0; 1024];
-        let bytes_read = stream.read(&mut buffer)?;
-        buffer.truncate(bytes_read);
-        dbg!(String::from_utf8_lossy(&buffer));
-        let command: RedisCommand = RedisCommand::parse_command(&buffer)?;
-        let mut map = map
-            .lock()
-            .map_err(|_| Error::StateError("Mutex lock failed".to_string()))?;
-        dbg!(&command);
-        match command {
-            RedisCommand::Ping => {
-                let response = b"+PONG\r\n";
-                stream.write_all(response)?;
-            }
-            RedisCommand::Echo(bytes) => {
-                let response = resp::encode_as_bulk_string(&bytes);
-                dbg!(String::from_utf8_lossy(&response));
-                stream.write_all(&response)?;
-            }
-            RedisCommand::Get(key_bytes) => {
-                let null = b"$-1\r\n";
-                let value: Option<Vec<u8>> = map.get(&key_bytes).and_then(|value| {
-                    let my_value = value.clone();
-                    my_value.move_out_data_if_valid()
-                });
-                let value_bulk_string = value.map(|data| resp::encode_as_bulk_string(&data));
-                match value_bulk_string {
-                    Some(val) => stream.write_all(&val)?,
-                    None => stream.write_all(null)?,
-                };
-            }
-            RedisCommand::Set(SetData {
-                key,
-                value,
-                options,
-            }) => {
-                let expiration_time = match options.get(0) {
-                    Some(SetOption::Px(period_of_validity)) => {
-                        let period_of_validity = Duration::from_millis(*period_of_validity);
-                        let expiration_time = SystemTime::now().add(period_of_validity);
-                        Some(expiration_time)
+        // Run every complete request that is already buffered before replying, so that the
+        // replies to a pipeline leave in a single write.
+        loop {
+            let frame = match connection.next_frame() {
+                Ok(Some(frame)) => frame,
+                Ok(None) => break,
+                Err(err) => {
+                    resp::encode_into(&mut responses, &error_reply(&err), client.protocol);
+                    connection.write_all(&responses).await?;
+                    return Err(err);
+                }
+            };
+            // The response is built while holding the lock and written once it is released,
+            // so that a slow client never blocks the keyspace across an await point.
+            let response = match command::execute(frame, &map, &config, &mut client) {
+                Ok(Response::Reply(reply)) => Ok(reply),
+                Ok(Response::Blocked(blocked)) => {
+                    // Replies to earlier requests must not wait for the blocked one.
+                    if !responses.is_empty() {
+                        connection.write_all(&responses).await?;
+                        responses.clear();
                     }
-                    _ => None,
-                };
-                let value = Value {
-                    data: value,
-                    expiration_time,
-                };
-                map.insert(key, value);
-                let response = b"+OK\r\n";
-                stream.write_all(response)?;
-            }
-            RedisCommand::ConfigGet(key) => {
-                let val = match key.as_slice() {
-                    b"dir" => &config.dir,
-                    b"dbfilename" => &config.dbfilename,
-                    _ => return Err(Error::ValidationError("Wrong argument to CONFIG GET command".to_string())),
-                };
-                let key = resp::encode_as_bulk_string(&key);
-                let key = RespVal::parse_resp_value(&key)?.0;
-                let val = resp::encode_as_bulk_string(val.to_str().unwrap().as_bytes());
-                let val = RespVal::parse_resp_value(&val)?.0;
-                let response = resp::encode(&RespVal::Array(vec![key, val]));
-                stream.write_all(&response)?;
-            }
-            RedisCommand::Keys(keys_pattern) => {
-                assert!(keys_pattern.as_slice() == b"*");
-                // TODO: don't return keys with expired values here
-                let keys: Vec<RespVal> = map.keys().map(|key| {
-                    let key = resp::encode_as_bulk_string(key);
-                    RespVal::parse_resp_value(&key).expect("We encode the values ourselves").0
-                }).collect();
-                let response = resp::encode_as_array(&keys);
-                stream.write_all(&response)?;
-            }
+                    match blocked.wait(&map, &mut connection).await? {
+                        Some(reply) => reply,
+                        None => return Ok(()),
+                    }
+                }
+                Err(err) => Err(err),
+            };
+            let response = match response {
+                Ok(response) => response,
+                Err(err) if err.is_fatal() => return Err(err),
+                Err(err) => error_reply(&err),
+            };
+            resp::encode_into(&mut responses, &response, client.protocol);
+        }
+        if !responses.is_empty() {
+            connection.write_all(&responses).await?;
+            responses.clear();
+        }
+        if !connection.fill_buffer().await? {
+            return Ok(());
         }
     }
 }
 
-pub fn start_redis_server(socket_addr: SocketAddr, config: Config) {
-    let listener = TcpListener::bind(socket_addr).expect("Failed to bind socket address");
+/// Periodic housekeeping of the keyspace: reclaims expired keys that are never accessed again
+/// and moves a resize along when there are no writes to do so.
+async fn server_cron(map: Database) {
+    let mut interval = tokio::time::interval(db::ACTIVE_EXPIRE_CYCLE_PERIOD);
+    loop {
+        interval.tick().await;
+        // A command that panicked while holding the lock poisons it. Commands then fail with a
+        // state error, so there is no keyspace left to maintain.
+        let Ok(mut db) = map.lock() else {
+            continue;
+        };
+        db.active_expire_cycle(db::ACTIVE_EXPIRE_CYCLE_BUDGET);
+        db.rehash_for(db::REHASH_BUDGET);
+    }
+}
+
+fn error_reply(err: &Error) -> RespVal {
+    RespVal::Error(err.reply_message().into_bytes())
+}
+
+pub async fn start_redis_server(socket_addr: SocketAddr, config: Config) {
+    let listener = TcpListener::bind(socket_addr).await.expect("Failed to bind socket address");
     let mut full_path = config.dir.clone();
     full_path.push(&config.dbfilename);
-    let map = persistence::load_rdb_file(&full_path)
-        .unwrap_or_else(|_| HashMap::new());
+    let map: Db = persistence::load_rdb_file(&full_path)
+        .map(|map| map.into_iter().collect())
+        .unwrap_or_default();
     let map = Arc::new(Mutex::new(map));
-    for stream in listener.incoming() {
-        match stream {
-            Ok(mut stream) => {
-                println!("accepted new connection");
+    tokio::spawn(server_cron(Arc::clone(&map)));
+    loop {
+        match listener.accept().await {
+            Ok((stream, _peer_addr)) => {
                 let map_arc = Arc::clone(&map);
                 let my_config = config.clone();
-                thread::spawn(move || {
-                    if let Err(e) = handle_client_connection(&mut stream, map_arc, my_config) {
+                tokio::spawn(async move {
+                    if let Err(e) = handle_client_connection(stream, map_arc, my_config).await {
                         eprintln!("Failed to handle client connection: {}", e);
                     }
                 });
@@ -168,3 +336,64 @@ pub fn start_redis_server(socket_addr: SocketAddr, config: Config) {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::task::JoinHandle;
+
+    /// Connects to a new connection handler, which returns once the connection is closed.
+    async fn connect() -> (TcpStream, JoinHandle<Result<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let handler = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let config = Config {
+                dir: ".".into(),
+                dbfilename: "dump.rdb".into(),
+            };
+            handle_client_connection(stream, Arc::new(Mutex::new(Db::new())), config).await
+        });
+        (TcpStream::connect(address).await.unwrap(), handler)
+    }
+
+    async fn read_reply(stream: &mut TcpStream, expected: &[u8]) {
+        let mut reply = vec![0; expected.len()];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&reply), String::from_utf8_lossy(expected));
+    }
+
+    #[tokio::test]
+    async fn test_command_error_keeps_connection_open() {
+        let (mut stream, handler) = connect().await;
+        stream
+            .write_all(b"*1\r\n$5\r\nbogus\r\n*2\r\n$4\r\nincr\r\n$1\r\nk\r\n*1\r\n$4\r\nping\r\n")
+            .await
+            .unwrap();
+        read_reply(&mut stream, b"-ERR unknown command 'bogus', with args beginning with: \r\n:1\r\n+PONG\r\n").await;
+        stream.write_all(b"*2\r\n$4\r\nlpop\r\n$1\r\nk\r\n").await.unwrap();
+        read_reply(&mut stream, b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n").await;
+        stream.write_all(b"*1\r\n$4\r\nping\r\n").await.unwrap();
+        read_reply(&mut stream, b"+PONG\r\n").await;
+        drop(stream);
+        assert!(handler.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_protocol_error_replies_and_closes() {
+        let (mut stream, handler) = connect().await;
+        // The request before the malformed one is still answered, the one after it isn't.
+        stream
+            .write_all(b"*1\r\n$4\r\nping\r\n*1\r\n:1\r\n*1\r\n$4\r\nping\r\n")
+            .await
+            .unwrap();
+        let mut replies = Vec::new();
+        stream.read_to_end(&mut replies).await.unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&replies),
+            "+PONG\r\n-ERR Protocol error: expected '$', got ':'\r\n"
+        );
+        assert!(matches!(handler.await.unwrap(), Err(Error::ParseError(_))));
+    }
+}
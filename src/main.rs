@@ -6,7 +6,8 @@ use std::env;
 // use clap::Parser;
 
 
-fn main() {
+#[tokio::main]
+async fn main() {
     let args: Vec<_> = env::args().collect();
     let config = if args.len() > 4 {
         match (args[1].as_str(), args[3].as_str()) {
@@ -27,5 +28,5 @@ fn main() {
         }
     };
     dbg!(&config);
-    start_redis_server(SocketAddr::from_str("127.0.0.1:6379").expect("hard coded SocketAddr"), config);
+    start_redis_server(SocketAddr::from_str("127.0.0.1:6379").expect("hard coded SocketAddr"), config).await;
 }
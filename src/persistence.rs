@@ -35,11 +35,8 @@ fn parse_rdb(input: &[u8]) -> IResult<&[u8], Database> {
         many_till(Operation::parse_part, bytes::tag(&[0xFF]))(input)?;
     let mut database = Database::new();
     for operation in operations {
-        match operation {
-            Operation::Entry(key, val) => {
-                database.insert(key, val);
-            }
-            _ => (),
+        if let Operation::Entry(key, val) = operation {
+            database.insert(key, val);
         }
     }
     Ok((input, database))
@@ -106,6 +103,7 @@ enum Operation {
 }
 
 use std::fmt;
+#[allow(dead_code)]
 struct HexSlice<'a>(&'a [u8]);
 impl<'a> fmt::Debug for HexSlice<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -137,10 +135,7 @@ fn entry_from_key_val(key: StringEncoding, val: RdbValue, expires_in: Option<u64
         RdbValue::StringEncoding(StringEncoding::String(val_raw)) => {
             let redis_val = match expires_in {
                 Some(expires_in) => Value::expiring_from_millis(val_raw, expires_in),
-                None => Value {
-                    data: val_raw,
-                    expiration_time: None,
-                },
+                None => Value::string(val_raw),
             };
             Operation::Entry(key_raw, redis_val)
         }
@@ -303,9 +298,9 @@ enum Length {
     StringEncoding(u32),
 }
 
-impl Into<u32> for Length {
-    fn into(self) -> u32 {
-        match self {
+impl From<Length> for u32 {
+    fn from(length: Length) -> u32 {
+        match length {
             Length::Simple(num) => num,
             Length::StringEncoding(num) => num,
         }
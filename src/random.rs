@@ -0,0 +1,56 @@
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::collections::HashSet;
+use std::hash::{BuildHasher, Hasher};
+
+thread_local! {
+    static STATE: Cell<u64> = Cell::new(seed());
+}
+
+/// Seeds from the randomly keyed std hasher, which is good enough for sampling keys.
+fn seed() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0x9e37_79b9_7f4a_7c15);
+    hasher.finish() | 1
+}
+
+/// A fast, non-cryptographic pseudo random number (xorshift64*).
+pub fn random_u64() -> u64 {
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    })
+}
+
+/// A pseudo random number in `0..bound`. `bound` must not be zero.
+pub fn random_below(bound: usize) -> usize {
+    (random_u64() % bound as u64) as usize
+}
+
+/// `count` distinct pseudo random numbers in `0..bound`, in random order. `count` must not
+/// exceed `bound`.
+pub fn distinct_below(bound: usize, count: usize) -> Vec<usize> {
+    // Rejection sampling is cheap while few numbers are taken; otherwise shuffle the front of
+    // all candidates, like Redis switches strategies for `SRANDMEMBER` with a large count.
+    if count * 3 <= bound {
+        let mut picked = HashSet::with_capacity(count);
+        let mut numbers = Vec::with_capacity(count);
+        while numbers.len() < count {
+            let number = random_below(bound);
+            if picked.insert(number) {
+                numbers.push(number);
+            }
+        }
+        return numbers;
+    }
+    let mut numbers: Vec<usize> = (0..bound).collect();
+    for i in 0..count {
+        numbers.swap(i, i + random_below(bound - i));
+    }
+    numbers.truncate(count);
+    numbers
+}
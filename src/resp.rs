@@ -3,178 +3,725 @@ use crate::CRLF;
 use std::io::Write;
 use std::str::FromStr;
 
-#[derive(Debug)]
+/// Largest bulk string a client may send, matching Redis' default `proto-max-bulk-len`.
+const MAX_BULK_LENGTH: usize = 512 * 1024 * 1024;
+/// Largest number of elements in a request array, matching Redis' multibulk limit.
+const MAX_ARRAY_LENGTH: usize = 1024 * 1024 * 1024;
+/// Longest line, such as the length line of an array or bulk string, matching Redis'
+/// `PROTO_INLINE_MAX_SIZE`. Without a limit, a line that never ends would be buffered and
+/// scanned again on every read.
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// The protocol a connection speaks, negotiated with `HELLO`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProtocolVersion {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+type Pairs = Vec<(RespVal, RespVal)>;
+
+/// A value of the RESP3 type set.
+///
+/// Handlers reply with the most specific type, e.g. a `Map` or a `Double`. The encoder
+/// downgrades types that don't exist in RESP2 to their RESP2 counterparts.
+#[derive(Debug, Clone, PartialEq)]
 pub enum RespVal {
     BulkString(Vec<u8>),
     Array(Vec<RespVal>),
     SimpleString(Vec<u8>),
-    UnsignedInteger(usize),
-    SignedInteger(isize),
+    /// A simple error, whose content starts with an error code such as `ERR` or `WRONGTYPE`.
+    Error(Vec<u8>),
+    Integer(i64),
+    /// A missing value, sent as null bulk string in RESP2.
+    Null,
+    /// A missing aggregate, sent as null array in RESP2.
+    NullArray,
+    Boolean(bool),
+    Double(f64),
+    /// An integer of arbitrary size, stored as its decimal digits with an optional sign.
+    BigNumber(Vec<u8>),
+    BulkError(Vec<u8>),
+    /// A string together with a three byte format hint such as `txt` or `mkd`.
+    VerbatimString([u8; 3], Vec<u8>),
+    Map(Vec<(RespVal, RespVal)>),
+    Set(Vec<RespVal>),
+    /// Out of band data, such as Pub/Sub messages.
+    Push(Vec<RespVal>),
+    /// Auxiliary data that a client may ignore; it is dropped for RESP2 clients.
+    Attribute(Vec<(RespVal, RespVal)>),
 }
 
+/// Decoding of arbitrary values, e.g. the replies of a server. Requests are flat arrays of bulk
+/// strings and are decoded by the `RequestDecoder` instead.
 impl RespVal {
-    fn parse_integer(raw: &[u8]) -> Result<(RespVal, &[u8])> {
-        if raw[0] != b':' {
-            return Err(Error::ParseError(
-                "Integer does not start with colon sign ':'".to_string(),
-            ));
+    /// Parses one value from the front of `raw`, returning `Error::Incomplete` if `raw` holds
+    /// only a prefix of it.
+    pub fn parse_resp_value(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        let first_byte = *raw.first().ok_or(Error::Incomplete)?;
+        match first_byte {
+            b'*' => RespVal::parse_array(raw),
+            b'$' => RespVal::parse_bulk_string(raw),
+            b':' => RespVal::parse_integer(raw),
+            b'+' => RespVal::parse_simple_string(raw),
+            b'-' => RespVal::parse_simple_error(raw),
+            b'_' => RespVal::parse_null(raw),
+            b'#' => RespVal::parse_boolean(raw),
+            b',' => RespVal::parse_double(raw),
+            b'(' => RespVal::parse_big_number(raw),
+            b'!' => RespVal::parse_bulk_error(raw),
+            b'=' => RespVal::parse_verbatim_string(raw),
+            b'%' => RespVal::parse_map(raw),
+            b'~' => RespVal::parse_set(raw),
+            b'>' => RespVal::parse_push(raw),
+            b'|' => RespVal::parse_attribute(raw),
+            _ => Err(Error::ParseError(format!(
+                "Leading byte {} does not correspond to a RESP type",
+                first_byte
+            ))),
         }
-        match raw[1] {
-            b'+' => {
-                let (num, raw_tail) = RespVal::parse_number(&raw[2..])?;
-                Ok((RespVal::UnsignedInteger(num), raw_tail))
-            }
-            b'-' => {
-                let (num, raw_tail) = RespVal::parse_number(&raw[2..])?;
-                let negative_num: isize = -isize::try_from(num).map_err(|_| {
-                    Error::ParseError(format!("Integer value is too large, max is {}", isize::MAX))
-                })?;
-                Ok((RespVal::SignedInteger(negative_num), raw_tail))
+    }
+
+    fn parse_array(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        let (length, raw_tail) = RespVal::parse_length_line(raw, b'*', "Array")?;
+        match length {
+            None => Ok((RespVal::NullArray, raw_tail)),
+            Some(length) => {
+                let (vals, raw_tail) = RespVal::parse_elements(raw_tail, length)?;
+                Ok((RespVal::Array(vals), raw_tail))
             }
-            _ => Err(Error::ParseError(
-                "Integer does not have sign prefix '+' or '-'.".to_string(),
-            )),
         }
     }
 
+    fn parse_set(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        let (length, raw_tail) = RespVal::parse_length_line(raw, b'~', "Set")?;
+        let (vals, raw_tail) = RespVal::parse_elements(raw_tail, length.unwrap_or(0))?;
+        Ok((RespVal::Set(vals), raw_tail))
+    }
+
+    fn parse_push(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        let (length, raw_tail) = RespVal::parse_length_line(raw, b'>', "Push")?;
+        let (vals, raw_tail) = RespVal::parse_elements(raw_tail, length.unwrap_or(0))?;
+        Ok((RespVal::Push(vals), raw_tail))
+    }
+
+    fn parse_map(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        let (length, raw_tail) = RespVal::parse_length_line(raw, b'%', "Map")?;
+        let (pairs, raw_tail) = RespVal::parse_pairs(raw_tail, length.unwrap_or(0))?;
+        Ok((RespVal::Map(pairs), raw_tail))
+    }
+
+    fn parse_attribute(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        let (length, raw_tail) = RespVal::parse_length_line(raw, b'|', "Attribute")?;
+        let (pairs, raw_tail) = RespVal::parse_pairs(raw_tail, length.unwrap_or(0))?;
+        Ok((RespVal::Attribute(pairs), raw_tail))
+    }
+
+    fn parse_elements(mut raw_tail: &[u8], length: usize) -> Result<(Vec<RespVal>, &[u8])> {
+        // Don't trust the announced length for preallocation before the elements arrived.
+        let mut vals = Vec::with_capacity(length.min(1024));
+        for _i in 0..length {
+            let (val, new_raw_tail) = RespVal::parse_resp_value(raw_tail)?;
+            vals.push(val);
+            raw_tail = new_raw_tail;
+        }
+        Ok((vals, raw_tail))
+    }
+
+    fn parse_pairs(mut raw_tail: &[u8], length: usize) -> Result<(Pairs, &[u8])> {
+        let mut pairs = Vec::with_capacity(length.min(1024));
+        for _i in 0..length {
+            let (key, new_raw_tail) = RespVal::parse_resp_value(raw_tail)?;
+            let (val, new_raw_tail) = RespVal::parse_resp_value(new_raw_tail)?;
+            pairs.push((key, val));
+            raw_tail = new_raw_tail;
+        }
+        Ok((pairs, raw_tail))
+    }
+
     fn parse_bulk_string(raw: &[u8]) -> Result<(RespVal, &[u8])> {
-        if raw[0] != b'$' {
-            return Err(Error::ParseError(
-                "Argument length does not start with dollar sign '$'".to_string(),
-            ));
+        match RespVal::parse_blob(raw, b'$', "Bulk String")? {
+            (None, raw_tail) => Ok((RespVal::Null, raw_tail)),
+            (Some(blob), raw_tail) => Ok((RespVal::BulkString(blob.to_vec()), raw_tail)),
         }
-        let (length, raw_tail) = RespVal::parse_number(&raw[1..])?;
-        let raw_tail = raw_tail.strip_prefix(CRLF.as_ref()).ok_or_else(|| {
-            Error::ParseError(
-                "Error parsing Bulk String: length was not followed by CRLF sequence \r\n"
-                    .to_string(),
-            )
-        })?;
-        let bulk_string = RespVal::BulkString(raw_tail[0..length].to_vec());
-        let raw_tail = raw_tail[length..]
-            .strip_prefix(CRLF.as_ref())
-            .ok_or_else(|| {
-                Error::ParseError(
-                    "Error parsing Bulk String: string was not followed by CRLF sequence \r\n"
-                        .to_string(),
-                )
-            })?;
-        Ok((bulk_string, &raw_tail))
     }
 
-    pub fn parse_resp_value(raw: &[u8]) -> Result<(RespVal, &[u8])> {
-        match raw[0] {
-            b'*' => Ok(RespVal::parse_array(raw)?),
-            b'$' => Ok(RespVal::parse_bulk_string(raw)?),
-            b':' => Ok(RespVal::parse_integer(raw)?),
-            b'+' => Ok(RespVal::parse_simple_string(raw)?),
-            _ => Err(Error::ParseError(format!(
-                "Leading byte {} does not correspond to a RESP type",
-                raw[0]
-            ))),
+    fn parse_bulk_error(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        match RespVal::parse_blob(raw, b'!', "Bulk Error")? {
+            (None, raw_tail) => Ok((RespVal::Null, raw_tail)),
+            (Some(blob), raw_tail) => Ok((RespVal::BulkError(blob.to_vec()), raw_tail)),
+        }
+    }
+
+    fn parse_verbatim_string(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        match RespVal::parse_blob(raw, b'=', "Verbatim String")? {
+            (None, raw_tail) => Ok((RespVal::Null, raw_tail)),
+            (Some(blob), raw_tail) => match blob {
+                [f1, f2, f3, b':', text @ ..] => Ok((
+                    RespVal::VerbatimString([*f1, *f2, *f3], text.to_vec()),
+                    raw_tail,
+                )),
+                _ => Err(Error::ParseError(
+                    "Verbatim String does not start with a format like 'txt:'".to_string(),
+                )),
+            },
         }
     }
 
     fn parse_simple_string(raw: &[u8]) -> Result<(RespVal, &[u8])> {
-        if raw[0] != b'+' {
+        let (line, raw_tail) = RespVal::parse_line(raw, b'+', "Simple String")?;
+        Ok((RespVal::SimpleString(line.to_vec()), raw_tail))
+    }
+
+    fn parse_simple_error(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        let (line, raw_tail) = RespVal::parse_line(raw, b'-', "Simple Error")?;
+        Ok((RespVal::Error(line.to_vec()), raw_tail))
+    }
+
+    fn parse_integer(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        let (line, raw_tail) = RespVal::parse_line(raw, b':', "Integer")?;
+        Ok((RespVal::Integer(RespVal::parse_number(line)?), raw_tail))
+    }
+
+    fn parse_null(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        match RespVal::parse_line(raw, b'_', "Null")? {
+            (b"", raw_tail) => Ok((RespVal::Null, raw_tail)),
+            _ => Err(Error::ParseError("Null must not have content".to_string())),
+        }
+    }
+
+    fn parse_boolean(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        match RespVal::parse_line(raw, b'#', "Boolean")? {
+            (b"t", raw_tail) => Ok((RespVal::Boolean(true), raw_tail)),
+            (b"f", raw_tail) => Ok((RespVal::Boolean(false), raw_tail)),
+            _ => Err(Error::ParseError(
+                "Boolean must be either t or f".to_string(),
+            )),
+        }
+    }
+
+    fn parse_double(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        let (line, raw_tail) = RespVal::parse_line(raw, b',', "Double")?;
+        let double = std::str::from_utf8(line)
+            .ok()
+            .and_then(|line| f64::from_str(line).ok())
+            .ok_or_else(|| Error::ParseError("Error parsing Double".to_string()))?;
+        Ok((RespVal::Double(double), raw_tail))
+    }
+
+    fn parse_big_number(raw: &[u8]) -> Result<(RespVal, &[u8])> {
+        let (line, raw_tail) = RespVal::parse_line(raw, b'(', "Big Number")?;
+        let digits = line
+            .strip_prefix(b"-")
+            .or_else(|| line.strip_prefix(b"+"))
+            .unwrap_or(line);
+        if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
             return Err(Error::ParseError(
-                "Simple String did not start with +".to_string(),
+                "Big Number must consist of digits".to_string(),
             ));
         }
-        let mut simple_string_content: Vec<u8> = Vec::new();
-        let mut end = 0;
-        loop {
-            let byte = raw.get(end);
-            match byte {
-                Some(b'\r') => {
-                    if let Some(b'\n') = raw.get(end + 1) {
-                        break;
-                    } else {
-                        return Err(Error::ParseError("Carriage return byte \r without succeeding \n appeared within Simple String, which is not allowed".to_string()));
+        Ok((RespVal::BigNumber(line.to_vec()), raw_tail))
+    }
+}
+
+/// Incrementally decodes requests, which like in Redis are arrays of bulk strings. Any other
+/// element type is a protocol error, so a request can't nest aggregates.
+///
+/// The state of a partially received request is kept across calls, so that every argument is
+/// copied out of the input once, however many reads a large request arrives in.
+#[derive(Debug, Default)]
+pub struct RequestDecoder {
+    /// The number of arguments still missing once the header of a request was decoded.
+    missing: Option<usize>,
+    args: Vec<RespVal>,
+}
+
+impl RequestDecoder {
+    /// Decodes as much of the request at the front of `raw` as has arrived. Returns the number
+    /// of bytes consumed, which the caller has to drop before decoding more, and the request
+    /// once it is complete.
+    ///
+    /// A request is a non-empty `Array` of `BulkString`s. Returns `Error::ParseError` if the
+    /// bytes can never form a request.
+    pub fn decode(&mut self, raw: &[u8]) -> Result<(usize, Option<RespVal>)> {
+        let mut raw_tail = raw;
+        let mut missing = match self.missing {
+            Some(missing) => missing,
+            None => loop {
+                match RespVal::parse_length_line(raw_tail, b'*', "Array") {
+                    // Like Redis, skip empty and null requests.
+                    Ok((None | Some(0), new_raw_tail)) => raw_tail = new_raw_tail,
+                    Ok((Some(length), new_raw_tail)) => {
+                        raw_tail = new_raw_tail;
+                        // Don't trust the announced length for preallocation before the arguments arrived.
+                        self.args = Vec::with_capacity(length.min(1024));
+                        break length;
                     }
+                    Err(Error::Incomplete) => return Ok((raw.len() - raw_tail.len(), None)),
+                    Err(err) => return Err(err),
                 }
-                Some(b'\n') => {
-                    return Err(Error::ParseError(
-                        "Newline byte \n appeared within Simple String, which is not allowed"
-                            .to_string(),
-                    ));
+            },
+        };
+        while missing > 0 {
+            if let Some(&first_byte) = raw_tail.first().filter(|&&byte| byte != b'$') {
+                return Err(Error::ParseError(format!(
+                    "expected '$', got '{}'",
+                    first_byte as char
+                )));
+            }
+            match RespVal::parse_blob(raw_tail, b'$', "Bulk String") {
+                Ok((Some(arg), new_raw_tail)) => {
+                    self.args.push(RespVal::BulkString(arg.to_vec()));
+                    raw_tail = new_raw_tail;
+                    missing -= 1;
                 }
-                Some(&allowed_byte) => simple_string_content.push(allowed_byte),
-                None => {
-                    return Err(Error::ParseError(
-                        "Simple string did not end with CRLF sequence \r\n".to_string(),
-                    ));
+                Ok((None, _)) => {
+                    return Err(Error::ParseError("null bulk string in request".to_string()))
                 }
+                Err(Error::Incomplete) => break,
+                Err(err) => return Err(err),
             }
-            end += 1;
         }
-        let simple_string = RespVal::SimpleString(simple_string_content);
-        Ok((simple_string, &raw[end..]))
+        let consumed = raw.len() - raw_tail.len();
+        if missing > 0 {
+            self.missing = Some(missing);
+            return Ok((consumed, None));
+        }
+        self.missing = None;
+        Ok((
+            consumed,
+            Some(RespVal::Array(std::mem::take(&mut self.args))),
+        ))
     }
 
-    pub fn parse_array(raw: &[u8]) -> Result<(RespVal, &[u8])> {
-        if raw[0] != b'*' {
-            return Err(Error::ParseError("Array did not start with *".to_string()));
+    /// Whether part of a request was consumed and the rest is still missing.
+    pub fn is_partial(&self) -> bool {
+        self.missing.is_some()
+    }
+}
+
+impl RespVal {
+    /// Splits off the line following the type byte `prefix`, without its CRLF terminator.
+    fn parse_line<'a>(raw: &'a [u8], prefix: u8, type_name: &str) -> Result<(&'a [u8], &'a [u8])> {
+        match raw.first() {
+            None => return Err(Error::Incomplete),
+            Some(&first_byte) if first_byte == prefix => (),
+            Some(_) => {
+                return Err(Error::ParseError(format!(
+                    "{} did not start with {}",
+                    type_name, prefix as char
+                )))
+            }
         }
-        let (length, raw_tail) = RespVal::parse_number(&raw[1..])?;
-        let mut raw_tail = raw_tail.strip_prefix(CRLF.as_ref()).ok_or_else(|| {
-            Error::ParseError(
-                "Error parsing Array: length was not followed by CRLF sequence \r\n".to_string(),
-            )
-        })?;
+        let raw = &raw[1..];
+        let end = match raw[..raw.len().min(MAX_LINE_LENGTH)]
+            .iter()
+            .position(|&byte| byte == b'\r' || byte == b'\n')
+        {
+            Some(end) => end,
+            None if raw.len() >= MAX_LINE_LENGTH => {
+                return Err(Error::ParseError(format!("too big {} line", type_name)))
+            }
+            None => return Err(Error::Incomplete),
+        };
+        match raw.get(end..end + CRLF.len()) {
+            Some(crlf) if crlf == CRLF => Ok((&raw[..end], &raw[end + CRLF.len()..])),
+            None if raw[end] == b'\r' => Err(Error::Incomplete),
+            _ => Err(Error::ParseError(format!(
+                "{} contains a CR or LF byte that is not part of a CRLF sequence \r\n",
+                type_name
+            ))),
+        }
+    }
 
-        let mut array = Vec::with_capacity(length);
-        for _i in 0..length {
-            let (val, new_raw_tail) = RespVal::parse_resp_value(raw_tail)?;
-            array.push(val);
-            raw_tail = new_raw_tail;
+    /// Parses the length line of an aggregate or blob type, where `-1` stands for null.
+    fn parse_length_line<'a>(
+        raw: &'a [u8],
+        prefix: u8,
+        type_name: &str,
+    ) -> Result<(Option<usize>, &'a [u8])> {
+        let (line, raw_tail) = RespVal::parse_line(raw, prefix, type_name)?;
+        match RespVal::parse_number(line)? {
+            -1 => Ok((None, raw_tail)),
+            length if length < 0 || length as usize > MAX_ARRAY_LENGTH => {
+                Err(Error::ParseError(format!("invalid {} length", type_name)))
+            }
+            length => Ok((Some(length as usize), raw_tail)),
         }
-        let resp_array = RespVal::Array(array);
-        Ok((resp_array, &raw_tail))
     }
 
-    fn parse_number(raw: &[u8]) -> Result<(usize, &[u8])> {
-        // dbg!(String::from_utf8_lossy(raw));
-        let mut end = 0;
-        while let Some(byte) = raw.get(end) {
-            if byte.is_ascii_digit() {
-                end += 1;
-            } else {
-                break;
+    fn parse_blob<'a>(
+        raw: &'a [u8],
+        prefix: u8,
+        type_name: &str,
+    ) -> Result<(Option<&'a [u8]>, &'a [u8])> {
+        let (length, raw_tail) = RespVal::parse_length_line(raw, prefix, type_name)?;
+        let length = match length {
+            None => return Ok((None, raw_tail)),
+            Some(length) if length > MAX_BULK_LENGTH => {
+                return Err(Error::ParseError(format!("invalid {} length", type_name)))
             }
+            Some(length) => length,
+        };
+        // The payload and its trailing CRLF may still be in flight.
+        if raw_tail.len() < length + CRLF.len() {
+            return Err(Error::Incomplete);
         }
-        let string = String::from_utf8(raw[0..end].to_vec())?;
-        // dbg!(&string);
-        let number = usize::from_str(&string)
+        if raw_tail[length..length + CRLF.len()] != CRLF {
+            return Err(Error::ParseError(format!(
+                "Error parsing {}: content was not followed by CRLF sequence \r\n",
+                type_name
+            )));
+        }
+        Ok((Some(&raw_tail[..length]), &raw_tail[length + CRLF.len()..]))
+    }
+
+    fn parse_number(raw: &[u8]) -> Result<i64> {
+        let string = std::str::from_utf8(raw)
             .map_err(|err| Error::ParseError(format!("Error parsing number: {}", err)))?;
-        Ok((number, &raw[end..]))
+        i64::from_str(string)
+            .map_err(|err| Error::ParseError(format!("Error parsing number: {}", err)))
     }
 }
 
-pub fn encode(val: &RespVal) -> Vec<u8> {
+/// Formats a double the way Redis replies with it: the shortest representation that
+/// round-trips, `inf`, `-inf` or `nan`, and exponent notation for very large or small values.
+pub fn format_double(double: f64) -> String {
+    if double.is_nan() {
+        return "nan".to_string();
+    }
+    if double.is_infinite() {
+        return if double > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let magnitude = double.abs();
+    if magnitude != 0.0 && !(1e-5..1e17).contains(&magnitude) {
+        let formatted = format!("{:e}", double);
+        match formatted.split_once('e') {
+            Some((mantissa, exponent)) if !exponent.starts_with('-') => {
+                format!("{}e+{}", mantissa, exponent)
+            }
+            _ => formatted,
+        }
+    } else {
+        format!("{}", double)
+    }
+}
+
+/// Appends the encoding of `val` to `out`. For RESP2, RESP3-only types are downgraded:
+/// maps are flattened into arrays, sets and pushes become arrays and scalars become
+/// integers, bulk strings or errors.
+pub fn encode_into(out: &mut Vec<u8>, val: &RespVal, protocol: ProtocolVersion) {
+    let resp3 = protocol == ProtocolVersion::Resp3;
     match val {
-        RespVal::BulkString(bytes) => encode_as_bulk_string(bytes),
-        RespVal::Array(vals) => encode_as_array(vals),
-        RespVal::SimpleString(_bytes) => todo!(),
-        RespVal::UnsignedInteger(_u) => todo!(),
-        RespVal::SignedInteger(_i) => todo!(),
+        RespVal::BulkString(bytes) => encode_blob(out, b'$', bytes),
+        RespVal::Array(vals) => encode_aggregate(out, b'*', vals, protocol),
+        RespVal::SimpleString(bytes) => encode_line(out, b'+', bytes),
+        RespVal::Error(bytes) => encode_line(out, b'-', bytes),
+        RespVal::Integer(num) => write_line(out, ':', num),
+        RespVal::Null if resp3 => out.extend_from_slice(b"_\r\n"),
+        RespVal::Null => out.extend_from_slice(b"$-1\r\n"),
+        RespVal::NullArray if resp3 => out.extend_from_slice(b"_\r\n"),
+        RespVal::NullArray => out.extend_from_slice(b"*-1\r\n"),
+        RespVal::Boolean(boolean) if resp3 => {
+            write_line(out, '#', if *boolean { 't' } else { 'f' })
+        }
+        RespVal::Boolean(boolean) => write_line(out, ':', i64::from(*boolean)),
+        RespVal::Double(double) if resp3 => write_line(out, ',', format_double(*double)),
+        RespVal::Double(double) => encode_blob(out, b'$', format_double(*double).as_bytes()),
+        RespVal::BigNumber(digits) if resp3 => encode_line(out, b'(', digits),
+        RespVal::BigNumber(digits) => encode_blob(out, b'$', digits),
+        RespVal::BulkError(bytes) if resp3 => encode_blob(out, b'!', bytes),
+        RespVal::BulkError(bytes) => encode_line(out, b'-', bytes),
+        RespVal::VerbatimString(format, text) if resp3 => {
+            write_line(out, '=', format.len() + 1 + text.len());
+            out.extend_from_slice(format);
+            out.push(b':');
+            out.extend_from_slice(text);
+            out.extend_from_slice(&CRLF);
+        }
+        RespVal::VerbatimString(_format, text) => encode_blob(out, b'$', text),
+        RespVal::Map(pairs) if resp3 => encode_pairs(out, b'%', pairs, protocol),
+        RespVal::Map(pairs) => encode_pairs(out, b'*', pairs, protocol),
+        RespVal::Set(vals) if resp3 => encode_aggregate(out, b'~', vals, protocol),
+        RespVal::Set(vals) => encode_aggregate(out, b'*', vals, protocol),
+        RespVal::Push(vals) if resp3 => encode_aggregate(out, b'>', vals, protocol),
+        RespVal::Push(vals) => encode_aggregate(out, b'*', vals, protocol),
+        RespVal::Attribute(pairs) if resp3 => encode_pairs(out, b'|', pairs, protocol),
+        RespVal::Attribute(_pairs) => (),
     }
 }
 
-pub fn encode_as_array(vals: &[RespVal]) -> Vec<u8> {
-    let mut result_bytes = Vec::new();
-    write!(result_bytes, "*{}\r\n", vals.len()).expect("Failed to write to Vec.");
-    let inner_bytes: Vec<u8> = vals.iter().flat_map(encode).collect();
-    result_bytes.extend_from_slice(&inner_bytes);
-    result_bytes
+fn write_line(out: &mut Vec<u8>, prefix: char, content: impl std::fmt::Display) {
+    write!(out, "{}{}\r\n", prefix, content).expect("Failed to write to Vec.");
 }
 
-pub fn encode_as_bulk_string(bytes: &[u8]) -> Vec<u8> {
+/// Writes a single line value. CR and LF would end the line early, so they are replaced with
+/// spaces like Redis does.
+fn encode_line(out: &mut Vec<u8>, prefix: u8, bytes: &[u8]) {
+    out.reserve(bytes.len() + 3);
+    out.push(prefix);
+    out.extend(bytes.iter().map(|&byte| match byte {
+        b'\r' | b'\n' => b' ',
+        byte => byte,
+    }));
+    out.extend_from_slice(&CRLF);
+}
+
+fn encode_blob(out: &mut Vec<u8>, prefix: u8, bytes: &[u8]) {
     // 15 comes from approximation of max byte length of string representation of bytes.len()
-    let mut result = Vec::with_capacity(bytes.len() + 15);
-    write!(result, "${}\r\n", bytes.len()).expect("Failed to write length prefix");
-    result.extend_from_slice(bytes);
-    result.extend_from_slice(b"\r\n");
+    out.reserve(bytes.len() + 15);
+    write_line(out, prefix as char, bytes.len());
+    out.extend_from_slice(bytes);
+    out.extend_from_slice(&CRLF);
+}
+
+fn encode_aggregate(out: &mut Vec<u8>, prefix: u8, vals: &[RespVal], protocol: ProtocolVersion) {
+    write_line(out, prefix as char, vals.len());
+    for val in vals {
+        encode_into(out, val, protocol);
+    }
+}
+
+/// Writes key-value pairs. With the array prefix `*` the pairs are flattened, so the
+/// announced length is twice the number of pairs.
+fn encode_pairs(
+    out: &mut Vec<u8>,
+    prefix: u8,
+    pairs: &[(RespVal, RespVal)],
+    protocol: ProtocolVersion,
+) {
+    let length = if prefix == b'*' {
+        pairs.len() * 2
+    } else {
+        pairs.len()
+    };
+    write_line(out, prefix as char, length);
+    for (key, val) in pairs {
+        encode_into(out, key, protocol);
+        encode_into(out, val, protocol);
+    }
+}
 
-    result
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn encode(val: &RespVal, protocol: ProtocolVersion) -> Vec<u8> {
+        let mut result = Vec::new();
+        encode_into(&mut result, val, protocol);
+        result
+    }
+
+    fn decode(raw: &[u8]) -> Result<(usize, Option<RespVal>)> {
+        RequestDecoder::default().decode(raw)
+    }
+
+    fn request(args: &[&str]) -> RespVal {
+        RespVal::Array(
+            args.iter()
+                .map(|arg| RespVal::BulkString(arg.as_bytes().to_vec()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_decode_request() {
+        let bytes = b"*2\r\n$4\r\necho\r\n$3\r\nhey\r\n";
+        assert_eq!(
+            decode(bytes).unwrap(),
+            (bytes.len(), Some(request(&["echo", "hey"])))
+        );
+        // Empty and null requests are skipped.
+        assert_eq!(decode(b"*0\r\n*-1\r\n").unwrap(), (9, None));
+        let bytes = b"*-1\r\n*0\r\n*1\r\n$4\r\nping\r\n";
+        assert_eq!(
+            decode(bytes).unwrap(),
+            (bytes.len(), Some(request(&["ping"])))
+        );
+    }
+
+    #[test]
+    fn test_decode_leaves_pipelined_remainder() {
+        let bytes = b"*1\r\n$4\r\nping\r\n*1\r\n$4\r\nping\r\n";
+        let (consumed, _) = decode(bytes).unwrap();
+        assert_eq!(&bytes[consumed..], b"*1\r\n$4\r\nping\r\n");
+    }
+
+    #[test]
+    fn test_decode_every_prefix_is_incomplete() {
+        let bytes = b"*2\r\n$3\r\nget\r\n$5\r\nhello\r\n";
+        for end in 0..bytes.len() {
+            assert!(
+                matches!(decode(&bytes[..end]), Ok((_, None))),
+                "prefix of length {} should be incomplete",
+                end
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_across_reads() {
+        let bytes = b"*3\r\n$3\r\nset\r\n$1\r\nk\r\n$5\r\nvalue\r\n";
+        let mut decoder = RequestDecoder::default();
+        let mut buffer = Vec::new();
+        for (i, &byte) in bytes.iter().enumerate() {
+            buffer.push(byte);
+            let (consumed, frame) = decoder.decode(&buffer).unwrap();
+            buffer.drain(..consumed);
+            // Complete arguments leave the buffer as soon as they arrived.
+            assert!(buffer.len() <= b"$5\r\nvalue\r".len());
+            assert_eq!(frame.is_some(), i == bytes.len() - 1);
+            assert_eq!(decoder.is_partial(), (3..bytes.len() - 1).contains(&i));
+            if let Some(frame) = frame {
+                assert_eq!(frame, request(&["set", "k", "value"]));
+            }
+        }
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_decode_malformed() {
+        assert!(matches!(decode(b"+OK\r\n"), Err(Error::ParseError(_))));
+        assert!(matches!(decode(b"*x\r\n"), Err(Error::ParseError(_))));
+        assert!(matches!(
+            decode(b"*1\r\n$3\r\nabcde\r\n"),
+            Err(Error::ParseError(_))
+        ));
+        assert!(matches!(decode(b"*1\r\n:1\r\n"), Err(Error::ParseError(_))));
+        assert!(matches!(
+            decode(b"*1\r\n$-1\r\n"),
+            Err(Error::ParseError(_))
+        ));
+        // Requests never nest, however deep the nesting is.
+        assert!(matches!(
+            decode(b"*1\r\n*1\r\n$4\r\nping\r\n"),
+            Err(Error::ParseError(_))
+        ));
+        assert!(matches!(
+            decode(&b"*1\r\n".repeat(200_000)),
+            Err(Error::ParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_decode_line_too_long() {
+        let mut bytes = b"*".to_vec();
+        bytes.resize(MAX_LINE_LENGTH, b'1');
+        assert!(matches!(decode(&bytes), Ok((0, None))));
+        bytes.push(b'1');
+        assert!(matches!(decode(&bytes), Err(Error::ParseError(_))));
+        let mut bytes = b"*1\r\n$".to_vec();
+        bytes.resize(bytes.len() + MAX_LINE_LENGTH, b'1');
+        assert!(matches!(decode(&bytes), Err(Error::ParseError(_))));
+    }
+
+    #[test]
+    fn test_decode_large_bulk_string() {
+        let payload = vec![b'x'; 100_000];
+        let bytes = encode(
+            &RespVal::Array(vec![RespVal::BulkString(payload.clone())]),
+            ProtocolVersion::Resp2,
+        );
+        assert_eq!(
+            decode(&bytes).unwrap(),
+            (
+                bytes.len(),
+                Some(RespVal::Array(vec![RespVal::BulkString(payload)]))
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_resp3_types() {
+        let bytes = b"%2\r\n+first\r\n:-1\r\n$6\r\nsecond\r\n~3\r\n,1.5\r\n#t\r\n_\r\n";
+        let (val, remainder) = RespVal::parse_resp_value(bytes).unwrap();
+        assert!(remainder.is_empty());
+        assert_eq!(
+            val,
+            RespVal::Map(vec![
+                (
+                    RespVal::SimpleString(b"first".to_vec()),
+                    RespVal::Integer(-1)
+                ),
+                (
+                    RespVal::BulkString(b"second".to_vec()),
+                    RespVal::Set(vec![
+                        RespVal::Double(1.5),
+                        RespVal::Boolean(true),
+                        RespVal::Null
+                    ])
+                ),
+            ])
+        );
+        let bytes = b"=9\r\ntxt:hello\r\n(-123456789012345678901234567890\r\n!3\r\nERR\r\n";
+        let (val, remainder) = RespVal::parse_resp_value(bytes).unwrap();
+        assert_eq!(val, RespVal::VerbatimString(*b"txt", b"hello".to_vec()));
+        let (val, remainder) = RespVal::parse_resp_value(remainder).unwrap();
+        assert_eq!(
+            val,
+            RespVal::BigNumber(b"-123456789012345678901234567890".to_vec())
+        );
+        let (val, _) = RespVal::parse_resp_value(remainder).unwrap();
+        assert_eq!(val, RespVal::BulkError(b"ERR".to_vec()));
+    }
+
+    #[test]
+    fn test_parse_resp2_nulls() {
+        assert_eq!(
+            RespVal::parse_resp_value(b"$-1\r\n").unwrap().0,
+            RespVal::Null
+        );
+        assert_eq!(
+            RespVal::parse_resp_value(b"*-1\r\n").unwrap().0,
+            RespVal::NullArray
+        );
+    }
+
+    #[test]
+    fn test_encode_roundtrip() {
+        let val = RespVal::Array(vec![
+            RespVal::SimpleString(b"OK".to_vec()),
+            RespVal::Error(b"ERR bad".to_vec()),
+            RespVal::Integer(-42),
+            RespVal::BulkString(b"bulk".to_vec()),
+            RespVal::Map(vec![(
+                RespVal::BulkString(b"k".to_vec()),
+                RespVal::Double(0.25),
+            )]),
+            RespVal::Push(vec![RespVal::BigNumber(b"12345678901234567890".to_vec())]),
+            RespVal::VerbatimString(*b"txt", b"verbatim".to_vec()),
+            RespVal::Boolean(false),
+            RespVal::Null,
+        ]);
+        let bytes = encode(&val, ProtocolVersion::Resp3);
+        assert_eq!(RespVal::parse_resp_value(&bytes).unwrap(), (val, &b""[..]));
+    }
+
+    #[test]
+    fn test_encode_downgrades_to_resp2() {
+        let val = RespVal::Array(vec![
+            RespVal::Map(vec![(
+                RespVal::BulkString(b"k".to_vec()),
+                RespVal::Double(1.5),
+            )]),
+            RespVal::Boolean(true),
+            RespVal::Null,
+            RespVal::NullArray,
+        ]);
+        assert_eq!(
+            encode(&val, ProtocolVersion::Resp2),
+            b"*4\r\n*2\r\n$1\r\nk\r\n$3\r\n1.5\r\n:1\r\n$-1\r\n*-1\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_format_double() {
+        assert_eq!(format_double(3.0), "3");
+        assert_eq!(format_double(-0.5), "-0.5");
+        assert_eq!(format_double(f64::INFINITY), "inf");
+        assert_eq!(format_double(f64::NEG_INFINITY), "-inf");
+        assert_eq!(format_double(1e300), "1e+300");
+        assert_eq!(format_double(1.5e-10), "1.5e-10");
+    }
 }
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+/// Sets of integers only stay in the compact encoding up to this many members, like Redis'
+/// default `set-max-intset-entries`.
+const MAX_INTSET_ENTRIES: usize = 512;
+
+/// The members of a set value.
+///
+/// Like in Redis, a set whose members are all integers in canonical form is kept as a sorted
+/// vector of integers, an "intset", until it grows too large or gets a member that isn't an
+/// integer. It then converts to a table of members for good.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Set {
+    encoding: Encoding,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Encoding {
+    Intset(Vec<i64>),
+    /// Members in a vector, which makes picking random members O(1), and the position of each.
+    Hashtable {
+        members: Vec<Vec<u8>>,
+        positions: HashMap<Vec<u8>, usize>,
+    },
+}
+
+impl Default for Set {
+    fn default() -> Set {
+        Set {
+            encoding: Encoding::Intset(Vec::new()),
+        }
+    }
+}
+
+/// The integer `member` spells, if it is in the canonical form an integer is printed in.
+fn as_integer(member: &[u8]) -> Option<i64> {
+    let integer: i64 = std::str::from_utf8(member).ok()?.parse().ok()?;
+    (integer.to_string().as_bytes() == member).then_some(integer)
+}
+
+impl Set {
+    pub fn len(&self) -> usize {
+        match &self.encoding {
+            Encoding::Intset(integers) => integers.len(),
+            Encoding::Hashtable { members, .. } => members.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The name `OBJECT ENCODING` reports.
+    pub fn encoding_name(&self) -> &'static str {
+        match self.encoding {
+            Encoding::Intset(_) => "intset",
+            Encoding::Hashtable { .. } => "hashtable",
+        }
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match &self.encoding {
+            Encoding::Intset(integers) => as_integer(member).is_some_and(|integer| integers.binary_search(&integer).is_ok()),
+            Encoding::Hashtable { positions, .. } => positions.contains_key(member),
+        }
+    }
+
+    /// Adds `member`, returning true if it is new.
+    pub fn insert(&mut self, member: Vec<u8>) -> bool {
+        if let Encoding::Intset(integers) = &mut self.encoding {
+            if let Some(integer) = as_integer(&member) {
+                match integers.binary_search(&integer) {
+                    Ok(_) => return false,
+                    Err(position) if integers.len() < MAX_INTSET_ENTRIES => {
+                        integers.insert(position, integer);
+                        return true;
+                    }
+                    Err(_) => {}
+                }
+            }
+            self.convert_to_hashtable();
+        }
+        let Encoding::Hashtable { members, positions } = &mut self.encoding else {
+            unreachable!("the set was converted");
+        };
+        if positions.contains_key(&member) {
+            return false;
+        }
+        positions.insert(member.clone(), members.len());
+        members.push(member);
+        true
+    }
+
+    /// Removes `member`, returning true if it was there.
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match &mut self.encoding {
+            Encoding::Intset(integers) => {
+                let position = as_integer(member).and_then(|integer| integers.binary_search(&integer).ok());
+                position.map(|position| integers.remove(position)).is_some()
+            }
+            Encoding::Hashtable { members, positions } => {
+                let position = match positions.remove(member) {
+                    Some(position) => position,
+                    None => return false,
+                };
+                members.swap_remove(position);
+                if let Some(moved_member) = members.get(position) {
+                    *positions.get_mut(moved_member).expect("every member has a position") = position;
+                }
+                true
+            }
+        }
+    }
+
+    /// The member at `index`, which must be less than the length.
+    pub fn member_at(&self, index: usize) -> Vec<u8> {
+        match &self.encoding {
+            Encoding::Intset(integers) => integers[index].to_string().into_bytes(),
+            Encoding::Hashtable { members, .. } => members[index].clone(),
+        }
+    }
+
+    pub fn members(&self) -> Vec<Vec<u8>> {
+        match &self.encoding {
+            Encoding::Intset(integers) => integers.iter().map(|integer| integer.to_string().into_bytes()).collect(),
+            Encoding::Hashtable { members, .. } => members.clone(),
+        }
+    }
+
+    fn convert_to_hashtable(&mut self) {
+        if let Encoding::Intset(integers) = &self.encoding {
+            let members: Vec<Vec<u8>> = integers.iter().map(|integer| integer.to_string().into_bytes()).collect();
+            let positions = members
+                .iter()
+                .enumerate()
+                .map(|(position, member)| (member.clone(), position))
+                .collect();
+            self.encoding = Encoding::Hashtable { members, positions };
+        }
+    }
+}
+
+impl FromIterator<Vec<u8>> for Set {
+    fn from_iter<I: IntoIterator<Item = Vec<u8>>>(members: I) -> Set {
+        let mut set = Set::default();
+        for member in members {
+            set.insert(member);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_intset_converts_to_hashtable() {
+        let mut set: Set = ["3", "1", "2"].iter().map(|member| member.as_bytes().to_vec()).collect();
+        assert_eq!(set.encoding_name(), "intset");
+        assert_eq!(set.members(), vec![b"1".to_vec(), b"2".to_vec(), b"3".to_vec()]);
+        // Not canonical integers.
+        assert!(!set.contains(b"01"));
+        assert!(set.insert(b"01".to_vec()));
+        assert_eq!(set.encoding_name(), "hashtable");
+        assert!(set.contains(b"1") && set.contains(b"01"));
+        assert!(set.remove(b"01"));
+        assert_eq!(set.encoding_name(), "hashtable");
+        let mut large: Set = (0..MAX_INTSET_ENTRIES).map(|i| i.to_string().into_bytes()).collect();
+        assert_eq!(large.encoding_name(), "intset");
+        assert!(!large.insert(b"0".to_vec()));
+        assert!(large.insert(b"-1".to_vec()));
+        assert_eq!(large.encoding_name(), "hashtable");
+        assert_eq!(large.len(), MAX_INTSET_ENTRIES + 1);
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Entries per node of the radix tree Redis keeps streams in, its default
+/// `stream-node-max-entries`. Approximate trimming only removes whole nodes.
+pub const NODE_MAX_ENTRIES: usize = 100;
+
+/// The ID of a stream entry: a millisecond timestamp and a sequence number for entries added
+/// within the same millisecond.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    pub const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    pub const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    pub fn new(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    /// Parses `ms-seq`, or `ms` alone with `missing_seq` as sequence number.
+    pub fn parse(arg: &[u8], missing_seq: u64) -> Option<StreamId> {
+        let arg = std::str::from_utf8(arg).ok()?;
+        // Unlike `u64::from_str`, no sign is allowed.
+        let parse_part = |part: &str| {
+            if part.bytes().all(|byte| byte.is_ascii_digit()) {
+                part.parse().ok()
+            } else {
+                None
+            }
+        };
+        match arg.split_once('-') {
+            Some((ms, seq)) => Some(StreamId::new(parse_part(ms)?, parse_part(seq)?)),
+            None => Some(StreamId::new(parse_part(arg)?, missing_seq)),
+        }
+    }
+
+    /// The smallest ID greater than this one.
+    pub fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_add(1)?, 0)),
+        }
+    }
+
+    /// The greatest ID smaller than this one.
+    pub fn previous(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId::new(self.ms, seq)),
+            None => Some(StreamId::new(self.ms.checked_sub(1)?, u64::MAX)),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// The field-value pairs of an entry.
+pub type Fields = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Which entries trimming keeps: the newest ones, or those from an ID on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrimStrategy {
+    MaxLen(usize),
+    MinId(StreamId),
+}
+
+/// How `XTRIM` and `XADD` trim a stream. Approximate trimming removes nothing but whole nodes,
+/// at most `limit` entries worth of them; exact trimming has no limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Trim {
+    pub strategy: TrimStrategy,
+    pub approximate: bool,
+    pub limit: Option<usize>,
+}
+
+/// A message delivered to a consumer of a group but not acknowledged yet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PendingEntry {
+    pub consumer: Vec<u8>,
+    /// Unix time in milliseconds of the last delivery.
+    pub delivery_time: u64,
+    pub delivery_count: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Consumer {
+    /// Unix time in milliseconds the consumer was last seen, reading or claiming or not.
+    pub seen_time: u64,
+    /// Unix time in milliseconds the consumer last got a message, if it ever did.
+    pub active_time: Option<u64>,
+    /// The IDs of the messages pending for this consumer.
+    pending: BTreeSet<StreamId>,
+}
+
+impl Consumer {
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+/// A consumer group: where its consumers are in the stream and which messages they were given
+/// without acknowledging them, the pending entries list or PEL. Every pending entry is in the
+/// PEL of the group and in the one of the consumer that owns it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConsumerGroup {
+    last_delivered_id: StreamId,
+    /// Number of entries delivered to the group since the stream was created, counting those
+    /// before `last_delivered_id` at creation.
+    entries_read: u64,
+    pending: BTreeMap<StreamId, PendingEntry>,
+    consumers: BTreeMap<Vec<u8>, Consumer>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_delivered_id: StreamId, entries_read: u64) -> ConsumerGroup {
+        ConsumerGroup {
+            last_delivered_id,
+            entries_read,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+        }
+    }
+
+    pub fn last_delivered_id(&self) -> StreamId {
+        self.last_delivered_id
+    }
+
+    pub fn entries_read(&self) -> u64 {
+        self.entries_read
+    }
+
+    pub fn set_last_delivered_id(&mut self, id: StreamId, entries_read: u64) {
+        self.last_delivered_id = id;
+        self.entries_read = entries_read;
+    }
+
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn pending_entry(&self, id: StreamId) -> Option<&PendingEntry> {
+        self.pending.get(&id)
+    }
+
+    /// The pending entries with IDs from `start` to `end`, inclusive.
+    pub fn pending_range(&self, start: StreamId, end: StreamId) -> impl DoubleEndedIterator<Item = (StreamId, &PendingEntry)> {
+        let range = if start <= end { Some(self.pending.range(start..=end)) } else { None };
+        range.into_iter().flatten().map(|(&id, entry)| (id, entry))
+    }
+
+    pub fn consumers(&self) -> impl Iterator<Item = (&Vec<u8>, &Consumer)> {
+        self.consumers.iter()
+    }
+
+    pub fn consumer(&self, name: &[u8]) -> Option<&Consumer> {
+        self.consumers.get(name)
+    }
+
+    /// Adds a consumer, returning false if it exists already.
+    pub fn create_consumer(&mut self, name: &[u8], now: u64) -> bool {
+        if self.consumers.contains_key(name) {
+            return false;
+        }
+        let consumer = Consumer {
+            seen_time: now,
+            ..Consumer::default()
+        };
+        self.consumers.insert(name.to_vec(), consumer);
+        true
+    }
+
+    /// Records that a consumer was seen, creating it if needed.
+    pub fn touch_consumer(&mut self, name: &[u8], now: u64) {
+        if !self.create_consumer(name, now) {
+            self.consumers.get_mut(name).expect("the consumer exists").seen_time = now;
+        }
+    }
+
+    /// Deletes a consumer and its pending entries, returning how many it had, or `None` if
+    /// there is no such consumer.
+    pub fn delete_consumer(&mut self, name: &[u8]) -> Option<usize> {
+        let consumer = self.consumers.remove(name)?;
+        for id in &consumer.pending {
+            self.pending.remove(id);
+        }
+        Some(consumer.pending.len())
+    }
+
+    /// The IDs of the entries pending for `consumer` after `after`, at most `count` of them.
+    pub fn consumer_pending_after(&self, consumer: &[u8], after: StreamId, count: Option<usize>) -> Vec<StreamId> {
+        let (consumer, start) = match (self.consumers.get(consumer), after.next()) {
+            (Some(consumer), Some(start)) => (consumer, start),
+            _ => return Vec::new(),
+        };
+        consumer.pending.range(start..).take(count.unwrap_or(usize::MAX)).copied().collect()
+    }
+
+    /// Makes `consumer`, which must exist, the owner of the entry with `id`, which becomes
+    /// pending if it wasn't, and sets when it was delivered and how often.
+    pub fn assign(&mut self, id: StreamId, consumer: &[u8], delivery_time: u64, delivery_count: u64) {
+        let entry = PendingEntry {
+            consumer: consumer.to_vec(),
+            delivery_time,
+            delivery_count,
+        };
+        if let Some(previous) = self.pending.insert(id, entry) {
+            if let Some(owner) = self.consumers.get_mut(&previous.consumer) {
+                owner.pending.remove(&id);
+            }
+        }
+        let owner = self.consumers.get_mut(consumer).expect("the consumer exists");
+        owner.pending.insert(id);
+    }
+
+    /// Records that a pending entry was delivered again to its owner.
+    pub fn redeliver(&mut self, id: StreamId, now: u64) {
+        if let Some(entry) = self.pending.get_mut(&id) {
+            entry.delivery_time = now;
+            entry.delivery_count += 1;
+        }
+    }
+
+    /// Records that `consumer`, which must exist, got messages.
+    pub fn mark_active(&mut self, consumer: &[u8], now: u64) {
+        self.consumers.get_mut(consumer).expect("the consumer exists").active_time = Some(now);
+    }
+
+    /// Removes an entry from the pending entries, returning false if it wasn't pending.
+    pub fn acknowledge(&mut self, id: StreamId) -> bool {
+        let entry = match self.pending.remove(&id) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        if let Some(owner) = self.consumers.get_mut(&entry.consumer) {
+            owner.pending.remove(&id);
+        }
+        true
+    }
+}
+
+/// The entries of a stream value, ordered by ID, which makes range queries logarithmic, and
+/// its consumer groups.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stream {
+    entries: BTreeMap<StreamId, Fields>,
+    /// The ID of the last entry ever added; it stays when that entry is deleted so IDs never
+    /// go backwards.
+    last_id: StreamId,
+    /// Number of entries ever added.
+    entries_added: u64,
+    /// The greatest ID of an entry deleted with `XDEL`, 0-0 if there is none.
+    max_deleted_id: StreamId,
+    groups: BTreeMap<Vec<u8>, ConsumerGroup>,
+}
+
+impl Stream {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    /// The ID an entry added at `now_ms` automatically gets, or `None` if the last possible
+    /// ID was used.
+    pub fn next_id(&self, now_ms: u64) -> Option<StreamId> {
+        if now_ms > self.last_id.ms {
+            Some(StreamId::new(now_ms, 0))
+        } else {
+            self.last_id.next()
+        }
+    }
+
+    /// Appends an entry; `id` must be greater than the last ID.
+    pub fn insert(&mut self, id: StreamId, fields: Fields) {
+        self.entries.insert(id, fields);
+        self.last_id = id;
+        self.entries_added += 1;
+    }
+
+    pub fn remove(&mut self, id: StreamId) -> bool {
+        if self.entries.remove(&id).is_none() {
+            return false;
+        }
+        self.max_deleted_id = self.max_deleted_id.max(id);
+        true
+    }
+
+    pub fn entries_added(&self) -> u64 {
+        self.entries_added
+    }
+
+    pub fn max_deleted_id(&self) -> StreamId {
+        self.max_deleted_id
+    }
+
+    pub fn get(&self, id: StreamId) -> Option<&Fields> {
+        self.entries.get(&id)
+    }
+
+    /// Number of entries with IDs greater than `id`.
+    pub fn count_after(&self, id: StreamId) -> usize {
+        id.next().map_or(0, |start| self.entries.range(start..).count())
+    }
+
+    /// An estimate of how many entries up to `id` were ever added: exact unless entries after
+    /// `id` were deleted.
+    pub fn entries_read_up_to(&self, id: StreamId) -> u64 {
+        self.entries_added.saturating_sub(self.count_after(id) as u64)
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = (&Vec<u8>, &ConsumerGroup)> {
+        self.groups.iter()
+    }
+
+    pub fn group(&self, name: &[u8]) -> Option<&ConsumerGroup> {
+        self.groups.get(name)
+    }
+
+    pub fn group_mut(&mut self, name: &[u8]) -> Option<&mut ConsumerGroup> {
+        self.groups.get_mut(name)
+    }
+
+    /// Adds a consumer group, returning false if there is one with that name already.
+    pub fn create_group(&mut self, name: &[u8], group: ConsumerGroup) -> bool {
+        if self.groups.contains_key(name) {
+            return false;
+        }
+        self.groups.insert(name.to_vec(), group);
+        true
+    }
+
+    pub fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    /// Gives up to `count` of the entries after the last one delivered to the group `group`,
+    /// which must exist, to `consumer`. Unless `no_ack`, they stay pending until acknowledged.
+    pub fn deliver_new(&mut self, group: &[u8], consumer: &[u8], count: Option<usize>, no_ack: bool, now: u64) -> Vec<(StreamId, Fields)> {
+        let group = self.groups.get_mut(group).expect("the group exists");
+        let delivered: Vec<(StreamId, Fields)> = match group.last_delivered_id.next() {
+            Some(start) => self
+                .entries
+                .range(start..)
+                .take(count.unwrap_or(usize::MAX))
+                .map(|(&id, fields)| (id, fields.clone()))
+                .collect(),
+            None => Vec::new(),
+        };
+        for (id, _) in &delivered {
+            if !no_ack {
+                group.assign(*id, consumer, now, 1);
+            }
+            group.last_delivered_id = *id;
+            group.entries_read += 1;
+        }
+        if !delivered.is_empty() {
+            group.mark_active(consumer, now);
+        }
+        delivered
+    }
+
+    pub fn first_entry(&self) -> Option<(StreamId, &Fields)> {
+        self.entries.first_key_value().map(|(&id, fields)| (id, fields))
+    }
+
+    pub fn last_entry(&self) -> Option<(StreamId, &Fields)> {
+        self.entries.last_key_value().map(|(&id, fields)| (id, fields))
+    }
+
+    /// The entries with IDs from `start` to `end`, inclusive, from the newest if `reverse`, at
+    /// most `count` of them.
+    pub fn range(&self, start: StreamId, end: StreamId, reverse: bool, count: Option<usize>) -> Vec<(StreamId, &Fields)> {
+        if start > end {
+            return Vec::new();
+        }
+        let range = self.entries.range(start..=end).map(|(&id, fields)| (id, fields));
+        let count = count.unwrap_or(usize::MAX);
+        if reverse {
+            range.rev().take(count).collect()
+        } else {
+            range.take(count).collect()
+        }
+    }
+
+    /// Removes the oldest entries `trim` asks for, returning how many.
+    pub fn trim(&mut self, trim: &Trim) -> usize {
+        let mut excess = match trim.strategy {
+            TrimStrategy::MaxLen(max_len) => self.len().saturating_sub(max_len),
+            TrimStrategy::MinId(min_id) => self.entries.range(..min_id).count(),
+        };
+        if trim.approximate {
+            if let Some(limit) = trim.limit {
+                excess = excess.min(limit);
+            }
+            excess -= excess % NODE_MAX_ENTRIES;
+        }
+        for _ in 0..excess {
+            self.entries.pop_first();
+        }
+        excess
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ids() {
+        assert_eq!(StreamId::parse(b"5", 0), Some(StreamId::new(5, 0)));
+        assert_eq!(StreamId::parse(b"5", u64::MAX), Some(StreamId::new(5, u64::MAX)));
+        assert_eq!(StreamId::parse(b"5-3", 0), Some(StreamId::new(5, 3)));
+        assert_eq!(StreamId::parse(b"5-", 0), None);
+        assert_eq!(StreamId::parse(b"-3", 0), None);
+        assert_eq!(StreamId::parse(b"+5-3", 0), None);
+        assert_eq!(StreamId::new(1, u64::MAX).next(), Some(StreamId::new(2, 0)));
+        assert_eq!(StreamId::MAX.next(), None);
+        assert_eq!(StreamId::new(2, 0).previous(), Some(StreamId::new(1, u64::MAX)));
+        assert_eq!(StreamId::MIN.previous(), None);
+        let mut stream = Stream::default();
+        assert_eq!(stream.next_id(7), Some(StreamId::new(7, 0)));
+        stream.insert(StreamId::new(7, 0), Vec::new());
+        // The clock went backwards.
+        assert_eq!(stream.next_id(6), Some(StreamId::new(7, 1)));
+    }
+
+    #[test]
+    fn test_trim() {
+        let mut stream = Stream::default();
+        for ms in 1..=250 {
+            stream.insert(StreamId::new(ms, 0), Vec::new());
+        }
+        let trim = |strategy, approximate, limit| Trim {
+            strategy,
+            approximate,
+            limit,
+        };
+        assert_eq!(stream.trim(&trim(TrimStrategy::MaxLen(100), true, None)), 100);
+        assert_eq!(stream.trim(&trim(TrimStrategy::MaxLen(100), true, None)), 0);
+        assert_eq!(stream.trim(&trim(TrimStrategy::MinId(StreamId::new(240, 0)), true, Some(50))), 0);
+        assert_eq!(stream.trim(&trim(TrimStrategy::MinId(StreamId::new(240, 0)), false, None)), 139);
+        assert_eq!(stream.first_entry().unwrap().0, StreamId::new(240, 0));
+        assert_eq!(stream.last_id(), StreamId::new(250, 0));
+        assert_eq!(stream.range(StreamId::new(248, 0), StreamId::MAX, true, Some(2)).len(), 2);
+    }
+}
@@ -0,0 +1,541 @@
+use crate::random;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Levels of the skiplist; enough for 4^32 elements.
+const MAX_LEVEL: usize = 32;
+/// A node that has a level also has the next level with this probability.
+const LEVEL_PROBABILITY: f64 = 0.25;
+/// The header node, which holds no element.
+const HEAD: usize = 0;
+
+#[derive(Clone, Debug)]
+struct Node {
+    member: Vec<u8>,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Level>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Level {
+    forward: Option<usize>,
+    /// Number of elements this link skips over, counting the one it points to.
+    span: usize,
+}
+
+/// Orders elements by score, and members with equal scores lexicographically.
+fn compare(score: f64, member: &[u8], other_score: f64, other_member: &[u8]) -> Ordering {
+    score
+        .partial_cmp(&other_score)
+        .expect("scores are never NaN")
+        .then_with(|| member.cmp(other_member))
+}
+
+fn random_level() -> usize {
+    let threshold = (LEVEL_PROBABILITY * u32::MAX as f64) as u64;
+    let mut level = 1;
+    while level < MAX_LEVEL && (random::random_u64() & 0xFFFF_FFFF) < threshold {
+        level += 1;
+    }
+    level
+}
+
+/// A skiplist of members ordered by score, modeled on Redis' `zskiplist`.
+///
+/// Links store how many elements they skip, which makes finding the rank of an element, or the
+/// element at a rank, O(log n) like any other lookup. Nodes live in a vector and refer to each
+/// other by index; the slots of deleted nodes are reused.
+#[derive(Clone, Debug)]
+pub struct SkipList {
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: Option<usize>,
+    len: usize,
+    /// Number of levels in use.
+    level: usize,
+}
+
+impl Default for SkipList {
+    fn default() -> SkipList {
+        let head = Node {
+            member: Vec::new(),
+            score: 0.0,
+            backward: None,
+            levels: vec![Level { forward: None, span: 0 }; MAX_LEVEL],
+        };
+        SkipList {
+            nodes: vec![head],
+            free: Vec::new(),
+            tail: None,
+            len: 0,
+            level: 1,
+        }
+    }
+}
+
+impl SkipList {
+    fn forward(&self, node: usize, level: usize) -> Option<usize> {
+        self.nodes[node].levels[level].forward
+    }
+
+    fn span(&self, node: usize, level: usize) -> usize {
+        self.nodes[node].levels[level].span
+    }
+
+    /// Whether the element of `node` is ordered before `score` and `member`.
+    fn is_before(&self, node: usize, score: f64, member: &[u8]) -> bool {
+        let node = &self.nodes[node];
+        compare(node.score, &node.member, score, member) == Ordering::Less
+    }
+
+    /// The last node before `score` and `member` on every level, and the rank of each.
+    fn find_predecessors(&self, score: f64, member: &[u8]) -> ([usize; MAX_LEVEL], [usize; MAX_LEVEL]) {
+        let mut update = [HEAD; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut node = HEAD;
+        for level in (0..self.level).rev() {
+            rank[level] = if level == self.level - 1 { 0 } else { rank[level + 1] };
+            while let Some(next) = self.forward(node, level).filter(|&next| self.is_before(next, score, member)) {
+                rank[level] += self.span(node, level);
+                node = next;
+            }
+            update[level] = node;
+        }
+        (update, rank)
+    }
+
+    /// Inserts an element, which must not be in the list yet.
+    pub fn insert(&mut self, score: f64, member: Vec<u8>) {
+        let (mut update, mut rank) = self.find_predecessors(score, &member);
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                rank[i] = 0;
+                update[i] = HEAD;
+                self.nodes[HEAD].levels[i].span = self.len;
+            }
+            self.level = level;
+        }
+        let node = Node {
+            member,
+            score,
+            backward: (update[0] != HEAD).then_some(update[0]),
+            levels: vec![Level { forward: None, span: 0 }; level],
+        };
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.nodes[id] = node;
+                id
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        };
+        for i in 0..level {
+            let previous = self.nodes[update[i]].levels[i];
+            self.nodes[id].levels[i] = Level {
+                forward: previous.forward,
+                span: previous.span - (rank[0] - rank[i]),
+            };
+            self.nodes[update[i]].levels[i] = Level {
+                forward: Some(id),
+                span: rank[0] - rank[i] + 1,
+            };
+        }
+        for (i, &node) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[node].levels[i].span += 1;
+        }
+        match self.forward(id, 0) {
+            Some(next) => self.nodes[next].backward = Some(id),
+            None => self.tail = Some(id),
+        }
+        self.len += 1;
+    }
+
+    /// Deletes an element, returning false if it isn't in the list.
+    pub fn remove(&mut self, score: f64, member: &[u8]) -> bool {
+        let (update, _) = self.find_predecessors(score, member);
+        let id = match self.forward(update[0], 0) {
+            Some(id) if self.nodes[id].score == score && self.nodes[id].member == member => id,
+            _ => return false,
+        };
+        for (i, &node) in update.iter().enumerate().take(self.level) {
+            if self.forward(node, i) == Some(id) {
+                self.nodes[node].levels[i] = Level {
+                    forward: self.forward(id, i),
+                    span: self.span(node, i) + self.span(id, i) - 1,
+                };
+            } else {
+                self.nodes[node].levels[i].span -= 1;
+            }
+        }
+        match self.forward(id, 0) {
+            Some(next) => self.nodes[next].backward = self.nodes[id].backward,
+            None => self.tail = self.nodes[id].backward,
+        }
+        while self.level > 1 && self.forward(HEAD, self.level - 1).is_none() {
+            self.level -= 1;
+        }
+        // Release the memory of the member, the slot is reused by the next insert.
+        self.nodes[id].member = Vec::new();
+        self.nodes[id].levels = Vec::new();
+        self.free.push(id);
+        self.len -= 1;
+        true
+    }
+
+    /// The 1-based rank of an element that is in the list.
+    pub fn rank(&self, score: f64, member: &[u8]) -> usize {
+        let mut rank = 0;
+        let mut node = HEAD;
+        for level in (0..self.level).rev() {
+            while let Some(next) = self.forward(node, level).filter(|&next| {
+                let next = &self.nodes[next];
+                compare(next.score, &next.member, score, member) != Ordering::Greater
+            }) {
+                rank += self.span(node, level);
+                node = next;
+            }
+            if node != HEAD && self.nodes[node].member == member {
+                return rank;
+            }
+        }
+        unreachable!("the element is in the list")
+    }
+
+    /// The node at a 1-based rank.
+    pub fn node_at_rank(&self, rank: usize) -> Option<usize> {
+        let mut traversed = 0;
+        let mut node = HEAD;
+        for level in (0..self.level).rev() {
+            while let Some(next) = self.forward(node, level).filter(|_| traversed + self.span(node, level) <= rank) {
+                traversed += self.span(node, level);
+                node = next;
+            }
+            if traversed == rank {
+                return (node != HEAD).then_some(node);
+            }
+        }
+        None
+    }
+
+    /// The first node for which `before` is false, given that `before` holds for a prefix of
+    /// the list.
+    pub fn first_not(&self, before: impl Fn(f64, &[u8]) -> bool) -> Option<usize> {
+        let mut node = HEAD;
+        for level in (0..self.level).rev() {
+            while let Some(next) = self
+                .forward(node, level)
+                .filter(|&next| before(self.nodes[next].score, &self.nodes[next].member))
+            {
+                node = next;
+            }
+        }
+        self.forward(node, 0)
+    }
+
+    /// The last node for which `after` is false, given that `after` holds for a suffix of the
+    /// list.
+    pub fn last_not(&self, after: impl Fn(f64, &[u8]) -> bool) -> Option<usize> {
+        let mut node = HEAD;
+        for level in (0..self.level).rev() {
+            while let Some(next) = self
+                .forward(node, level)
+                .filter(|&next| !after(self.nodes[next].score, &self.nodes[next].member))
+            {
+                node = next;
+            }
+        }
+        (node != HEAD).then_some(node)
+    }
+
+    pub fn entry(&self, node: usize) -> (&Vec<u8>, f64) {
+        (&self.nodes[node].member, self.nodes[node].score)
+    }
+
+    pub fn next(&self, node: usize) -> Option<usize> {
+        self.forward(node, 0)
+    }
+
+    pub fn previous(&self, node: usize) -> Option<usize> {
+        self.nodes[node].backward
+    }
+}
+
+/// Moves from a node to its neighbor in one direction.
+type Step = fn(&SkipList, usize) -> Option<usize>;
+
+/// One end of a score range, as in `ZRANGE BYSCORE`; `(1.5` excludes the score itself.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScoreBound {
+    pub value: f64,
+    pub exclusive: bool,
+}
+
+/// One end of a member range, as in `ZRANGE BYLEX`: `-` and `+` are the smallest and largest
+/// member, `[a` includes `a` and `(a` excludes it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LexBound {
+    Min,
+    Max,
+    Inclusive(Vec<u8>),
+    Exclusive(Vec<u8>),
+}
+
+/// A range of elements by score or by member. Member ranges are meant for sorted sets where
+/// all scores are equal, like in Redis.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Range {
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound, LexBound),
+}
+
+impl Range {
+    fn is_below_min(&self, score: f64, member: &[u8]) -> bool {
+        match self {
+            Range::Score(min, _) => score < min.value || (min.exclusive && score == min.value),
+            Range::Lex(LexBound::Min, _) => false,
+            Range::Lex(LexBound::Max, _) => true,
+            Range::Lex(LexBound::Inclusive(min), _) => member < min.as_slice(),
+            Range::Lex(LexBound::Exclusive(min), _) => member <= min.as_slice(),
+        }
+    }
+
+    fn is_above_max(&self, score: f64, member: &[u8]) -> bool {
+        match self {
+            Range::Score(_, max) => score > max.value || (max.exclusive && score == max.value),
+            Range::Lex(_, LexBound::Max) => false,
+            Range::Lex(_, LexBound::Min) => true,
+            Range::Lex(_, LexBound::Inclusive(max)) => member > max.as_slice(),
+            Range::Lex(_, LexBound::Exclusive(max)) => member >= max.as_slice(),
+        }
+    }
+}
+
+/// The members and scores of a sorted set value: a hash index from member to score for O(1)
+/// score lookups, and a skiplist for everything ordered.
+#[derive(Clone, Debug, Default)]
+pub struct SortedSet {
+    scores: HashMap<Vec<u8>, f64>,
+    list: SkipList,
+}
+
+// Scores are never NaN, so equality is total.
+impl PartialEq for SortedSet {
+    fn eq(&self, other: &SortedSet) -> bool {
+        self.scores == other.scores
+    }
+}
+
+impl Eq for SortedSet {}
+
+impl SortedSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Adds `member` or changes its score, returning true if the member is new. `score` must
+    /// not be NaN.
+    pub fn insert(&mut self, member: Vec<u8>, score: f64) -> bool {
+        match self.scores.insert(member.clone(), score) {
+            Some(previous) if previous == score => false,
+            Some(previous) => {
+                self.list.remove(previous, &member);
+                self.list.insert(score, member);
+                false
+            }
+            None => {
+                self.list.insert(score, member);
+                true
+            }
+        }
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match self.scores.remove(member) {
+            Some(score) => self.list.remove(score, member),
+            None => false,
+        }
+    }
+
+    /// The 0-based position of `member` in ascending order.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(self.list.rank(score, member) - 1)
+    }
+
+    /// The element at a 0-based position, which must be less than the length.
+    pub fn entry_at(&self, rank: usize) -> (&Vec<u8>, f64) {
+        let node = self.list.node_at_rank(rank + 1).expect("the rank is within the set");
+        self.list.entry(node)
+    }
+
+    /// Removes and returns the element with the lowest score, or the highest if `highest`.
+    pub fn pop(&mut self, highest: bool) -> Option<(Vec<u8>, f64)> {
+        if self.is_empty() {
+            return None;
+        }
+        let rank = if highest { self.len() - 1 } else { 0 };
+        let (member, score) = self.entry_at(rank);
+        let member = member.clone();
+        self.remove(&member);
+        Some((member, score))
+    }
+
+    /// The elements between the 0-based positions `start` and `stop`, inclusive, counted from
+    /// the highest score if `reverse`. Both must be less than the length.
+    pub fn range_by_rank(&self, start: usize, stop: usize, reverse: bool) -> Vec<(Vec<u8>, f64)> {
+        let (first_rank, step): (usize, Step) = if reverse {
+            (self.len() - start, SkipList::previous)
+        } else {
+            (start + 1, SkipList::next)
+        };
+        let mut node = self.list.node_at_rank(first_rank);
+        let mut entries = Vec::with_capacity(stop + 1 - start);
+        for _ in start..=stop {
+            let current = node.expect("the range is within the set");
+            let (member, score) = self.list.entry(current);
+            entries.push((member.clone(), score));
+            node = step(&self.list, current);
+        }
+        entries
+    }
+
+    /// The elements in `range`, from the highest if `reverse`, skipping `offset` of them and
+    /// returning at most `limit`.
+    pub fn range(&self, range: &Range, reverse: bool, offset: usize, limit: Option<usize>) -> Vec<(Vec<u8>, f64)> {
+        let (mut node, step): (_, Step) = if reverse {
+            let last = self.list.last_not(|score, member| range.is_above_max(score, member));
+            (last, SkipList::previous)
+        } else {
+            let first = self.list.first_not(|score, member| range.is_below_min(score, member));
+            (first, SkipList::next)
+        };
+        let is_outside = |score, member: &[u8]| {
+            if reverse {
+                range.is_below_min(score, member)
+            } else {
+                range.is_above_max(score, member)
+            }
+        };
+        let mut entries = Vec::new();
+        let mut skipped = 0;
+        while let Some(current) = node {
+            if limit.is_some_and(|limit| entries.len() >= limit) {
+                break;
+            }
+            let (member, score) = self.list.entry(current);
+            if is_outside(score, member) {
+                break;
+            }
+            if skipped < offset {
+                skipped += 1;
+            } else {
+                entries.push((member.clone(), score));
+            }
+            node = step(&self.list, current);
+        }
+        entries
+    }
+
+    /// Number of elements in `range`, found from the ranks of its ends.
+    pub fn count(&self, range: &Range) -> usize {
+        let first = match self.list.first_not(|score, member| range.is_below_min(score, member)) {
+            Some(first) => first,
+            None => return 0,
+        };
+        let (first_member, first_score) = self.list.entry(first);
+        if range.is_above_max(first_score, first_member) {
+            return 0;
+        }
+        let last = self
+            .list
+            .last_not(|score, member| range.is_above_max(score, member))
+            .expect("the first element is in range");
+        let (last_member, last_score) = self.list.entry(last);
+        self.list.rank(last_score, last_member) - self.list.rank(first_score, first_member) + 1
+    }
+
+    /// All elements in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Vec<u8>, f64)> {
+        std::iter::successors(self.list.next(HEAD), |&node| self.list.next(node)).map(|node| self.list.entry(node))
+    }
+}
+
+impl FromIterator<(Vec<u8>, f64)> for SortedSet {
+    fn from_iter<I: IntoIterator<Item = (Vec<u8>, f64)>>(entries: I) -> SortedSet {
+        let mut set = SortedSet::default();
+        for (member, score) in entries {
+            set.insert(member, score);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn members(entries: &[(Vec<u8>, f64)]) -> Vec<String> {
+        entries.iter().map(|(member, _)| String::from_utf8(member.clone()).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_ranks_survive_updates() {
+        let mut set = SortedSet::default();
+        for i in 0..1000 {
+            assert!(set.insert(format!("m{:04}", i).into_bytes(), (i % 100) as f64));
+        }
+        // Equal scores order members lexicographically.
+        assert_eq!(set.rank(b"m0000"), Some(0));
+        assert_eq!(set.rank(b"m0100"), Some(1));
+        assert_eq!(set.rank(b"m0999"), Some(999));
+        assert!(!set.insert(b"m0000".to_vec(), 1000.0));
+        assert_eq!(set.rank(b"m0000"), Some(999));
+        for i in (0..1000).step_by(2) {
+            assert!(set.remove(format!("m{:04}", i).as_bytes()));
+        }
+        assert!(!set.remove(b"m0000"));
+        assert_eq!(set.len(), 500);
+        let ordered: Vec<(Vec<u8>, f64)> = set.iter().map(|(member, score)| (member.clone(), score)).collect();
+        for (rank, (member, _)) in ordered.iter().enumerate() {
+            assert_eq!(set.rank(member), Some(rank));
+        }
+        assert_eq!(set.range_by_rank(0, 499, false), ordered);
+        assert_eq!(set.range_by_rank(1, 2, true), vec![ordered[498].clone(), ordered[497].clone()]);
+    }
+
+    #[test]
+    fn test_score_and_lex_ranges() {
+        let mut set = SortedSet::default();
+        for (member, score) in [("a", 1.0), ("b", 2.0), ("c", 2.0), ("d", 3.0)] {
+            set.insert(member.as_bytes().to_vec(), score);
+        }
+        let bound = |value, exclusive| ScoreBound { value, exclusive };
+        let range = Range::Score(bound(2.0, false), bound(f64::INFINITY, false));
+        assert_eq!(members(&set.range(&range, false, 0, None)), ["b", "c", "d"]);
+        assert_eq!(members(&set.range(&range, true, 1, Some(1))), ["c"]);
+        assert_eq!(set.count(&range), 3);
+        let range = Range::Score(bound(1.0, true), bound(3.0, true));
+        assert_eq!(members(&set.range(&range, false, 0, None)), ["b", "c"]);
+        assert_eq!(set.count(&Range::Score(bound(3.0, true), bound(1.0, false))), 0);
+        let range = Range::Lex(LexBound::Exclusive(b"a".to_vec()), LexBound::Inclusive(b"c".to_vec()));
+        let mut equal_scores = SortedSet::default();
+        for member in ["a", "b", "c", "d"] {
+            equal_scores.insert(member.as_bytes().to_vec(), 0.0);
+        }
+        assert_eq!(members(&equal_scores.range(&range, false, 0, None)), ["b", "c"]);
+        assert_eq!(members(&equal_scores.range(&range, true, 0, None)), ["c", "b"]);
+        assert_eq!(equal_scores.count(&Range::Lex(LexBound::Min, LexBound::Max)), 4);
+    }
+}